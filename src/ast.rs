@@ -1,9 +1,19 @@
+use std::cell::RefCell;
 use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use crate::effects::Effects;
+use crate::error::*;
+use crate::ordered::Ordered;
+use crate::primitives::Primitive;
 
-pub type Result<T> = core::result::Result<T, Box<dyn std::error::Error>>;
+/// A result whose error is annotated with a span: `E` is one of the error kinds in `error`.
+pub type Result<T, E> = core::result::Result<T, Diagnostic<E>>;
 
+/// The typing environment (for `check` and `infer`), the value environment (for `execute`),
+/// the implementations of signatures (for interfaces), and the existentials being solved for (for polymorphism).
+/// The last is shared between a context and its clones: solving an existential solves it everywhere.
 #[derive(Debug, Clone, PartialEq, Default)]
-pub struct Context(HashMap<Identifier, Term>, HashMap<Signature, Expression>);
+pub struct Context(HashMap<Identifier, Type>, HashMap<Identifier, Term>, HashMap<Signature, Implementation>, Rc<RefCell<Ordered>>);
 
 pub type Identifier = String;
 
@@ -14,18 +24,115 @@ pub struct Signature {
     pub to: Type
 }
 
+impl Signature {
+    /// The (curried) function type of a signature.
+    pub fn kind(&self) -> Type {
+        Type::Function(Box::new(self.from.clone()), Box::new(self.to.clone()), Effects::new())
+    }
+}
+
+/// How a signature is implemented: natively, or by an expression in the language.
+/// Signatures required of a type variable by its bound are implemented by whatever it is instantiated at.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Implementation {
+    Primitive(Primitive),
+    Defined(Expression),
+    Bound,
+}
+
 /// Fundamental expressions for the lambda calculus.
-/// To be extended: bindings, loops/continuations, typedefs?
+/// To be extended: loops/continuations, typedefs?
 // note: built-in functions do NOT go here!
 // note: we keep parameters as an Identifier because we annotate the WHOLE Abstraction
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expression {
-    Annotation{expr: Box<Expression>, kind: Type},
-    Constant{term: Term},
-    Variable{id: Identifier},
-    Abstraction{param: Identifier, func: Box<Expression>},
-    Application{func: Box<Expression>, arg: Box<Expression>},
-    Conditional{if_cond: Box<Expression>, if_then: Box<Expression>, if_else: Box<Expression>}
+    Annotation{expr: Box<Expression>, kind: Type, span: Span},
+    Constant{term: Term, span: Span},
+    Variable{id: Identifier, span: Span},
+    Abstraction{param: Identifier, func: Box<Expression>, span: Span},
+    Application{func: Box<Expression>, arg: Box<Expression>, span: Span},
+    Conditional{if_cond: Box<Expression>, if_then: Box<Expression>, if_else: Box<Expression>, span: Span},
+    Let{id: Identifier, kind: Option<Type>, value: Box<Expression>, body: Box<Expression>, span: Span},
+    Fix{func: Box<Expression>, span: Span},     // general recursion: letrec desugars to this
+    Access{expr: Box<Expression>, at: Accessor, span: Span},
+    Case{expr: Box<Expression>, arms: Vec<(Pattern, Expression)>, span: Span},   // arms are tried in order
+    Injection{label: Identifier, expr: Box<Expression>, span: Span},             // into a variant: <some = 3>
+    Is{expr: Box<Expression>, kind: Type, span: Span},  // runtime type tests: narrow variables in conditionals
+    // compounds of arbitrary expressions: compounds of terms are constants
+    List{data: Vec<Expression>, span: Span},
+    Array{data: Vec<Expression>, span: Span},
+    Struct{data: BTreeMap<Identifier, Expression>, span: Span},
+    Tuple{data: Vec<Expression>, fields: Vec<Option<Identifier>>, span: Span},
+    TypeAbstraction{param: Identifier, bound: Type, expr: Box<Expression>, span: Span},   // Λa <: T. e
+    TypeApplication{expr: Box<Expression>, kind: Type, span: Span},          // e[T]: erased at runtime
+    Pack{witness: Type, expr: Box<Expression>, kind: Type, span: Span},      // {*S, e} as T: hides S behind T
+    Unpack{param: Identifier, id: Identifier, expr: Box<Expression>, body: Box<Expression>, span: Span},  // let {X, x} = e in b
+}
+
+/// What is projected out of a compound value.
+// note: tuple fields are accessed by label or by position: `t.name` or `t.0`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Accessor {
+    Field(Identifier),          // structs and tuples
+    Index(Box<Expression>),     // lists and arrays
+}
+
+impl Accessor {
+    /// The position of a field in a tuple with the given labels: by label first, then by number.
+    pub fn position(field: &Identifier, labels: &[Option<Identifier>]) -> Option<usize> {
+        labels.iter().position(|label| label.as_ref() == Some(field))
+            .or(field.parse::<usize>().ok().filter(|i| *i < labels.len()))
+    }
+}
+
+/// What an arm of a case matches. Patterns bind the value they match.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Member(Identifier, Type),   // unions: values of one of its member types, i.e. `x: int`
+    Label(Identifier, Identifier),  // variants: values injected with a label, i.e. `<some = x>`
+}
+
+impl Pattern {
+    /// The variable the matched value is bound to.
+    pub fn binding(&self) -> &Identifier {
+        match self {
+            Pattern::Member(id, _) | Pattern::Label(_, id) => id
+        }
+    }
+}
+
+/// A range of bytes in the source an expression was parsed from.
+/// Spans are positional metadata: they never affect the equality of expressions.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize
+}
+
+impl PartialEq for Span {
+    fn eq(&self, other: &Self) -> bool {
+        true
+    }
+}
+
+/// A whole source file: top-level function definitions and interface implementations,
+/// followed by expressions to evaluate.
+// note: each function is stored as its annotated abstraction, i.e. `(λx. body): (from -> to)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub functions: Vec<(Identifier, Expression)>,
+    pub instances: Vec<Instance>,
+    pub expressions: Vec<Expression>,
+}
+
+/// An implementation of an interface for a type: `impl interface[func show(Self): str] for int:`.
+// note: methods are stored as their unannotated abstractions: their types are those of the signatures they implement
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instance {
+    pub interface: Type,
+    pub kind: Type,
+    pub methods: Vec<(Identifier, Expression)>,
+    pub span: Span
 }
 
 /// All supported types.
@@ -38,16 +145,20 @@ pub enum Type {
     Slice(Box<Type>),                               // potentially fucky lifetime stuff too
     Union(Vec<Type>),                               // unordered
     Struct(BTreeMap<Identifier, Type>),             // unordered
+    Variant(BTreeMap<Identifier, Type>),            // labeled alternatives: a tagged union
     Tuple(Vec<Type>, Vec<Option<Identifier>>),      // ordered with labels (vectors must be same length)
-    Function(Box<Type>, Box<Type>),                 // from, to: multiple params expressed via tuples
+    Function(Box<Type>, Box<Type>, Effects),        // from, to, and the effects of applying it
     Interface(Vec<Signature>, Option<Box<Type>>),   // typeclasses "interfaces"
     Oneself,                                        // "Self" type associated with interfaces. replaced by subtyping checks
     Generic(Option<Vec<Type>>),                     // stand in generic type. fully generic, or a list of valid types.
+    Variable(Identifier),                           // type variables: solved for by unification, or bound by forall
+    Forall(Identifier, Box<Type>, Box<Type>),       // bounded universal quantification: forall a <: T. a -> a (T is empty if unbounded)
+    Existential(Identifier),                        // unknown types: introduced and solved while checking, never written
 }
 
 /// Data associated with a type.
 // Note: no Interfaces, Slices, Empty, Error: those cannot be constructed.
-// Note: no Functions: those inhabit a separate context. it's just easier
+// Note: functions are closures, capturing the value environment they were created in.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Term {
     Unit(), Boolean(bool),
@@ -57,13 +168,24 @@ pub enum Term {
     Array(Vec<Term>),
     Union(Box<Term>),
     Struct(BTreeMap<Identifier, Term>),
+    Variant(Identifier, Box<Term>),
     Tuple(Vec<Term>, Vec<Option<Identifier>>),
+    Closure { param: Identifier, func: Box<Expression>, env: HashMap<Identifier, Term> },
+    Package(Type, Box<Term>),   // a value and the type it was packed as: which signatures dispatch on
 }
 
 impl Term {
     /// Convert a term into its corresponding type.
+    /// The elements of lists and arrays must all be of the same type: `Context::convert` joins them instead.
     // Empty lists/arrays and unions currently cannot be inferred.
-    pub fn convert(&self) -> Result<Type> {
+    pub fn convert(&self) -> core::result::Result<Type, TypeError> {
+        let elements = |data: &Vec<Term>| match data.split_first() {
+            Some((first, rest)) => rest.iter().try_fold(first.convert()?, |acc, term| match term.convert()? {
+                kind if kind == acc => Ok(acc),
+                kind => Err(TypeError::Mismatch { expected: acc, found: kind })
+            }),
+            None => Err(TypeError::CannotInferEmpty)
+        };
         match self {
             Term::Unit() => Ok(Type::Unit),
             Term::Boolean(_) => Ok(Type::Boolean),
@@ -71,15 +193,9 @@ impl Term {
             Term::Integer(_) => Ok(Type::Integer),
             Term::Float(_) => Ok(Type::Float),
             Term::String(_) => Ok(Type::String),
-            Term::List(data) => match data.len() {
-                0 => Err("attempting to infer the type of an empty list!".into()),
-                _ => Ok(Type::List(Box::new(data.get(0).unwrap().convert()?))),
-            },
-            Term::Array(data) => match data.len() {
-                0 => Err("attempting to infer the type of an empty array!".into()),
-                _ => Ok(Type::Array(Box::new(data.get(0).unwrap().convert()?), data.len()))
-            },
-            Term::Union(data) => Err("attempting to infer the type of a union variant!".into()),
+            Term::List(data) => Ok(Type::List(Box::new(elements(data)?))),
+            Term::Array(data) => Ok(Type::Array(Box::new(elements(data)?), data.len())),
+            Term::Union(data) => Err(TypeError::CannotInferUnion),
             Term::Struct(data) => {
                 let mut result = BTreeMap::new();
                 for (key, val) in data {
@@ -87,6 +203,7 @@ impl Term {
                 }
                 Ok(Type::Struct(result))
             },
+            Term::Variant(label, data) => Ok(Type::Variant(BTreeMap::from([(label.clone(), data.convert()?)]))),
             Term::Tuple(data, fields) => {
                 let mut result = Vec::new();
                 for val in data {
@@ -94,18 +211,365 @@ impl Term {
                 }
                 Ok(Type::Tuple(result, fields.clone()))
             },
+            // closures are unannotated abstractions
+            Term::Closure { .. } => Err(TypeError::CannotInferAbstraction),
+            // packages are of their witness type at runtime
+            Term::Package(witness, _) => Ok(witness.clone()),
+        }
+    }
+}
+
+impl Expression {
+    /// The span of source this expression was parsed from.
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Annotation { span, .. } | Expression::Constant { span, .. } |
+            Expression::Variable { span, .. } | Expression::Abstraction { span, .. } |
+            Expression::Application { span, .. } | Expression::Conditional { span, .. } |
+            Expression::Let { span, .. } | Expression::Fix { span, .. } |
+            Expression::Access { span, .. } | Expression::Case { span, .. } |
+            Expression::Injection { span, .. } | Expression::Is { span, .. } |
+            Expression::List { span, .. } | Expression::Array { span, .. } |
+            Expression::Struct { span, .. } | Expression::Tuple { span, .. } |
+            Expression::TypeAbstraction { span, .. } | Expression::TypeApplication { span, .. } |
+            Expression::Pack { span, .. } | Expression::Unpack { span, .. } => *span
+        }
+    }
+
+    /// A compound constant as a compound of constants, so that its components may be checked one by one.
+    /// Any other term is left a constant.
+    pub fn lift(term: Term, span: Span) -> Expression {
+        let lift = |data: Vec<Term>| data.into_iter().map(|term| Expression::Constant { term, span }).collect();
+        match term {
+            Term::List(data) => Expression::List { data: lift(data), span },
+            Term::Array(data) => Expression::Array { data: lift(data), span },
+            Term::Struct(data) => Expression::Struct {
+                data: data.into_iter().map(|(k, term)| (k, Expression::Constant { term, span })).collect(), span },
+            Term::Tuple(data, fields) => Expression::Tuple { data: lift(data), fields, span },
+            term => Expression::Constant { term, span }
+        }
+    }
+
+    /// The function at the head of a chain of applications: `f` in `((f a) b)`.
+    pub fn head(&self) -> &Expression {
+        match self {
+            Expression::Application { func, .. } => func.head(),
+            _ => self
+        }
+    }
+
+    /// Split a chain of applications into its head and arguments: `((f a) b)` is `f` and `[a, b]`.
+    pub fn spine(self) -> (Expression, Vec<Expression>) {
+        match self {
+            Expression::Application { func, arg, .. } => {
+                let (head, mut args) = func.spine();
+                args.push(*arg);
+                (head, args)
+            },
+            _ => (self, Vec::new())
+        }
+    }
+
+    /// Replace every free occurrence of a variable with another expression.
+    /// Substitution avoids capture: binders binding a variable free in the replacement are renamed.
+    pub fn substitute(self, id: &Identifier, replacement: &Expression) -> Expression {
+        match self {
+            Expression::Annotation { expr, kind, span } => Expression::Annotation {
+                expr: Box::new(expr.substitute(id, replacement)), kind, span },
+            Expression::Constant { term, span } => Expression::Constant { term, span },
+            Expression::Variable { id: var, .. } if &var == id => replacement.clone(),
+            Expression::Variable { id, span } => Expression::Variable { id, span },
+            Expression::Abstraction { param, func, span } if &param == id =>
+                Expression::Abstraction { param, func, span },
+            Expression::Abstraction { param, func, span } => {
+                let (param, func) = rename(param, *func, id, replacement);
+                Expression::Abstraction { param, func: Box::new(func.substitute(id, replacement)), span }
+            },
+            Expression::Application { func, arg, span } => Expression::Application {
+                func: Box::new(func.substitute(id, replacement)),
+                arg: Box::new(arg.substitute(id, replacement)), span },
+            Expression::Conditional { if_cond, if_then, if_else, span } => Expression::Conditional {
+                if_cond: Box::new(if_cond.substitute(id, replacement)),
+                if_then: Box::new(if_then.substitute(id, replacement)),
+                if_else: Box::new(if_else.substitute(id, replacement)), span },
+            Expression::Let { id: var, kind, value, body, span } if &var == id => Expression::Let {
+                id: var, kind, value: Box::new(value.substitute(id, replacement)), body, span },
+            Expression::Let { id: var, kind, value, body, span } => {
+                let (var, body) = rename(var, *body, id, replacement);
+                Expression::Let {
+                    id: var, kind,
+                    value: Box::new(value.substitute(id, replacement)),
+                    body: Box::new(body.substitute(id, replacement)), span }
+            },
+            Expression::Fix { func, span } => Expression::Fix {
+                func: Box::new(func.substitute(id, replacement)), span },
+            Expression::Access { expr, at, span } => Expression::Access {
+                expr: Box::new(expr.substitute(id, replacement)),
+                at: match at {
+                    Accessor::Field(field) => Accessor::Field(field),
+                    Accessor::Index(index) => Accessor::Index(Box::new(index.substitute(id, replacement)))
+                }, span },
+            Expression::Case { expr, arms, span } => Expression::Case {
+                expr: Box::new(expr.substitute(id, replacement)),
+                arms: arms.into_iter().map(|(pattern, body)| match pattern.binding() == id {
+                    true => (pattern, body),
+                    false => {
+                        let (binding, body) = rename(pattern.binding().clone(), body, id, replacement);
+                        let pattern = match pattern {
+                            Pattern::Member(_, kind) => Pattern::Member(binding, kind),
+                            Pattern::Label(label, _) => Pattern::Label(label, binding)
+                        };
+                        (pattern, body.substitute(id, replacement))
+                    }
+                }).collect(), span },
+            Expression::Injection { label, expr, span } => Expression::Injection {
+                label, expr: Box::new(expr.substitute(id, replacement)), span },
+            Expression::Is { expr, kind, span } => Expression::Is {
+                expr: Box::new(expr.substitute(id, replacement)), kind, span },
+            Expression::List { data, span } => Expression::List {
+                data: data.into_iter().map(|x| x.substitute(id, replacement)).collect(), span },
+            Expression::Array { data, span } => Expression::Array {
+                data: data.into_iter().map(|x| x.substitute(id, replacement)).collect(), span },
+            Expression::Struct { data, span } => Expression::Struct {
+                data: data.into_iter().map(|(k, x)| (k, x.substitute(id, replacement))).collect(), span },
+            Expression::Tuple { data, fields, span } => Expression::Tuple {
+                data: data.into_iter().map(|x| x.substitute(id, replacement)).collect(), fields, span },
+            Expression::TypeAbstraction { param, bound, expr, span } => Expression::TypeAbstraction {
+                param, bound, expr: Box::new(expr.substitute(id, replacement)), span },
+            Expression::TypeApplication { expr, kind, span } => Expression::TypeApplication {
+                expr: Box::new(expr.substitute(id, replacement)), kind, span },
+            Expression::Pack { witness, expr, kind, span } => Expression::Pack {
+                witness, expr: Box::new(expr.substitute(id, replacement)), kind, span },
+            Expression::Unpack { param, id: var, expr, body, span } if &var == id => Expression::Unpack {
+                param, id: var, expr: Box::new(expr.substitute(id, replacement)), body, span },
+            Expression::Unpack { param, id: var, expr, body, span } => {
+                let (var, body) = rename(var, *body, id, replacement);
+                Expression::Unpack {
+                    param, id: var,
+                    expr: Box::new(expr.substitute(id, replacement)),
+                    body: Box::new(body.substitute(id, replacement)), span }
+            },
+        }
+    }
+
+    /// The free variables occurring in an expression, in order of first appearance.
+    pub fn free(&self) -> Vec<Identifier> {
+        let mut result = Vec::new();
+        self.collect(&mut Vec::new(), &mut result);
+        result
+    }
+
+    fn collect(&self, bound: &mut Vec<Identifier>, result: &mut Vec<Identifier>) {
+        let scoped = |id: &Identifier, body: &Expression, bound: &mut Vec<Identifier>, result: &mut Vec<Identifier>| {
+            bound.push(id.clone());
+            body.collect(bound, result);
+            bound.pop();
+        };
+        match self {
+            Expression::Variable { id, .. } => if !bound.contains(id) && !result.contains(id) {
+                result.push(id.clone())
+            },
+            Expression::Constant { .. } => (),
+            Expression::Annotation { expr, .. } | Expression::Fix { func: expr, .. } |
+            Expression::Injection { expr, .. } | Expression::Is { expr, .. } |
+            Expression::TypeAbstraction { expr, .. } | Expression::TypeApplication { expr, .. } |
+            Expression::Pack { expr, .. } => expr.collect(bound, result),
+            Expression::Abstraction { param, func, .. } => scoped(param, func, bound, result),
+            Expression::Application { func, arg, .. } => {
+                func.collect(bound, result);
+                arg.collect(bound, result);
+            },
+            Expression::Conditional { if_cond, if_then, if_else, .. } => {
+                if_cond.collect(bound, result);
+                if_then.collect(bound, result);
+                if_else.collect(bound, result);
+            },
+            Expression::Let { id, value, body, .. } | Expression::Unpack { id, expr: value, body, .. } => {
+                value.collect(bound, result);
+                scoped(id, body, bound, result);
+            },
+            Expression::Access { expr, at, .. } => {
+                expr.collect(bound, result);
+                if let Accessor::Index(index) = at {
+                    index.collect(bound, result);
+                }
+            },
+            Expression::Case { expr, arms, .. } => {
+                expr.collect(bound, result);
+                arms.iter().for_each(|(pattern, body)| scoped(pattern.binding(), body, bound, result));
+            },
+            Expression::List { data, .. } | Expression::Array { data, .. } | Expression::Tuple { data, .. } =>
+                data.iter().for_each(|x| x.collect(bound, result)),
+            Expression::Struct { data, .. } => data.values().for_each(|x| x.collect(bound, result)),
+        }
+    }
+}
+
+/// Rename the binder of a body if substituting into it would capture a free variable of the replacement.
+fn rename(binder: Identifier, body: Expression, id: &Identifier, replacement: &Expression) -> (Identifier, Expression) {
+    let free = replacement.free();
+    match free.contains(&binder) && body.free().contains(id) {
+        true => {
+            let avoid: Vec<Identifier> = free.into_iter().chain(body.free()).collect();
+            let fresh = Type::fresh(&binder, &avoid);
+            let span = body.span();
+            let body = body.substitute(&binder, &Expression::Variable { id: fresh.clone(), span });
+            (fresh, body)
+        },
+        false => (binder, body)
+    }
+}
+
+impl Program {
+    /// Lower a program into standalone expressions, each binding the functions it calls around it.
+    /// Functions calling each other are bound in one recursive scope, after the functions they call:
+    /// an (annotated) function calling itself is wrapped in a fixpoint, and a group of functions calling
+    /// each other in a fixpoint over a record of them.
+    // Later definitions replace earlier ones. Unannotated functions are bound as they are: fixpoints need a type.
+    pub fn lower(&self) -> Vec<Expression> {
+        let groups = self.groups();
+        self.expressions.iter().map(|expr| bind(&groups, expr)).collect()
+    }
+
+    /// The interface implementations of a program, with the functions their methods call bound around them.
+    pub fn instances(&self) -> Vec<Instance> {
+        let groups = self.groups();
+        self.instances.iter().map(|instance| Instance {
+            methods: instance.methods.iter().map(|(name, method)| (name.clone(), bind(&groups, method))).collect(),
+            ..instance.clone()
+        }).collect()
+    }
+
+    /// The functions of a program in groups of those calling each other, each after the groups it calls.
+    fn groups(&self) -> Vec<Vec<(Identifier, Expression)>> {
+        let mut functions: Vec<(Identifier, Expression)> = Vec::new();
+        for (name, func) in &self.functions {
+            functions.retain(|(other, _)| other != name);
+            functions.push((name.clone(), func.clone()));
+        }
+        let calls: Vec<Vec<usize>> = functions.iter().map(|(_, func)| {
+            let free = func.free();
+            (0..functions.len()).filter(|i| free.contains(&functions[*i].0)).collect()
+        }).collect();
+        components(&calls).into_iter()
+            .map(|group| group.into_iter().map(|i| functions[i].clone()).collect())
+            .collect()
+    }
+}
+
+/// Bind the groups of functions an expression calls (directly or not) around it.
+fn bind(groups: &[Vec<(Identifier, Expression)>], expr: &Expression) -> Expression {
+    let mut free = expr.free();
+    let mut result = expr.clone();
+    for group in groups.iter().rev() {
+        if group.iter().any(|(name, _)| free.contains(name)) {
+            group.iter().for_each(|(_, func)| free.extend(func.free()));
+            result = scope(group, result);
+        }
+    }
+    result
+}
+
+/// Bind a group of functions calling each other around a body.
+fn scope(group: &[(Identifier, Expression)], body: Expression) -> Expression {
+    let span = body.span();
+    let bind = |id: Identifier, value: Expression, body: Expression| Expression::Let {
+        id, kind: None, value: Box::new(value), body: Box::new(body), span
+    };
+    let fix = |param: Identifier, func: Expression, kind: Type| Expression::Fix {
+        func: Box::new(Expression::Annotation {
+            expr: Box::new(Expression::Abstraction { param, func: Box::new(func), span }),
+            kind: Type::Function(Box::new(kind.clone()), Box::new(kind), Effects::new()),
+            span
+        }),
+        span
+    };
+    let kinds: Option<Vec<Type>> = group.iter().map(|(_, func)| match func {
+        Expression::Annotation { kind, .. } => Some(kind.clone()),
+        _ => None
+    }).collect();
+    match kinds {
+        Some(kinds) if group.len() == 1 && group[0].1.free().contains(&group[0].0) => {
+            let (name, func) = group[0].clone();
+            bind(name.clone(), fix(name, func, kinds[0].clone()), body)
+        },
+        // the record is built on demand, so that its functions may access each other through it
+        Some(kinds) if group.len() > 1 => {
+            let mut avoid = body.free();
+            for (name, func) in group {
+                avoid.push(name.clone());
+                avoid.extend(func.free());
+            }
+            let this = Type::fresh(&String::from("group"), &avoid);
+            let unit = Type::fresh(&String::from("unit"), &avoid);
+            let access = |name: &Identifier| Expression::Access {
+                expr: Box::new(Expression::Application {
+                    func: Box::new(Expression::Variable { id: this.clone(), span }),
+                    arg: Box::new(Expression::Constant { term: Term::Unit(), span }),
+                    span
+                }),
+                at: Accessor::Field(name.clone()),
+                span
+            };
+            let fields = group.iter().map(|(name, func)| (name.clone(),
+                group.iter().fold(func.clone(), |func, (other, _)| func.substitute(other, &access(other))))).collect();
+            let record = Type::Struct(std::iter::zip(group.iter().map(|(name, _)| name.clone()), kinds).collect());
+            let thunk = Type::Function(Box::new(Type::Unit), Box::new(record), Effects::new());
+            let value = fix(this.clone(), Expression::Abstraction {
+                param: unit, func: Box::new(Expression::Struct { data: fields, span }), span }, thunk);
+            let body = group.iter().rev().fold(body, |body, (name, _)| bind(name.clone(), access(name), body));
+            bind(this.clone(), value, body)
+        },
+        _ => group.iter().rev().fold(body, |body, (name, func)| bind(name.clone(), func.clone(), body))
+    }
+}
+
+/// The strongly connected components of a graph, each after the components it has edges to (by Tarjan's algorithm).
+fn components(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    struct State {
+        index: Vec<Option<usize>>,
+        low: Vec<usize>,
+        count: usize,
+        stack: Vec<usize>,
+        result: Vec<Vec<usize>>
+    }
+    fn visit(edges: &[Vec<usize>], node: usize, state: &mut State) {
+        state.index[node] = Some(state.count);
+        state.low[node] = state.count;
+        state.count += 1;
+        state.stack.push(node);
+        for &next in &edges[node] {
+            match state.index[next] {
+                None => {
+                    visit(edges, next, state);
+                    state.low[node] = state.low[node].min(state.low[next]);
+                },
+                Some(index) if state.stack.contains(&next) => state.low[node] = state.low[node].min(index),
+                Some(_) => ()
+            }
+        }
+        if state.index[node] == Some(state.low[node]) {
+            let at = state.stack.iter().position(|x| *x == node).unwrap();
+            let mut component = state.stack.split_off(at);
+            component.sort();
+            state.result.push(component);
         }
     }
+    let mut state = State { index: vec![None; edges.len()], low: vec![0; edges.len()], count: 0, stack: Vec::new(), result: Vec::new() };
+    for node in 0..edges.len() {
+        if state.index[node].is_none() {
+            visit(edges, node, &mut state);
+        }
+    }
+    state.result
 }
 
 impl Type {
     /// Get the default value of a type. Throws an error if it doesn't exist.
-    // Unions are invalid as they are not ordered.
+    // Unions and variants are invalid as they are not ordered.
     // Empty, Error, Slice, Function, Interface, Onself, Generic are invalid as they cannot be constructed.
-    pub fn default(&self) -> Result<Term> {
+    pub fn default(&self) -> core::result::Result<Term, TypeError> {
         match self {
-            Type::Empty => Err("attempting to take the default term for empty".into()),
-            Type::Error => Err("attempting to take the default term for error".into()),
             Type::Unit => Ok(Term::Unit()),
             Type::Boolean => Ok(Term::Boolean(false)),
             Type::Natural => Ok(Term::Natural(0)),
@@ -114,8 +578,6 @@ impl Type {
             Type::String => Ok(Term::String(String::new())),
             Type::List(data) => Ok(Term::List(Vec::<Term>::new())),
             Type::Array(data, len) => Ok(Term::Array(vec![data.default()?; *len])),
-            Type::Slice(_) => Err("attempting to take the default term of a slice".into()),
-            Type::Union(_) => Err("attempting to take the default term of a union".into()),
             Type::Struct(data) => {
                 let mut result = BTreeMap::new();
                 for (key, val) in data {
@@ -130,14 +592,109 @@ impl Type {
                 }
                 Ok(Term::Tuple(result, fields.clone()))
             },
-            Type::Function(from, to) =>
-                Err("attempting to take the default term of a function type".into()),
-            Type::Interface(_, _) =>
-                Err("attempting to take the default term of an interface".into()),
-            Type::Oneself =>
-                Err("attempting to take the default term of Self".into()),
-            Type::Generic(_) =>
-                Err("attempting to take the default term of a generic".into()),
+            Type::Empty | Type::Error | Type::Slice(_) | Type::Union(_) | Type::Variant(_) | Type::Function(..) |
+            Type::Interface(_, _) | Type::Oneself | Type::Generic(_) | Type::Variable(_) | Type::Forall(..) |
+            Type::Existential(_) =>
+                Err(TypeError::NoDefault(self.clone())),
+        }
+    }
+
+    /// Replace free type variables with types, all at once.
+    /// Substitution avoids capture: quantifiers binding a variable free in a replacement are renamed.
+    pub fn substitute(&self, map: &HashMap<Identifier, Type>) -> Type {
+        self.replace(&|kind| match kind {
+            Type::Variable(id) => map.get(id).cloned(),
+            // the quantified variable is not in scope in its bound
+            Type::Forall(id, bound, body) => {
+                let bound = Box::new(bound.substitute(map));
+                let mut map = map.clone();
+                map.remove(id);
+                let free: Vec<Identifier> = map.values().flat_map(|kind| kind.free()).collect();
+                match free.contains(id) {
+                    true => {
+                        let avoid: Vec<Identifier> = free.into_iter().chain(body.free()).collect();
+                        let fresh = Type::fresh(id, &avoid);
+                        map.insert(id.clone(), Type::Variable(fresh.clone()));
+                        Some(Type::Forall(fresh, bound, Box::new(body.substitute(&map))))
+                    },
+                    false => Some(Type::Forall(id.clone(), bound, Box::new(body.substitute(&map))))
+                }
+            },
+            _ => None
+        })
+    }
+
+    /// A variable named after another that occurs in none of the given variables: `a`, `a1`, `a2`...
+    pub fn fresh(id: &Identifier, avoid: &[Identifier]) -> Identifier {
+        std::iter::once(id.clone()).chain((1..).map(|i| format!("{}{}", id, i)))
+            .find(|id| !avoid.contains(id)).unwrap()
+    }
+
+    /// Rebuild a type top-down, replacing every part `f` gives a replacement for.
+    pub fn replace(&self, f: &dyn Fn(&Type) -> Option<Type>) -> Type {
+        if let Some(kind) = f(self) {
+            return kind;
+        }
+        let sub = |kind: &Type| kind.replace(f);
+        match self {
+            Type::Empty | Type::Error | Type::Unit | Type::Boolean | Type::Natural |
+            Type::Integer | Type::Float | Type::String | Type::Oneself | Type::Variable(_) | Type::Existential(_) => self.clone(),
+            Type::List(data) => Type::List(Box::new(sub(data))),
+            Type::Array(data, len) => Type::Array(Box::new(sub(data)), *len),
+            Type::Slice(data) => Type::Slice(Box::new(sub(data))),
+            Type::Union(data) => Type::Union(data.iter().map(sub).collect()),
+            Type::Struct(data) => Type::Struct(data.iter().map(|(k, v)| (k.clone(), sub(v))).collect()),
+            Type::Variant(data) => Type::Variant(data.iter().map(|(k, v)| (k.clone(), sub(v))).collect()),
+            Type::Tuple(data, fields) => Type::Tuple(data.iter().map(sub).collect(), fields.clone()),
+            Type::Function(from, to, effects) => Type::Function(Box::new(sub(from)), Box::new(sub(to)), effects.clone()),
+            Type::Interface(signatures, associated) => Type::Interface(
+                signatures.iter().map(|sig| Signature { name: sig.name.clone(), from: sub(&sig.from), to: sub(&sig.to) }).collect(),
+                associated.as_ref().map(|x| Box::new(sub(x)))),
+            Type::Generic(data) => Type::Generic(data.as_ref().map(|data| data.iter().map(sub).collect())),
+            Type::Forall(id, bound, body) => Type::Forall(id.clone(), Box::new(sub(bound)), Box::new(sub(body))),
+        }
+    }
+
+    /// The free type variables occurring in a type, in order of first appearance.
+    pub fn free(&self) -> Vec<Identifier> {
+        let mut result = Vec::new();
+        self.collect(&mut result);
+        result
+    }
+
+    fn collect(&self, result: &mut Vec<Identifier>) {
+        match self {
+            Type::Variable(id) => if !result.contains(id) {
+                result.push(id.clone())
+            },
+            Type::Empty | Type::Error | Type::Unit | Type::Boolean | Type::Natural |
+            Type::Integer | Type::Float | Type::String | Type::Oneself | Type::Generic(None) | Type::Existential(_) => (),
+            Type::List(data) | Type::Array(data, _) | Type::Slice(data) => data.collect(result),
+            Type::Union(data) | Type::Tuple(data, _) | Type::Generic(Some(data)) =>
+                data.iter().for_each(|x| x.collect(result)),
+            Type::Struct(data) | Type::Variant(data) => data.values().for_each(|x| x.collect(result)),
+            Type::Function(from, to, _) => {
+                from.collect(result);
+                to.collect(result);
+            },
+            Type::Interface(signatures, associated) => {
+                for sig in signatures {
+                    sig.from.collect(result);
+                    sig.to.collect(result);
+                }
+                if let Some(data) = associated {
+                    data.collect(result);
+                }
+            },
+            // variables the quantifier binds are only free if they occur outside of it
+            Type::Forall(id, bound, body) => {
+                bound.collect(result);
+                for free in body.free() {
+                    if &free != id && !result.contains(&free) {
+                        result.push(free);
+                    }
+                }
+            },
         }
     }
 }
@@ -145,12 +702,59 @@ impl Type {
 impl core::fmt::Display for Expression {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
-            Expression::Annotation { expr, kind } => write!(f, "({}: {})", expr, kind),
-            Expression::Constant { term } => write!(f, "'{:?}", term),
-            Expression::Variable { id } => write!(f, "{}", id),
-            Expression::Abstraction { param, func } => write!(f, "(λ{}.{})", param, func),
-            Expression::Application { func, arg } => write!(f, "({} {})", func, arg),
-            Expression::Conditional { if_cond, if_then, if_else } => write!(f, "(if {} then {} else {})", if_cond, if_then, if_else),
+            Expression::Annotation { expr, kind, .. } => write!(f, "({}: {})", expr, kind),
+            Expression::Constant { term, .. } => write!(f, "'{:?}", term),
+            Expression::Variable { id, .. } => write!(f, "{}", id),
+            Expression::Abstraction { param, func, .. } => write!(f, "(λ{}.{})", param, func),
+            Expression::Application { func, arg, .. } => write!(f, "({} {})", func, arg),
+            Expression::Conditional { if_cond, if_then, if_else, .. } => write!(f, "(if {} then {} else {})", if_cond, if_then, if_else),
+            Expression::Let { id, kind: Some(kind), value, body, .. } => write!(f, "(let {}: {} = {} in {})", id, kind, value, body),
+            Expression::Let { id, kind: None, value, body, .. } => write!(f, "(let {} = {} in {})", id, value, body),
+            Expression::Fix { func, .. } => write!(f, "(fix {})", func),
+            Expression::Access { expr, at: Accessor::Field(field), .. } => write!(f, "{}.{}", expr, field),
+            Expression::Access { expr, at: Accessor::Index(index), .. } => write!(f, "{}[{}]", expr, index),
+            Expression::Case { expr, arms, .. } => {
+                write!(f, "(case {} of", expr)?;
+                for (i, (pattern, body)) in arms.iter().enumerate() {
+                    write!(f, "{} {} => {}", if i == 0 { "" } else { " |" }, pattern, body)?;
+                }
+                write!(f, ")")
+            },
+            Expression::Injection { label, expr, .. } => write!(f, "<{} = {}>", label, expr),
+            Expression::Is { expr, kind, .. } => write!(f, "({} is {})", expr, kind),
+            Expression::List { data, .. } => write!(f, "[{}]", commas(data.iter())),
+            Expression::Array { data, .. } => write!(f, "#[{}]", commas(data.iter())),
+            Expression::Struct { data, .. } =>
+                write!(f, "{{{}}}", commas(data.iter().map(|(k, x)| format!("{} = {}", k, x)))),
+            Expression::TypeAbstraction { param, bound: Type::Empty, expr, .. } => write!(f, "(Λ{}.{})", param, expr),
+            Expression::TypeAbstraction { param, bound, expr, .. } => write!(f, "(Λ{} <: {}.{})", param, bound, expr),
+            Expression::TypeApplication { expr, kind, .. } => write!(f, "{}[{}]", expr, kind),
+            Expression::Pack { witness, expr, kind, .. } => write!(f, "({{*{}, {}}} as {})", witness, expr, kind),
+            Expression::Unpack { param, id, expr, body, .. } => write!(f, "(let {{{}, {}}} = {} in {})", param, id, expr, body),
+            Expression::Tuple { data, fields, .. } => {
+                let data = commas(std::iter::zip(data, fields).map(|(x, field)| match field {
+                    Some(field) => format!("{}: {}", field, x),
+                    None => x.to_string()
+                }));
+                match fields.len() {
+                    1 => write!(f, "({},)", data),
+                    _ => write!(f, "({})", data)
+                }
+            },
+        }
+    }
+}
+
+/// Display a sequence separated by commas.
+fn commas<T: core::fmt::Display>(data: impl Iterator<Item = T>) -> String {
+    data.map(|x| x.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+impl core::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Pattern::Member(id, kind) => write!(f, "{}: {}", id, kind),
+            Pattern::Label(label, id) => write!(f, "<{} = {}>", label, id),
         }
     }
 }
@@ -189,6 +793,16 @@ impl core::fmt::Display for Type {
                 }
                 write!(f, "]")
             },
+            Type::Variant(data) => {
+                write!(f, "<")?;
+                for (i, (key, val)) in data.iter().enumerate() {
+                    write!(f, "{}: {}", key, val)?;
+                    if i != data.len() - 1 {
+                        write!(f, ", ")?;
+                    }
+                }
+                write!(f, ">")
+            },
             Type::Tuple(data, fields) => {
                 write!(f, "tuple[")?;
                 for (i, (val, ident)) in std::iter::zip(data, fields).enumerate() {
@@ -202,7 +816,17 @@ impl core::fmt::Display for Type {
                 }
                 write!(f, "]")
             },
-            Type::Function(from, to) => write!(f, "{}->{}", from, to),
+            // the arrow is right-associative: bracket functions on the left
+            Type::Function(from, to, effects) => {
+                match **from {
+                    Type::Function(..) | Type::Forall(..) => write!(f, "({})", from)?,
+                    _ => write!(f, "{}", from)?
+                }
+                if !effects.is_empty() {
+                    write!(f, "-[{}]", effects.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(", "))?;
+                }
+                write!(f, "->{}", to)
+            },
             Type::Interface(data, kind) => {
                 write!(f, "interface[")?;
                 for (i, sig) in data.iter().enumerate() {
@@ -217,6 +841,10 @@ impl core::fmt::Display for Type {
                 write!(f, "]")
             },
             Type::Oneself => write!(f, "Self"),
+            Type::Variable(id) => write!(f, "{}", id),
+            Type::Forall(id, bound, body) if **bound == Type::Empty => write!(f, "forall {}. {}", id, body),
+            Type::Forall(id, bound, body) => write!(f, "forall {} <: {}. {}", id, bound, body),
+            Type::Existential(id) => write!(f, "^{}", id),
             Type::Generic(data) =>  {
                 write!(f, "generic[")?;
                 if let Some(data) = data {
@@ -248,32 +876,61 @@ impl core::fmt::Display for Term {
             Term::Array(data) => write!(f, "[{:?}]", data),
             Term::Union(data) => write!(f, "{{{:?}}}", data),
             Term::Struct(term) => write!(f, "{{{:?}}}", term),
+            Term::Variant(label, data) => write!(f, "<{} = {}>", label, data),
             Term::Tuple(data, fields) => write!(f, "({:?})", data),
+            Term::Closure { param, func, .. } => write!(f, "(λ{}.{})", param, func),
+            Term::Package(witness, term) => write!(f, "{{*{}, {}}}", witness, term),
         }
     }
 }
 
 /// expose necessary functions for the underlying HashMaps
 impl Context {
+    /// A context with every primitive registered.
     pub fn new() -> Self {
-        Context(HashMap::new(), HashMap::new())
+        let mut context = Context(HashMap::new(), HashMap::new(), HashMap::new(), Rc::default());
+        for (sig, op) in Primitive::signatures() {
+            context.insert_func(sig, Implementation::Primitive(op));
+        }
+        context
     }
-    pub fn get_term(&self, k: &Identifier) -> Option<&Term> {
+    pub fn get_type(&self, k: &Identifier) -> Option<&Type> {
         self.0.get(k)
     }
-    pub fn insert_term(&mut self, k: Identifier, v: Term) -> Option<Term> {
+    pub fn insert_type(&mut self, k: Identifier, v: Type) -> Option<Type> {
         self.0.insert(k, v)
     }
-    pub fn get_func(&self, k: &Signature) -> Option<&Expression> {
+    pub fn get_term(&self, k: &Identifier) -> Option<&Term> {
         self.1.get(k)
     }
-    pub fn insert_func(&mut self, k: Signature, v: Expression) -> Option<Expression> {
+    pub fn insert_term(&mut self, k: Identifier, v: Term) -> Option<Term> {
         self.1.insert(k, v)
     }
-    pub fn contains_term(&self, k: &Identifier) -> bool {
+    pub fn get_terms(&self) -> &HashMap<Identifier, Term> {
+        &self.1
+    }
+    pub fn set_terms(&mut self, v: HashMap<Identifier, Term>) -> HashMap<Identifier, Term> {
+        std::mem::replace(&mut self.1, v)
+    }
+    pub fn get_func(&self, k: &Signature) -> Option<&Implementation> {
+        self.2.get(k)
+    }
+    pub fn insert_func(&mut self, k: Signature, v: Implementation) -> Option<Implementation> {
+        self.2.insert(k, v)
+    }
+    pub fn get_sigs(&self, name: &Identifier) -> Vec<&Signature> {
+        self.2.keys().filter(|sig| &sig.name == name).collect()
+    }
+    pub fn contains_type(&self, k: &Identifier) -> bool {
         self.0.contains_key(k)
     }
-    pub fn contains_sig(&self, k: &Signature) -> bool {
+    pub fn contains_term(&self, k: &Identifier) -> bool {
         self.1.contains_key(k)
     }
+    pub fn contains_sig(&self, k: &Signature) -> bool {
+        self.2.contains_key(k)
+    }
+    pub fn get_ordered(&self) -> &RefCell<Ordered> {
+        &self.3
+    }
 }
@@ -1,99 +1,532 @@
+use std::collections::{BTreeMap, HashMap};
 use crate::ast::*;
+use crate::effects::{divergent, recursive, Effects};
+use crate::error::*;
+use crate::ordered::{existentials, function};
 
 impl Context {
     /// Checking judgement: takes an expression and a type to check against and calls out to `infer` as needed.
-    pub fn check(&self, expression: Expression, target: &Type) -> Result<()> {
+    pub fn check(&self, expression: Expression, target: &Type) -> Result<(), TypeError> {
+        let span = expression.span();
+        let target = &self.apply(target);
         match expression {
+            // ∀I: expressions are checked against the body of a quantified type, with its variable in scope
+            expression if let Type::Forall(id, bound, kind) = target && !matches!(expression, Expression::TypeAbstraction { .. }) =>
+                self.universal(id, bound, || self.assume(id, bound).check(expression, kind)),
             // fall through to inference mode
-            Expression::Annotation { expr, kind } => {
-                let result = self.infer(Expression::Annotation { expr, kind })?;
+            Expression::Annotation { expr, kind, span } => {
+                let result = self.infer(Expression::Annotation { expr, kind, span })?;
                 match self.subtype(&result, target) {
                     true => Ok(()),
-                    false => Err(format!("inferred type {result} does not match target {target}").into())
+                    false => Err(Diagnostic::new(span, TypeError::Mismatch { expected: target.clone(), found: result }))
                 }
             },
+            // union introduction: the value must be of some member type
+            Expression::Constant { term: Term::Union(data), .. } if matches!(target, Type::Union(_)) =>
+                self.check(Expression::Constant { term: *data, span }, target),
+            // compound constants are checked component-wise: so empty lists check against any list
+            Expression::Constant { term: term @ (Term::List(_) | Term::Array(_) | Term::Struct(_) | Term::Tuple(..)), .. } =>
+                self.check(Expression::lift(term, span), target),
             // Bt-CheckInfer
-            Expression::Constant { term } => match self.subtype(&term.convert()?, target) {
-                true => Ok(()),
-                false => Err(format!("constant is of wrong type, expected {target}").into())
-                // false => Ok(()) // all our constants are Empty for now
+            Expression::Constant { term, .. } => {
+                let kind = self.convert(&term).map_err(|e| Diagnostic::new(span, e))?;
+                match self.subtype(&kind, target) {
+                    true => Ok(()),
+                    false => Err(Diagnostic::new(span, TypeError::Mismatch { expected: target.clone(), found: kind }))
+                    // false => Ok(()) // all our constants are Empty for now
+                }
             },
             // Bt-CheckInfer
-            Expression::Variable { id } => match self.get_term(&id) {
-                Some(term) if self.subtype(&term.convert()?, target) => Ok(()),
-                Some(_) => Err(format!("variable {id} is of wrong type").into()),
-                None => Err(format!("failed to find variable {id} in context").into())
-            },
-            // Bt-Abs
-            Expression::Abstraction { param, func } => match target {
-                Type::Function(from, to) => {
+            Expression::Variable { id, .. } => match self.get_type(&id) {
+                Some(kind) => match self.subtype(kind, target) {
+                    true => Ok(()),
+                    false => Err(Diagnostic::new(span, TypeError::Mismatch { expected: target.clone(), found: kind.clone() }))
+                },
+                // signatures: any overload of the right type will do
+                None if self.get_sigs(&id).iter().any(|sig| self.subtype(&self.signature(sig), target)) => Ok(()),
+                None => {
+                    let result = self.infer(Expression::Variable { id, span })?;
+                    Err(Diagnostic::new(span, TypeError::Mismatch { expected: target.clone(), found: result }))
+                }
+            },
+            // Bt-Abs: the effects of the body must be declared by the function type
+            Expression::Abstraction { param, func, .. } => match target {
+                Type::Function(from, to, effects) => {
+                    let mut context = self.clone();
+                    context.insert_type(param, *from.clone());
+                    context.check(*func.clone(), to)?;
+                    let found = context.effects(&func)?;
+                    match found.is_subset(effects) {
+                        true => Ok(()),
+                        false => Err(Diagnostic::new(span, TypeError::UndeclaredEffects(
+                            found.difference(effects).copied().collect())))
+                    }
+                },
+                // →I against an existential: it is split into a function, with the effects of the body
+                Type::Existential(id) => {
+                    let (from, to) = self.split(id);
                     let mut context = self.clone();
-                    context.insert_term(param, from.default()?);
-                    return context.check(*func, to);
+                    context.insert_type(param, Type::Existential(from.clone()));
+                    context.check(*func.clone(), &Type::Existential(to.clone()))?;
+                    let effects = context.effects(&func)?;
+                    self.solve(id, function(&from, &to, effects));
+                    Ok(())
                 },
-                _ => Err(format!("attempting to check an abstraction with a non-function type {target}").into())
+                _ => Err(Diagnostic::new(span, TypeError::NonFunctionAbstraction(target.clone())))
             },
             // fall through to inference mode
-            Expression::Application { func, arg } => {
-                let result = &self.infer(Expression::Application { func, arg })?;
-                match self.subtype(result, target) {
+            Expression::Application { func, arg, span } => {
+                let result = self.infer(Expression::Application { func, arg, span })?;
+                match self.subtype(&result, target) {
                     true => Ok(()),
-                    false => Err(format!("inferred type {result} does not match {target}").into())
+                    false => Err(Diagnostic::new(span, TypeError::Mismatch { expected: target.clone(), found: result }))
                 }
             },
-            // T-If
-            Expression::Conditional { if_cond, if_then, if_else } => {
+            // T-If: with variables narrowed by type tests
+            Expression::Conditional { if_cond, if_then, if_else, .. } => {
+                let (then_context, else_context) = self.narrow(&if_cond);
                 self.check(*if_cond, &Type::Boolean)?;
-                self.check(*if_then, target)?;
-                self.check(*if_else, target)?;
+                then_context.check(*if_then, target)?;
+                else_context.check(*if_else, target)?;
                 Ok(())
+            },
+            // T-Let
+            Expression::Let { id, kind, value, body, .. } => {
+                let kind = match kind {
+                    Some(kind) => self.check(*value, &kind).map(|x| kind)?,
+                    None => self.infer(*value)?
+                };
+                let mut context = self.clone();
+                context.insert_type(id, kind);
+                context.check(*body, target)
+            },
+            // fall through to inference mode
+            Expression::Access { expr, at, span } => {
+                let result = self.infer(Expression::Access { expr, at, span })?;
+                match self.subtype(&result, target) {
+                    true => Ok(()),
+                    false => Err(Diagnostic::new(span, TypeError::Mismatch { expected: target.clone(), found: result }))
+                }
+            },
+            // fall through to inference mode
+            Expression::Is { expr, kind, span } => {
+                let result = self.infer(Expression::Is { expr, kind, span })?;
+                match self.subtype(&result, target) {
+                    true => Ok(()),
+                    false => Err(Diagnostic::new(span, TypeError::Mismatch { expected: target.clone(), found: result }))
+                }
+            },
+            // T-List: elements are checked against the element type
+            Expression::List { data, .. } if let Type::List(kind) | Type::Slice(kind) = target =>
+                data.into_iter().try_for_each(|expr| self.check(expr, kind)),
+            Expression::Array { data, .. } if let Type::Array(kind, len) = target && data.len() == *len =>
+                data.into_iter().try_for_each(|expr| self.check(expr, kind)),
+            Expression::Array { data, .. } if let Type::Slice(kind) = target =>
+                data.into_iter().try_for_each(|expr| self.check(expr, kind)),
+            // T-Struct: fields are checked against their types, and any extra fields inferred
+            Expression::Struct { data, .. } if let Type::Struct(fields) = target
+                && fields.keys().all(|key| data.contains_key(key)) =>
+                data.into_iter().try_for_each(|(key, expr)| match fields.get(&key) {
+                    Some(kind) => self.check(expr, kind),
+                    None => self.infer(expr).map(|_| ())
+                }),
+            // T-Tuple: elements are checked in order
+            Expression::Tuple { data, fields, .. } if let Type::Tuple(kinds, labels) = target
+                && data.len() == kinds.len() && labels == &fields =>
+                std::iter::zip(data, kinds).try_for_each(|(expr, kind)| self.check(expr, kind)),
+            // T-TAbs: the body is checked against the quantified type, its variable renamed to the parameter
+            // the body may only assume the bound of the parameter: which the quantified type must satisfy
+            // note: type variables shadow: in Λa. Λa. e, a refers to the innermost
+            Expression::TypeAbstraction { param, bound, expr, span } if let Type::Forall(id, of, kind) = target => {
+                if !self.subtype(of, &bound) {
+                    return Err(Diagnostic::new(span, TypeError::Mismatch { expected: *of.clone(), found: bound }));
+                }
+                let kind = kind.substitute(&HashMap::from([(id.clone(), Type::Variable(param.clone()))]));
+                self.universal(&param, &bound, || self.assume(&param, &bound).check(*expr, &kind))
+            },
+            // T-Unpack: the body is checked with the witness of the package abstract
+            Expression::Unpack { param, id, expr, body, .. } => {
+                let kind = self.infer(*expr)?;
+                let mut context = self.assume(&param, &kind);
+                context.insert_type(id, Type::Variable(param.clone()));
+                self.universal(&param, &kind, || context.check(*body, target))
+            },
+            // fall through to inference mode
+            expression @ (Expression::List { .. } | Expression::Array { .. } |
+                Expression::Struct { .. } | Expression::Tuple { .. } | Expression::Pack { .. } |
+                Expression::TypeAbstraction { .. } | Expression::TypeApplication { .. }) => {
+                let result = self.infer(expression)?;
+                match self.subtype(&result, target) {
+                    true => Ok(()),
+                    false => Err(Diagnostic::new(span, TypeError::Mismatch { expected: target.clone(), found: result }))
+                }
+            },
+            // T-Variant: the injected expression is checked against its alternative
+            Expression::Injection { label, expr, span } => match target {
+                Type::Variant(data) => match data.get(&label) {
+                    Some(kind) => self.check(*expr, kind),
+                    None => Err(Diagnostic::new(span, TypeError::NoField(target.clone(), label)))
+                },
+                _ => {
+                    let result = self.infer(Expression::Injection { label, expr, span })?;
+                    match self.subtype(&result, target) {
+                        true => Ok(()),
+                        false => Err(Diagnostic::new(span, TypeError::Mismatch { expected: target.clone(), found: result }))
+                    }
+                }
+            },
+            // T-Case: every arm is checked against the target, with its pattern bound
+            Expression::Case { expr, arms, .. } => {
+                let kind = self.infer(*expr)?;
+                let patterns: Vec<&Pattern> = arms.iter().map(|(pattern, _)| pattern).collect();
+                let bindings = self.cases(&kind, &patterns).map_err(|e| Diagnostic::new(span, e))?;
+                for ((id, kind), (_, body)) in std::iter::zip(bindings, arms) {
+                    let mut context = self.clone();
+                    context.insert_type(id, kind);
+                    context.check(body, target)?;
+                }
+                Ok(())
+            },
+            // T-Fix: unannotated functions are checked against target -> target, calling themselves divergently
+            Expression::Fix { func, span } => match *func {
+                Expression::Abstraction { .. } => self.check(*func,
+                    &Type::Function(Box::new(divergent(target)), Box::new(target.clone()), Effects::new())),
+                func => {
+                    let result = self.infer(Expression::Fix { func: Box::new(func), span })?;
+                    match self.subtype(&result, target) {
+                        true => Ok(()),
+                        false => Err(Diagnostic::new(span, TypeError::Mismatch { expected: target.clone(), found: result }))
+                    }
+                }
             }
         }
     }
 
     /// Inference judgement: takes an expression and attempts to infer the associated type.
-    pub fn infer(&self, expression: Expression) -> Result<Type> {
+    pub fn infer(&self, expression: Expression) -> Result<Type, TypeError> {
+        let span = expression.span();
         match expression {
             // Bt-Ann
-            Expression::Annotation { expr, kind } => self.check(*expr, &kind).map(|x| kind),
+            Expression::Annotation { expr, kind, .. } => self.check(*expr, &kind).map(|x| kind),
             // Bt-True / Bt-False / etc
-            Expression::Constant { term } => term.convert(),
+            Expression::Constant { term, .. } => self.convert(&term).map_err(|e| Diagnostic::new(span, e)),
             // Bt-Var
-            Expression::Variable { id } => match self.get_term(&id) {
-                Some(term) => Context::new().infer(Expression::Constant { term: term.clone() }),
-                None => Err(format!("failed to find variable in context {self:?}").into())
+            Expression::Variable { id, .. } => match self.get_type(&id) {
+                Some(kind) => Ok(self.apply(kind)),
+                None => match self.get_sigs(&id).as_slice() {
+                    [] => Err(Diagnostic::new(span, TypeError::Unbound(id))),
+                    [sig] => Ok(self.signature(sig)),
+                    _ => Err(Diagnostic::new(span, TypeError::Ambiguous(id)))
+                }
             },
-            // Bt-App
-            Expression::Application { func, arg } => match self.infer(*func)? {
-                Type::Function(from, to) => self.check(*arg, &from).map(|x| *to),
-                _ => Err("application abstraction is not a function type".into())
+            // overloaded signatures are resolved by the types of all their arguments
+            Expression::Application { func, arg, span } if self.overloaded(func.head()).is_some() => {
+                let (head, args) = Expression::Application { func, arg, span }.spine();
+                let id = self.overloaded(&head).unwrap();
+                let mut kinds = Vec::new();
+                for arg in args {
+                    kinds.push(self.infer(arg)?);
+                }
+                match self.resolve(&id, &kinds) {
+                    Some(sig) => Ok(uncurry(&self.signature(&sig), kinds.len()).unwrap().1),
+                    None => Err(Diagnostic::new(span, TypeError::NoOverload(id, kinds)))
+                }
+            },
+            // Bt-App: polymorphic functions are instantiated implicitly
+            Expression::Application { func, arg, .. } => {
+                let kind = self.infer(*func)?;
+                self.application(&kind, *arg, span)
             },
             // inference from an abstraction is always an error
             // we could try and infer the func without adding the parameter to scope:
             // but this is overwhelmingly likely to be an error, so just report it now.
-            Expression::Abstraction { param, func } =>
-                Err("attempting to infer from an abstraction".into()),
-            // idk
-            Expression::Conditional { if_cond, if_then, if_else } => {
+            Expression::Abstraction { param, func, .. } =>
+                Err(Diagnostic::new(span, TypeError::CannotInferAbstraction)),
+            // T-If: the branches may differ, the conditional is of the type of either
+            Expression::Conditional { if_cond, if_then, if_else, .. } => {
+                let (then_context, else_context) = self.narrow(&if_cond);
                 self.check(*if_cond, &Type::Boolean)?;
-                let if_then = self.infer(*if_then)?;
-                let if_else = self.infer(*if_else)?;
-                if self.subtype(&if_then, &if_else) && self.subtype(&if_else, &if_then) {
-                    Ok(if_then) // fixme: should be the join
-                } else {
-                    Err(format!("if clauses of different types: {if_then} and {if_else}").into())
+                let if_then = then_context.infer(*if_then)?;
+                let if_else = else_context.infer(*if_else)?;
+                Ok(self.join(&if_then, &if_else))
+            },
+            // type tests may test any well-typed expression
+            Expression::Is { expr, .. } => self.infer(*expr).map(|_| Type::Boolean),
+            // T-TAbs
+            Expression::TypeAbstraction { param, bound, expr, .. } =>
+                self.universal(&param, &bound, || self.assume(&param, &bound).infer(*expr).map(|kind| self.apply(&kind)))
+                    .map(|kind| Type::Forall(param, Box::new(bound), Box::new(kind))),
+            // T-TApp: the argument is substituted for the quantified variable, and must be within its bound
+            Expression::TypeApplication { expr, kind, .. } => match self.infer(*expr)? {
+                Type::Forall(id, bound, body) => match self.subtype(&kind, &bound) {
+                    true => Ok(body.substitute(&HashMap::from([(id, kind)]))),
+                    false => Err(Diagnostic::new(span, TypeError::Mismatch { expected: *bound, found: kind }))
+                },
+                found => Err(Diagnostic::new(span, TypeError::NotUniversal(found)))
+            },
+            // T-Pack: a package is of any supertype of its witness: typically an interface it implements
+            Expression::Pack { witness, expr, kind, .. } => {
+                self.check(*expr, &witness)?;
+                match self.subtype(&witness, &kind) {
+                    true => Ok(kind),
+                    false => Err(Diagnostic::new(span, TypeError::Mismatch { expected: kind, found: witness }))
+                }
+            },
+            // T-Unpack: the witness is a type variable bounded by the type of the package, and may not escape
+            Expression::Unpack { param, id, expr, body, .. } => {
+                let kind = self.infer(*expr)?;
+                let mut context = self.assume(&param, &kind);
+                context.insert_type(id, Type::Variable(param.clone()));
+                let result = self.universal(&param, &kind, || context.infer(*body).map(|kind| self.apply(&kind)))?;
+                match result.free().contains(&param) {
+                    true => Err(Diagnostic::new(span, TypeError::Escape(param, result))),
+                    false => Ok(result)
+                }
+            },
+            // T-List: elements of different types are joined
+            Expression::List { data, .. } => self.elements(data, span).map(|kind| Type::List(Box::new(kind))),
+            Expression::Array { data, .. } => {
+                let len = data.len();
+                self.elements(data, span).map(|kind| Type::Array(Box::new(kind), len))
+            },
+            Expression::Struct { data, .. } => {
+                let mut result = BTreeMap::new();
+                for (key, expr) in data {
+                    result.insert(key, self.infer(expr)?);
+                }
+                Ok(Type::Struct(result))
+            },
+            Expression::Tuple { data, fields, .. } => {
+                let mut result = Vec::new();
+                for expr in data {
+                    result.push(self.infer(expr)?);
                 }
+                Ok(Type::Tuple(result, fields))
+            },
+            // T-Let
+            Expression::Let { id, kind, value, body, .. } => {
+                let kind = match kind {
+                    Some(kind) => self.check(*value, &kind).map(|x| kind)?,
+                    None => self.infer(*value)?
+                };
+                let mut context = self.clone();
+                context.insert_type(id, kind);
+                context.infer(*body)
+            },
+            // T-Fix: annotated functions call themselves at their type, but may diverge in doing so
+            Expression::Fix { func, .. } => match self.infer(recursive(*func))? {
+                Type::Function(from, to, _) => match self.subtype(&to, &from) {
+                    true => Ok(*to),
+                    false => Err(Diagnostic::new(span, TypeError::Mismatch { expected: *from, found: *to }))
+                },
+                kind => Err(Diagnostic::new(span, TypeError::NotAFunction(kind)))
+            },
+            // T-Variant: an injection is of the variant of just its alternative, a subtype of any wider one
+            Expression::Injection { label, expr, .. } =>
+                Ok(Type::Variant(BTreeMap::from([(label, self.infer(*expr)?)]))),
+            // T-Case: the case is of the join of its arms
+            Expression::Case { expr, arms, .. } => {
+                let kind = self.infer(*expr)?;
+                let patterns: Vec<&Pattern> = arms.iter().map(|(pattern, _)| pattern).collect();
+                let bindings = self.cases(&kind, &patterns).map_err(|e| Diagnostic::new(span, e))?;
+                let mut result = Type::Error;
+                for ((id, kind), (_, body)) in std::iter::zip(bindings, arms) {
+                    let mut context = self.clone();
+                    context.insert_type(id, kind);
+                    result = self.join(&result, &context.infer(body)?);
+                }
+                Ok(result)
+            },
+            // T-Proj: fields of structs and tuples
+            Expression::Access { expr, at: Accessor::Field(field), .. } => match self.infer(*expr)? {
+                Type::Struct(data) if data.contains_key(&field) => Ok(data.get(&field).unwrap().clone()),
+                Type::Tuple(data, labels) if let Some(i) = Accessor::position(&field, &labels) => Ok(data[i].clone()),
+                kind => Err(Diagnostic::new(span, TypeError::NoField(kind, field)))
+            },
+            // indices of lists and arrays: bounds are checked at runtime
+            Expression::Access { expr, at: Accessor::Index(index), .. } => match self.infer(*expr)? {
+                Type::List(data) | Type::Array(data, _) | Type::Slice(data) =>
+                    self.check(*index, &Type::Natural).map(|x| *data),
+                kind => Err(Diagnostic::new(span, TypeError::NotIndexable(kind)))
             }
         }
     }
 
+    /// The name of a function implemented by signatures, if the expression refers to one.
+    /// Bound variables shadow signatures.
+    pub fn overloaded(&self, expression: &Expression) -> Option<Identifier> {
+        match expression {
+            Expression::Variable { id, .. } if !self.contains_type(id) && !self.contains_term(id) &&
+                !self.get_sigs(id).is_empty() => Some(id.clone()),
+            _ => None
+        }
+    }
+
+    /// Resolve an overloaded function by the types of its arguments: out of every signature
+    /// accepting them, pick the most specific (the one whose parameters are subtypes of all others).
+    pub fn resolve(&self, id: &Identifier, args: &[Type]) -> Option<Signature> {
+        let objects = objects(id, args);
+        let candidates: Vec<(&Signature, Vec<Type>)> = self.get_sigs(id).into_iter().chain(&objects)
+            .filter_map(|sig| uncurry(&sig.kind(), args.len()).map(|(params, _)| (sig, params)))
+            .filter(|(_, params)| std::iter::zip(args, params).all(|(arg, param)| self.subtype(arg, param)))
+            .collect();
+        candidates.iter().find(|(_, params)| candidates.iter().all(|(_, other)|
+            std::iter::zip(params, other).all(|(param, other)| self.subtype(param, other))))
+            .map(|(sig, _)| (*sig).clone())
+    }
+
+    /// The variables bound by the patterns of a case over a type, to their (narrowed) types.
+    /// Patterns must cover every member of a union and every alternative of a variant,
+    /// and every pattern must match some member.
+    pub fn cases(&self, kind: &Type, patterns: &[&Pattern]) -> core::result::Result<Vec<(Identifier, Type)>, TypeError> {
+        let members = members(kind);
+        let mut result = Vec::new();
+        for pattern in patterns {
+            let narrowed = match pattern {
+                Pattern::Member(_, of) => self.meet(kind, of),
+                Pattern::Label(label, _) => members.iter().filter_map(|member| match member {
+                    Type::Variant(data) => data.get(label),
+                    _ => None
+                }).fold(Type::Error, |acc, kind| self.join(&acc, kind))
+            };
+            match narrowed {
+                Type::Error => return Err(TypeError::Unreachable((*pattern).clone())),
+                narrowed => result.push((pattern.binding().clone(), narrowed))
+            }
+        }
+        let missing: Vec<Type> = members.into_iter().filter(|member| !patterns.iter().any(|pattern| match pattern {
+            Pattern::Member(_, of) => self.subtype(member, of),
+            Pattern::Label(label, _) => matches!(member, Type::Variant(data) if data.contains_key(label))
+        })).collect();
+        match missing.is_empty() {
+            true => Ok(result),
+            false => Err(TypeError::NonExhaustive(missing))
+        }
+    }
+
+    /// The join of the types of the elements of a list or array. Empty lists and arrays cannot be inferred.
+    fn elements(&self, data: Vec<Expression>, span: Span) -> Result<Type, TypeError> {
+        let mut result = None;
+        for expr in data {
+            let kind = self.infer(expr)?;
+            result = Some(match result {
+                Some(acc) => self.join(&acc, &kind),
+                None => kind
+            });
+        }
+        result.ok_or(Diagnostic::new(span, TypeError::CannotInferEmpty))
+    }
+
+    /// The contexts the branches of a conditional are checked in. Testing the type of a variable
+    /// narrows it to the tested type in the then-branch, and to the remaining members in the else-branch.
+    pub fn narrow(&self, cond: &Expression) -> (Context, Context) {
+        let (mut then_context, mut else_context) = (self.clone(), self.clone());
+        if let Expression::Is { expr, kind, .. } = cond && let Expression::Variable { id, .. } = &**expr
+            && let Some(of) = self.get_type(id) {
+            then_context.insert_type(id.clone(), self.meet(of, kind));
+            else_context.insert_type(id.clone(), self.difference(of, kind));
+        }
+        (then_context, else_context)
+    }
+
+    /// The context the scope of a type variable is checked in: where the signatures of an interface bound
+    /// are implemented for it, as they are by whatever it is instantiated at.
+    pub fn assume(&self, id: &Identifier, bound: &Type) -> Context {
+        let mut context = self.clone();
+        if let Type::Interface(signatures, _) = bound {
+            let variable = Type::Variable(id.clone());
+            for sig in signatures {
+                context.insert_func(Signature {
+                    name: sig.name.clone(),
+                    from: sig.from.clone().deselfify(&variable),
+                    to: sig.to.clone().deselfify(&variable)
+                }, Implementation::Bound);
+            }
+        }
+        context
+    }
+
+    /// Register an implementation of an interface: every signature of the interface must be implemented,
+    /// exactly once, by a method checked against it with `Self` replaced by the implementing type.
+    /// Methods may call each other, and themselves. Nothing is registered should any of them fail to check.
+    pub fn implement(&mut self, instance: Instance) -> Result<(), TypeError> {
+        let Type::Interface(signatures, _) = &instance.interface else {
+            return Err(Diagnostic::new(instance.span, TypeError::NotAnInterface(instance.interface)));
+        };
+        let implemented = |sig: &Signature| Signature {
+            name: sig.name.clone(),
+            from: sig.from.clone().deselfify(&instance.kind),
+            to: sig.to.clone().deselfify(&instance.kind)
+        };
+        let mut methods: Vec<(Signature, Expression)> = Vec::new();
+        for (name, method) in instance.methods.iter().cloned() {
+            let Some(sig) = signatures.iter().find(|sig| sig.name == name) else {
+                return Err(Diagnostic::new(method.span(), TypeError::NoMethod(instance.interface.clone(), name)));
+            };
+            let sig = implemented(sig);
+            if self.contains_sig(&sig) || methods.iter().any(|(other, _)| other == &sig) {
+                return Err(Diagnostic::new(method.span(), TypeError::Duplicate(sig.name.clone(), sig.kind())));
+            }
+            let (kind, span) = (sig.kind(), method.span());
+            methods.push((sig, Expression::Annotation { expr: Box::new(method), kind, span }));
+        }
+        if let Some(sig) = signatures.iter().map(implemented).find(|sig| !methods.iter().any(|(other, _)| other == sig)) {
+            return Err(Diagnostic::new(instance.span, TypeError::Unimplemented(sig.name.clone(), sig.kind())));
+        }
+        let mut scope = self.clone();
+        for (sig, method) in &methods {
+            scope.insert_func(sig.clone(), Implementation::Defined(method.clone()));
+        }
+        for (_, method) in &methods {
+            scope.infer(method.clone())?;
+        }
+        for (sig, method) in methods {
+            self.insert_func(sig, Implementation::Defined(method));
+        }
+        Ok(())
+    }
+
+    /// The members of a type that are not subtypes of another: what a value can be if it fails a type test.
+    pub fn difference(&self, kind: &Type, without: &Type) -> Type {
+        members(kind).into_iter().filter(|member| !self.subtype(member, without))
+            .fold(Type::Error, |acc, member| self.join(&acc, &member))
+    }
+
     /// The subtyping relation between any two types.
     /// "is" is a subtype of "of", i.e. "is" can be safely used in any context "of" is expected.
+    /// Existentials are solved as needed for it to hold, and left unsolved should it not.
     pub fn subtype(&self, is: &Type, of: &Type) -> bool {
+        self.attempt(|| self.subsume(is, of))
+    }
+
+    fn subsume(&self, is: &Type, of: &Type) -> bool {
         match (is, of) {
             (_, Type::Empty) => true,   // top type: every type is a subtype of the empty type (empty as in structurally empty)
             (Type::Error, _) => true,   // bottom type: no type is a subtype of the error type
             (Type::Natural, Type::Integer) => true, // obviously not, but let's pretend
+            // existentials are solved (InstantiateL, InstantiateR), unless they occur in what they are solved with
+            (Type::Existential(is), Type::Existential(of)) if is == of => true,
+            (Type::Existential(id), of) if let Some(is) = self.solution(id) => self.subtype(&is, of),
+            (is, Type::Existential(id)) if let Some(of) = self.solution(id) => self.subtype(is, &of),
+            (Type::Existential(id), of) => {
+                let of = self.apply(of);
+                !existentials(&of).contains(id) && self.instantiate_left(id, &of)
+            },
+            (is, Type::Existential(id)) => {
+                let is = self.apply(is);
+                !existentials(&is).contains(id) && self.instantiate_right(&is, id)
+            },
+            // ∀R: a subtype of a quantified type is a subtype of its body, for a variable occurring nowhere else
+            (is, Type::Forall(id, bound, of)) => {
+                let fresh = Type::fresh(id, &is.free());
+                self.universal(&fresh, bound, || self.subtype(is, &of.substitute(&HashMap::from([(id.clone(), Type::Variable(fresh.clone()))]))))
+            },
+            // ∀L: a quantified type is a subtype of whatever some instance of it, within its bound, is
+            // note: this subsumes kernel F<: (S-All): quantified types with the same bounds and related bodies
+            (Type::Forall(id, bound, is), of) => self.marked(|ex|
+                self.subtype(&is.substitute(&HashMap::from([(id.clone(), ex.clone())])), of) && self.subtype(&ex, bound)),
+            // S-TVar: type variables are subtypes of their bounds, and so of anything their bounds are
+            (Type::Variable(id), of) if let Some(bound) = self.bound(id) && bound != Type::Empty && self.subtype(&bound, of) => true,
             (Type::List(is), Type::Slice(of)) | (Type::Array(is, _), Type::Slice(of)) |
             (Type::List(is), Type::List(of)) |  (Type::Slice(is), Type::Slice(of)) => self.subtype(is, of),
             (Type::Array(is, is_size), Type::Array(of, of_size)) => self.subtype(is, of) && is_size == of_size,
@@ -128,18 +561,21 @@ impl Context {
                 }
                 true
             },
-            (Type::Union(is), Type::Union(of)) => {
-                // a union type is a subtype of another if the latter has *more* fields (opposite structs!)
-                for data in of {
-                    if !is.contains(data) {
-                        return false;
-                    }
-                }
-                true
+            (Type::Variant(is), Type::Variant(of)) => {
+                // width, depth, and permutation: the latter may have *more* alternatives (opposite structs!)
+                is.iter().all(|(key, is_value)| of.get(key).is_some_and(|of_value| self.subtype(is_value, of_value)))
             },
-            (Type::Function(is_from, is_to), Type::Function(of_from, of_to)) => {
-                self.subtype(of_from, is_from) && self.subtype(is_to, of_to)
+            // a union type is a subtype of another if the latter has *more* fields (opposite structs!)
+            (Type::Union(is), of) => is.iter().all(|is| self.subtype(is, of)),
+            (is, Type::Union(of)) => of.iter().any(|of| self.subtype(is, of)),
+            // subeffecting: a function may be used where one with more effects is expected
+            (Type::Function(is_from, is_to, is_effects), Type::Function(of_from, of_to, of_effects)) => {
+                self.subtype(of_from, is_from) && self.subtype(is_to, of_to) && is_effects.is_subset(of_effects)
             },
+            // an interface is a subtype of another requiring fewer signatures: as is a variable bounded by it
+            (Type::Interface(is, is_associated), Type::Interface(of, of_associated)) =>
+                of.iter().all(|sig| is.contains(sig)) && of_associated.as_ref().is_none_or(|of|
+                    is_associated.as_ref().is_some_and(|is| self.subtype(is, of))),
             (is, Type::Interface(signatures, associated)) => {
                 if let Some(of) = associated && !self.subtype(is, of) {
                     return false;
@@ -163,6 +599,132 @@ impl Context {
     }
 }
 
+impl Context {
+    /// The least upper bound of two types: the most specific type both are subtypes of.
+    /// Incomparable types are joined into a union, structs into their common fields.
+    pub fn join(&self, a: &Type, b: &Type) -> Type {
+        if self.subtype(a, b) {
+            return b.clone();
+        } else if self.subtype(b, a) {
+            return a.clone();
+        }
+        match (a, b) {
+            (Type::List(a), Type::List(b)) => Type::List(Box::new(self.join(a, b))),
+            (Type::Array(a, a_size), Type::Array(b, b_size)) if a_size == b_size =>
+                Type::Array(Box::new(self.join(a, b)), *a_size),
+            (Type::List(a) | Type::Array(a, _) | Type::Slice(a), Type::List(b) | Type::Array(b, _) | Type::Slice(b)) =>
+                Type::Slice(Box::new(self.join(a, b))),
+            (Type::Tuple(a, a_fields), Type::Tuple(b, b_fields)) if a.len() == b.len() && a_fields == b_fields =>
+                Type::Tuple(std::iter::zip(a, b).map(|(a, b)| self.join(a, b)).collect(), a_fields.clone()),
+            (Type::Struct(a), Type::Struct(b)) => Type::Struct(a.iter()
+                .filter_map(|(key, a)| b.get(key).map(|b| (key.clone(), self.join(a, b)))).collect()),
+            (Type::Variant(a), Type::Variant(b)) => {
+                let mut result = b.clone();
+                for (key, a) in a {
+                    let kind = match b.get(key) {
+                        Some(b) => self.join(a, b),
+                        None => a.clone()
+                    };
+                    result.insert(key.clone(), kind);
+                }
+                Type::Variant(result)
+            },
+            (Type::Function(a_from, a_to, a_effects), Type::Function(b_from, b_to, b_effects)) => Type::Function(
+                Box::new(self.meet(a_from, b_from)), Box::new(self.join(a_to, b_to)), a_effects | b_effects),
+            (a, b) => self.union(vec![a.clone(), b.clone()])
+        }
+    }
+
+    /// The greatest lower bound of two types: the most general type that is a subtype of both.
+    /// Types with nothing in common meet at the error type.
+    pub fn meet(&self, a: &Type, b: &Type) -> Type {
+        if self.subtype(a, b) {
+            return a.clone();
+        } else if self.subtype(b, a) {
+            return b.clone();
+        }
+        match (a, b) {
+            (Type::List(a), Type::List(b)) | (Type::List(a), Type::Slice(b)) | (Type::Slice(a), Type::List(b)) =>
+                Type::List(Box::new(self.meet(a, b))),
+            (Type::Array(a, size), Type::Array(b, b_size)) if size == b_size => Type::Array(Box::new(self.meet(a, b)), *size),
+            (Type::Array(a, size), Type::Slice(b)) | (Type::Slice(a), Type::Array(b, size)) =>
+                Type::Array(Box::new(self.meet(a, b)), *size),
+            (Type::Slice(a), Type::Slice(b)) => Type::Slice(Box::new(self.meet(a, b))),
+            (Type::Tuple(a, a_fields), Type::Tuple(b, b_fields)) if a.len() == b.len() && a_fields == b_fields =>
+                Type::Tuple(std::iter::zip(a, b).map(|(a, b)| self.meet(a, b)).collect(), a_fields.clone()),
+            (Type::Struct(a), Type::Struct(b)) => {
+                let mut result = b.clone();
+                for (key, a) in a {
+                    let kind = match b.get(key) {
+                        Some(b) => self.meet(a, b),
+                        None => a.clone()
+                    };
+                    result.insert(key.clone(), kind);
+                }
+                Type::Struct(result)
+            },
+            (Type::Variant(a), Type::Variant(b)) => Type::Variant(a.iter()
+                .filter_map(|(key, a)| b.get(key).map(|b| (key.clone(), self.meet(a, b)))).collect()),
+            (Type::Function(a_from, a_to, a_effects), Type::Function(b_from, b_to, b_effects)) => Type::Function(
+                Box::new(self.join(a_from, b_from)), Box::new(self.meet(a_to, b_to)), a_effects & b_effects),
+            // unions distribute over meets
+            (Type::Union(data), other) | (other, Type::Union(data)) =>
+                self.union(data.iter().map(|data| self.meet(data, other)).filter(|x| *x != Type::Error).collect()),
+            _ => Type::Error
+        }
+    }
+
+    /// A union of types, flattening nested unions and dropping members that are subtypes of others.
+    /// Unions of one type are that type, and the empty union is the error type.
+    fn union(&self, data: Vec<Type>) -> Type {
+        let mut result: Vec<Type> = Vec::new();
+        for kind in data.into_iter().flat_map(|kind| match kind {
+            Type::Union(data) => data,
+            kind => vec![kind]
+        }) {
+            if result.iter().any(|other| self.subtype(&kind, other)) {
+                continue;
+            }
+            result.retain(|other| !self.subtype(other, &kind));
+            result.push(kind);
+        }
+        match result.len() {
+            0 => Type::Error,
+            1 => result.remove(0),
+            _ => Type::Union(result)
+        }
+    }
+
+    /// Convert a term into its type. The elements of lists and arrays are of the join of their types.
+    pub fn convert(&self, term: &Term) -> core::result::Result<Type, TypeError> {
+        let elements = |data: &Vec<Term>| match data.split_first() {
+            Some((first, rest)) => rest.iter().try_fold(self.convert(first)?, |acc, term|
+                self.convert(term).map(|kind| self.join(&acc, &kind))),
+            None => Err(TypeError::CannotInferEmpty)
+        };
+        match term {
+            Term::List(data) => Ok(Type::List(Box::new(elements(data)?))),
+            Term::Array(data) => Ok(Type::Array(Box::new(elements(data)?), data.len())),
+            Term::Struct(data) => {
+                let mut result = BTreeMap::new();
+                for (key, val) in data {
+                    result.insert(key.clone(), self.convert(val)?);
+                }
+                Ok(Type::Struct(result))
+            },
+            Term::Variant(label, data) => Ok(Type::Variant(BTreeMap::from([(label.clone(), self.convert(data)?)]))),
+            Term::Tuple(data, fields) => {
+                let mut result = Vec::new();
+                for val in data {
+                    result.push(self.convert(val)?);
+                }
+                Ok(Type::Tuple(result, fields.clone()))
+            },
+            term => term.convert()
+        }
+    }
+}
+
 impl Type {
     /// Replace explicit Oneself types with a replacement type. For interfaces.
     fn deselfify(self, replacement: &Type) -> Self {
@@ -177,15 +739,59 @@ impl Type {
                 data.iter().map(|x| x.clone().deselfify(replacement)).collect()),
             Type::Struct(data) => Type::Struct(
                 data.iter().map(|(k, v)| (k.clone(), v.clone().deselfify(replacement))).collect()),
+            Type::Variant(data) => Type::Variant(
+                data.iter().map(|(k, v)| (k.clone(), v.clone().deselfify(replacement))).collect()),
             Type::Tuple(data, idents) => Type::Tuple(
                 data.iter().map(|x| x.clone().deselfify(replacement)).collect(), idents),
-            Type::Function(from, to) => Type::Function(
-                Box::new(from.deselfify(replacement)), Box::new(to.deselfify(replacement))),
+            Type::Function(from, to, effects) => Type::Function(
+                Box::new(from.deselfify(replacement)), Box::new(to.deselfify(replacement)), effects),
             Type::Interface(signatures, associated) => Type::Interface(signatures,
                 associated.map(|x| Box::new(x.deselfify(replacement)))),
             Type::Generic(Some(data)) => Type::Generic(
                 Some(data.iter().map(|x| x.clone().deselfify(replacement)).collect())),
             Type::Generic(None) => Type::Generic(None),
+            Type::Variable(_) => self,
+            Type::Forall(id, bound, body) => Type::Forall(id, Box::new(bound.deselfify(replacement)), Box::new(body.deselfify(replacement))),
+            Type::Existential(_) => self,
+        }
+    }
+}
+
+/// The alternatives a value of a type may be: every member of a union, every labeled alternative of a variant.
+fn members(kind: &Type) -> Vec<Type> {
+    match kind {
+        Type::Union(data) => data.iter().flat_map(members).collect(),
+        Type::Variant(data) => data.iter()
+            .map(|(label, kind)| Type::Variant(BTreeMap::from([(label.clone(), kind.clone())]))).collect(),
+        kind => vec![kind.clone()]
+    }
+}
+
+/// The signatures objects of interface type among some arguments may be called through: those of their
+/// interface taking Self first and nowhere else. Calls are dispatched on the witness of the object at runtime.
+// note: signatures taking Self again could be passed objects of different witnesses: they must be unpacked
+fn objects(id: &Identifier, args: &[Type]) -> Vec<Signature> {
+    args.iter().flat_map(|arg| match arg {
+        Type::Interface(signatures, _) => signatures.iter()
+            // deselfifying a type without Self leaves it as is
+            .filter(|sig| &sig.name == id && sig.from == Type::Oneself && sig.to.clone().deselfify(&Type::Error) == sig.to)
+            .map(|sig| Signature { name: sig.name.clone(), from: arg.clone(), to: sig.to.clone() }).collect(),
+        _ => Vec::new()
+    }).collect()
+}
+
+/// Split a curried function type into its first `count` parameters and its result.
+pub fn uncurry(kind: &Type, count: usize) -> Option<(Vec<Type>, Type)> {
+    let mut params = Vec::new();
+    let mut result = kind.clone();
+    while params.len() < count {
+        match result {
+            Type::Function(from, to, _) => {
+                params.push(*from);
+                result = *to;
+            },
+            _ => return None
         }
     }
+    Some((params, result))
 }
@@ -0,0 +1,203 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use crate::ast::*;
+use crate::error::*;
+
+/// The side effects evaluating an expression may have, besides returning a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Effect {
+    Io,     // interacts with the outside world, i.e. `print`
+    State,  // reads or writes mutable memory
+    Exn,    // may fail at runtime, i.e. division by zero or indexing out of bounds
+    Div,    // may not terminate
+}
+
+/// A set of effects. Functions carry the effects of applying them in their type:
+/// a function with no effects is pure.
+pub type Effects = BTreeSet<Effect>;
+
+impl Context {
+    /// The effects of evaluating a (well-typed) expression.
+    /// Abstractions are values: the effects of their bodies are only had once they are applied.
+    // note: arithmetic overflow is not considered an exception.
+    // Divergence is inferred from recursion: fixpoints call themselves at a divergent type
+    pub fn effects(&self, expression: &Expression) -> Result<Effects, TypeError> {
+        let span = expression.span();
+        match expression {
+            Expression::Annotation { expr, .. } => self.effects(expr),
+            Expression::Constant { .. } | Expression::Variable { .. } | Expression::Abstraction { .. } =>
+                Ok(Effects::new()),
+            // overloaded signatures have the effects of the implementation they resolve to
+            Expression::Application { .. } if let Some(id) = self.overloaded(expression.head()) => {
+                let (_, args) = expression.clone().spine();
+                let mut result = Effects::new();
+                let mut kinds = Vec::new();
+                for arg in &args {
+                    result.extend(self.effects(arg)?);
+                    kinds.push(self.infer(arg.clone())?);
+                }
+                match self.resolve(&id, &kinds) {
+                    Some(sig) => {
+                        result.extend(latent(&self.signature(&sig), kinds.len()));
+                        Ok(result)
+                    },
+                    None => Err(Diagnostic::new(span, TypeError::NoOverload(id, kinds)))
+                }
+            },
+            Expression::Application { func, arg, .. } => {
+                let mut result = self.effects(func)?;
+                result.extend(self.effects(arg)?);
+                result.extend(latent(&self.infer(*func.clone())?, 1));
+                Ok(result)
+            },
+            Expression::Conditional { if_cond, if_then, if_else, .. } => {
+                let mut result = self.effects(if_cond)?;
+                let (then_context, else_context) = self.narrow(if_cond);
+                result.extend(then_context.effects(if_then)?);
+                result.extend(else_context.effects(if_else)?);
+                Ok(result)
+            },
+            Expression::Let { id, kind, value, body, .. } => {
+                let mut result = self.effects(value)?;
+                let mut context = self.clone();
+                context.insert_type(id.clone(), match kind {
+                    Some(kind) => kind.clone(),
+                    None => self.infer(*value.clone())?
+                });
+                result.extend(context.effects(body)?);
+                Ok(result)
+            },
+            // recursive functions are values, like any other abstraction: any other fixpoint may diverge
+            Expression::Fix { func, .. } => match unannotated(func) {
+                Expression::Abstraction { func: body, .. } if matches!(unannotated(body), Expression::Abstraction { .. }) =>
+                    Ok(Effects::new()),
+                _ => {
+                    let mut result = self.effects(func)?;
+                    result.extend(latent(&self.infer(*func.clone())?, 1));
+                    result.insert(Effect::Div);
+                    Ok(result)
+                }
+            },
+            Expression::Case { expr, arms, .. } => {
+                let mut result = self.effects(expr)?;
+                let kind = self.infer(*expr.clone())?;
+                let patterns: Vec<&Pattern> = arms.iter().map(|(pattern, _)| pattern).collect();
+                let bindings = self.cases(&kind, &patterns).map_err(|e| Diagnostic::new(span, e))?;
+                for ((id, kind), (_, body)) in std::iter::zip(bindings, arms) {
+                    let mut context = self.clone();
+                    context.insert_type(id, kind);
+                    result.extend(context.effects(body)?);
+                }
+                Ok(result)
+            },
+            // type abstractions and applications are erased
+            Expression::Access { expr, at: Accessor::Field(_), .. } | Expression::Injection { expr, .. } |
+            Expression::Is { expr, .. } | Expression::TypeAbstraction { expr, .. } |
+            Expression::TypeApplication { expr, .. } =>
+                self.effects(expr),
+            Expression::Pack { expr, .. } => self.effects(expr),
+            Expression::Unpack { param, id, expr, body, .. } => {
+                let mut result = self.effects(expr)?;
+                let kind = self.infer(*expr.clone())?;
+                let mut context = self.assume(param, &kind);
+                context.insert_type(id.clone(), Type::Variable(param.clone()));
+                result.extend(self.universal(param, &kind, || context.effects(body))?);
+                Ok(result)
+            },
+            Expression::List { data, .. } | Expression::Array { data, .. } | Expression::Tuple { data, .. } => {
+                let mut result = Effects::new();
+                for expr in data {
+                    result.extend(self.effects(expr)?);
+                }
+                Ok(result)
+            },
+            Expression::Struct { data, .. } => {
+                let mut result = Effects::new();
+                for expr in data.values() {
+                    result.extend(self.effects(expr)?);
+                }
+                Ok(result)
+            },
+            // indices are bounds-checked at runtime
+            Expression::Access { expr, at: Accessor::Index(index), .. } => {
+                let mut result = self.effects(expr)?;
+                result.extend(self.effects(index)?);
+                result.insert(Effect::Exn);
+                Ok(result)
+            }
+        }
+    }
+
+    /// The type of a signature, carrying the effects of its implementation.
+    /// Defined implementations are checked against their signatures, and so are pure.
+    pub fn signature(&self, sig: &Signature) -> Type {
+        match self.get_func(sig) {
+            Some(Implementation::Primitive(op)) => effectful(&sig.kind(), op.arity(), &op.effects()),
+            _ => sig.kind()
+        }
+    }
+}
+
+/// The effects of applying a curried function to `count` arguments.
+pub fn latent(kind: &Type, count: usize) -> Effects {
+    match kind {
+        Type::Function(_, to, effects) if count > 0 => {
+            let mut result = effects.clone();
+            result.extend(latent(to, count - 1));
+            result
+        },
+        Type::Forall(_, _, body) => latent(body, count),
+        _ => Effects::new()
+    }
+}
+
+/// A function type that may diverge once fully applied: the type fixpoints call themselves at.
+pub fn divergent(kind: &Type) -> Type {
+    match kind {
+        Type::Function(from, to, effects) if matches!(**to, Type::Function(..)) =>
+            Type::Function(from.clone(), Box::new(divergent(to)), effects.clone()),
+        Type::Function(..) => effectful(kind, 1, &Effects::from([Effect::Div])),
+        Type::Forall(id, bound, body) => Type::Forall(id.clone(), bound.clone(), Box::new(divergent(body))),
+        kind => kind.clone()
+    }
+}
+
+/// An annotated function of its own type, as a fixpoint takes it: its parameter, the function itself, may diverge.
+pub fn recursive(func: Expression) -> Expression {
+    match func {
+        Expression::Annotation { expr, kind: Type::Function(from, to, effects), span }
+            if matches!(*expr, Expression::Abstraction { .. }) =>
+            Expression::Annotation { expr, kind: Type::Function(Box::new(divergent(&from)), to, effects), span },
+        func => func
+    }
+}
+
+/// An expression without the annotations around it.
+fn unannotated(expression: &Expression) -> &Expression {
+    match expression {
+        Expression::Annotation { expr, .. } => unannotated(expr),
+        expr => expr
+    }
+}
+
+/// Add effects to a curried function type, had once it is applied to `count` arguments.
+fn effectful(kind: &Type, count: usize, with: &Effects) -> Type {
+    match kind {
+        Type::Function(from, to, effects) if count == 1 =>
+            Type::Function(from.clone(), to.clone(), effects.union(with).copied().collect()),
+        Type::Function(from, to, effects) if count > 1 =>
+            Type::Function(from.clone(), Box::new(effectful(to, count - 1, with)), effects.clone()),
+        kind => kind.clone()
+    }
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Effect::Io => write!(f, "io"),
+            Effect::State => write!(f, "state"),
+            Effect::Exn => write!(f, "exn"),
+            Effect::Div => write!(f, "div"),
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use crate::ast::*;
+use crate::effects::Effects;
+use crate::primitives::Primitive;
+
+/// An error annotated with the span of source it concerns.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic<E> {
+    pub error: E,
+    pub span: Span
+}
+
+/// Errors from lexing and parsing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    Expected(Vec<&'static str>),
+    InvalidToken(String),
+    InvalidEscape,
+    InvalidCharacter,
+    UnterminatedLiteral,
+    UnbalancedBracket(char),
+    UnclosedBracket,
+    UnexpectedIndentation,
+    InconsistentIndentation(usize),
+    Tab,
+}
+
+/// Errors from type checking and inference.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeError {
+    Mismatch { expected: Type, found: Type },   // found is not a subtype of expected
+    BranchMismatch(Type, Type),                 // conditional branches that don't agree
+    Unbound(Identifier),
+    Ambiguous(Identifier),                      // an overloaded function used without arguments
+    NoOverload(Identifier, Vec<Type>),          // no signature accepts these argument types
+    NotAFunction(Type),                         // applying something that isn't a function
+    NotUniversal(Type),                         // applying a type to something that isn't polymorphic
+    Escape(Identifier, Type),                   // the abstract type of an unpacked package escaping its scope
+    NoField(Type, Identifier),                  // accessing a field a struct or tuple doesn't have
+    NotAnInterface(Type),                       // implementing something that isn't an interface
+    NoMethod(Type, Identifier),                 // implementing a method an interface doesn't have
+    Duplicate(Identifier, Type),                // implementing a signature that already is
+    Unimplemented(Identifier, Type),            // leaving a signature of an implemented interface out
+    NotIndexable(Type),                         // indexing something that isn't a list or array
+    Infinite(Identifier, Type),                 // unification failing the occurs check
+    Polymorphic(Type),                          // interfaces or generics left after monomorphization
+    UndeclaredEffects(Effects),                 // a function body has effects its type does not declare
+    NonExhaustive(Vec<Type>),                   // members of a union no arm of a case matches
+    Unreachable(Pattern),                       // an arm of a case no value can match
+    NonFunctionAbstraction(Type),               // checking an abstraction against a non-function type
+    CannotInferAbstraction,
+    CannotInferEmpty,                           // empty lists and arrays
+    CannotInferUnion,
+    NoDefault(Type),
+}
+
+/// Errors from evaluation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    Unbound(Identifier),
+    NoOverload(Identifier, Vec<Term>),
+    Unsaturated(Identifier),                    // primitives must be applied to all their arguments
+    InvalidArguments(Primitive, Vec<Term>),
+    Overflow,
+    DivisionByZero,
+    Unordered,
+    NotAFunction(Term),
+    NotABoolean(Term),
+    NoField(Term, Identifier),
+    NotIndexable(Term),
+    InvalidIndex(Term),
+    OutOfBounds { index: usize, len: usize },
+    NoMatch(Term),                              // no arm of a case matches the value
+}
+
+impl<E> Diagnostic<E> {
+    pub fn new(span: Span, error: E) -> Self {
+        Diagnostic { error, span }
+    }
+}
+
+impl<E: core::fmt::Display> Diagnostic<E> {
+    /// Render the error as a `file:line:col` header followed by the offending source line,
+    /// with the span underlined by carets.
+    pub fn render(&self, file: &str, source: &str) -> String {
+        let start = self.span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map(|x| x + 1).unwrap_or(0);
+        let line_end = source[start..].find('\n').map(|x| x + start).unwrap_or(source.len());
+        let line = source[..start].matches('\n').count() + 1;
+        let col = source[line_start..start].chars().count() + 1;
+
+        // underline the span, clipped to the end of its first line
+        let end = self.span.end.clamp(start, line_end);
+        let width = source[start..end].chars().count().max(1);
+        let gutter = " ".repeat(line.to_string().len());
+        format!("{}:{}:{}: error: {}\n{} |\n{} | {}\n{} | {}{}",
+            file, line, col, self.error,
+            gutter,
+            line, &source[line_start..line_end],
+            gutter, " ".repeat(col - 1), "^".repeat(width))
+    }
+}
+
+impl<E: core::fmt::Display> core::fmt::Display for Diagnostic<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl<E: core::fmt::Debug + core::fmt::Display> std::error::Error for Diagnostic<E> {}
+
+impl core::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ParseError::Expected(data) => {
+                write!(f, "expected ")?;
+                if data.len() > 1 {
+                    write!(f, "one of ")?;
+                }
+                write!(f, "{}", data.join(", "))
+            },
+            ParseError::InvalidToken(token) => write!(f, "could not parse token {}", token),
+            ParseError::InvalidEscape => write!(f, "invalid escape sequence"),
+            ParseError::InvalidCharacter => write!(f, "invalid character literal"),
+            ParseError::UnterminatedLiteral => write!(f, "unterminated string or character literal"),
+            ParseError::UnbalancedBracket(c) => write!(f, "unbalanced closing bracket {}", c),
+            ParseError::UnclosedBracket => write!(f, "unclosed bracket at end of input"),
+            ParseError::UnexpectedIndentation => write!(f, "unexpected indentation on the first line"),
+            ParseError::InconsistentIndentation(count) =>
+                write!(f, "inconsistent indentation: {} spaces does not match any enclosing scope", count),
+            ParseError::Tab => write!(f, "tabs are not supported"),
+        }
+    }
+}
+
+impl core::fmt::Display for TypeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TypeError::Mismatch { expected, found } =>
+                write!(f, "type mismatch: expected {}, found {}", expected, found),
+            TypeError::BranchMismatch(if_then, if_else) =>
+                write!(f, "if clauses of different types: {} and {}", if_then, if_else),
+            TypeError::Unbound(id) => write!(f, "failed to find variable {} in context", id),
+            TypeError::Ambiguous(id) => write!(f, "ambiguous use of overloaded function {}", id),
+            TypeError::NoOverload(id, kinds) => {
+                write!(f, "no signature of {} accepts arguments of type", id)?;
+                for kind in kinds {
+                    write!(f, " {}", kind)?;
+                }
+                Ok(())
+            },
+            TypeError::NotAFunction(kind) => write!(f, "attempting to apply a non-function of type {}", kind),
+            TypeError::NotUniversal(kind) => write!(f, "attempting to apply a type to non-universal type {}", kind),
+            TypeError::Escape(id, kind) => write!(f, "unpacked type {} escapes its scope in {}", id, kind),
+            TypeError::NoField(kind, field) => write!(f, "no field {} on type {}", field, kind),
+            TypeError::NotAnInterface(kind) => write!(f, "attempting to implement non-interface type {}", kind),
+            TypeError::NoMethod(kind, id) => write!(f, "no method {} on interface {}", id, kind),
+            TypeError::Duplicate(id, kind) => write!(f, "duplicate implementation of {} for type {}", id, kind),
+            TypeError::Unimplemented(id, kind) => write!(f, "missing implementation of {} for type {}", id, kind),
+            TypeError::NotIndexable(kind) => write!(f, "attempting to index into non-list type {}", kind),
+            TypeError::Infinite(id, kind) => write!(f, "infinite type: {} occurs in {}", id, kind),
+            TypeError::Polymorphic(kind) => write!(f, "cannot specialize polymorphic type {}", kind),
+            TypeError::UndeclaredEffects(effects) => write!(f, "function has undeclared effects {}",
+                effects.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(", ")),
+            TypeError::NonExhaustive(kinds) => write!(f, "case does not cover members {}",
+                kinds.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(", ")),
+            TypeError::Unreachable(pattern) => write!(f, "pattern {} can never match", pattern),
+            TypeError::NonFunctionAbstraction(kind) =>
+                write!(f, "attempting to check an abstraction with a non-function type {}", kind),
+            TypeError::CannotInferAbstraction => write!(f, "attempting to infer from an abstraction"),
+            TypeError::CannotInferEmpty => write!(f, "attempting to infer the type of an empty list or array"),
+            TypeError::CannotInferUnion => write!(f, "attempting to infer the type of a union variant"),
+            TypeError::NoDefault(kind) => write!(f, "attempting to take the default term of {}", kind),
+        }
+    }
+}
+
+impl core::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            RuntimeError::Unbound(id) => write!(f, "no such variable {} in context", id),
+            RuntimeError::NoOverload(id, terms) => {
+                write!(f, "no signature of {} accepts arguments", id)?;
+                for term in terms {
+                    write!(f, " {}", term)?;
+                }
+                Ok(())
+            },
+            RuntimeError::Unsaturated(id) => write!(f, "primitive {} must be applied to all its arguments", id),
+            RuntimeError::InvalidArguments(op, terms) => write!(f, "invalid arguments {:?} to primitive {:?}", terms, op),
+            RuntimeError::Overflow => write!(f, "arithmetic overflow"),
+            RuntimeError::DivisionByZero => write!(f, "division by zero"),
+            RuntimeError::Unordered => write!(f, "attempting to order incomparable values"),
+            RuntimeError::NotAFunction(term) => write!(f, "attempting to apply non-function {}", term),
+            RuntimeError::NotABoolean(term) => write!(f, "invalid term {} for a conditional", term),
+            RuntimeError::NoField(term, field) => write!(f, "no field {} on term {}", field, term),
+            RuntimeError::NotIndexable(term) => write!(f, "attempting to index into non-list {}", term),
+            RuntimeError::InvalidIndex(term) => write!(f, "invalid term {} for an index", term),
+            RuntimeError::OutOfBounds { index, len } =>
+                write!(f, "index {} out of bounds for length {}", index, len),
+            RuntimeError::NoMatch(term) => write!(f, "no arm of the case matches {}", term),
+        }
+    }
+}
@@ -3,6 +3,12 @@
 
 pub mod ast;
 pub mod bidirectional;
+pub mod effects;
+pub mod error;
+pub mod monomorphize;
+pub mod ordered;
 pub mod parser;
+pub mod primitives;
 pub mod util;
 pub mod simple;
+pub mod unification;
@@ -22,9 +22,16 @@ fn main() {
 
                 input.clear();
                 stdin().read_line(&mut input).unwrap();
-                match empty_context.infer(parser::parse_lambda(&input).unwrap()) {
+                let expr = match parser::parse_lambda(&input) {
+                    Ok(expr) => expr,
+                    Err(e) => {
+                        println!("{}", e.render("stdin", &input));
+                        continue;
+                    }
+                };
+                match empty_context.infer(expr) {
                     Ok(kind) => println!("infers! {}", kind),
-                    Err(e) => println!("{:?}", e),
+                    Err(e) => println!("{}", e.render("stdin", &input)),
                 }
             },
             "c" | "t" | "check" => {
@@ -34,12 +41,18 @@ fn main() {
 
                 input.clear();
                 stdin().read_line(&mut input).unwrap();
-                let kind = empty_context.infer(parser::parse_lambda(&input).unwrap());
-                match kind {
+                let expr = match parser::parse_lambda(&input) {
+                    Ok(expr) => expr,
+                    Err(e) => {
+                        println!("{}", e.render("stdin", &input));
+                        continue;
+                    }
+                };
+                match empty_context.infer(expr.clone()) {
                     Ok(kind) => {
-                        match empty_context.check(parser::parse_lambda(&input).unwrap(), &kind) {
+                        match empty_context.check(expr, &kind) {
                             Ok(_) => println!("checks!"),
-                            Err(e) => println!("{:?}", e),
+                            Err(e) => println!("{}", e.render("stdin", &input)),
                         }
                     },
                     Err(_) => println!("failed to infer high-level type!")
@@ -52,9 +65,16 @@ fn main() {
 
                 input.clear();
                 stdin().read_line(&mut input).unwrap();
-                match empty_context.execute(parser::parse_lambda(&input).unwrap()) {
+                let expr = match parser::parse_lambda(&input) {
+                    Ok(expr) => expr,
+                    Err(e) => {
+                        println!("{}", e.render("stdin", &input));
+                        continue;
+                    }
+                };
+                match empty_context.execute(expr) {
                     Ok(term) => println!("{}", term),
-                    Err(e) => println!("{:?}", e)
+                    Err(e) => println!("{}", e.render("stdin", &input))
                 }
             },
             _ => println!("invalid option {}. please try again.", input.trim())
@@ -0,0 +1,392 @@
+use std::collections::HashMap;
+use crate::ast::*;
+use crate::bidirectional::uncurry;
+use crate::error::*;
+
+/// A function over interface or generic parameters, waiting to be specialized.
+struct Template {
+    params: Vec<Identifier>,
+    kind: Type,
+    body: Expression,
+    span: Span
+}
+
+/// The state of monomorphization: the types of every (monomorphic) function, and the functions
+/// specialized so far.
+struct Monomorphizer {
+    globals: Context,
+    templates: HashMap<Identifier, Template>,
+    specialized: HashMap<Identifier, Type>,
+    functions: Vec<(Identifier, Expression)>
+}
+
+impl Context {
+    /// Specialize every function over interfaces and generics to the concrete types it is called with.
+    /// Specialized copies are named after their parameter types, i.e. `max[nat, nat]`,
+    /// and precede their callers. Unused polymorphic functions are dropped.
+    /// Implementations are specialized to the types they are for: interface objects cannot be specialized.
+    // Occurrences of the same interface (or generic) in a signature are instantiated to the same type:
+    // `Self` is the type instantiating the first interface.
+    pub fn monomorphize(&self, program: &Program) -> Result<Program, TypeError> {
+        let mut state = Monomorphizer {
+            globals: self.clone(),
+            templates: HashMap::new(),
+            specialized: HashMap::new(),
+            functions: Vec::new()
+        };
+        // the program's own implementations are registered first, so that its types satisfy their interfaces
+        for instance in &program.instances {
+            state.register(instance);
+        }
+        for (name, func) in &program.functions {
+            match func {
+                Expression::Annotation { expr, kind, span } if polymorphic(kind) => {
+                    let (params, body) = parameters(expr);
+                    state.templates.insert(name.clone(), Template { params, kind: kind.clone(), body, span: *span });
+                },
+                func => {
+                    let func = state.rewrite(&state.globals.clone(), &HashMap::new(), func.clone())?;
+                    if let Expression::Annotation { kind, .. } = &func {
+                        state.globals.insert_type(name.clone(), kind.clone());
+                    }
+                    state.templates.remove(name);
+                    state.functions.push((name.clone(), func));
+                }
+            }
+        }
+        // and again once specialized, so that expressions call their specializations
+        let mut instances = Vec::new();
+        for instance in &program.instances {
+            let instance = state.implement(instance)?;
+            state.register(&instance);
+            instances.push(instance);
+        }
+        let mut expressions = Vec::new();
+        for expr in &program.expressions {
+            expressions.push(state.rewrite(&state.globals.clone(), &HashMap::new(), expr.clone())?);
+        }
+        Ok(Program { functions: state.functions, instances, expressions })
+    }
+}
+
+impl Monomorphizer {
+    /// Register the methods of an implementation as those of the signatures they implement.
+    fn register(&mut self, instance: &Instance) {
+        let interface = specialize(&instance.interface, &HashMap::from([(Type::Oneself, instance.kind.clone())]));
+        if let Type::Interface(signatures, _) = interface {
+            for (name, method) in &instance.methods {
+                if let Some(sig) = signatures.iter().find(|sig| &sig.name == name) {
+                    let (kind, span) = (sig.kind(), method.span());
+                    self.globals.insert_func(sig.clone(),
+                        Implementation::Defined(Expression::Annotation { expr: Box::new(method.clone()), kind, span }));
+                }
+            }
+        }
+    }
+
+    /// Specialize an implementation to the type it is for: `Self` in its interface is replaced with the type,
+    /// and its methods are rewritten with their parameters of the types of the signatures they implement.
+    // note: methods not in the interface are left as they are, to be reported when the implementation is registered
+    fn implement(&mut self, instance: &Instance) -> Result<Instance, TypeError> {
+        if polymorphic(&instance.kind) {
+            return Err(Diagnostic::new(instance.span, TypeError::Polymorphic(instance.kind.clone())));
+        }
+        let interface = specialize(&instance.interface, &HashMap::from([(Type::Oneself, instance.kind.clone())]));
+        let Type::Interface(signatures, _) = &interface else {
+            return Err(Diagnostic::new(instance.span, TypeError::NotAnInterface(interface)));
+        };
+        let mut methods = Vec::new();
+        for (name, method) in &instance.methods {
+            let method = match signatures.iter().find(|sig| &sig.name == name) {
+                Some(sig) => {
+                    let (kind, span) = (sig.kind(), method.span());
+                    let expr = Expression::Annotation { expr: Box::new(method.clone()), kind, span };
+                    match self.rewrite(&self.globals.clone(), &HashMap::new(), expr)? {
+                        Expression::Annotation { expr, .. } => *expr,
+                        expr => expr
+                    }
+                },
+                None => method.clone()
+            };
+            methods.push((name.clone(), method));
+        }
+        Ok(Instance { interface, kind: instance.kind.clone(), methods, span: instance.span })
+    }
+
+    /// Specialize a polymorphic function to the types of its arguments, returning the specialized name.
+    fn specialize(&mut self, name: &Identifier, args: &[Type], span: Span) -> Result<Identifier, TypeError> {
+        let template = self.templates.get(name).unwrap();
+        let (params, _) = match uncurry(&template.kind, template.params.len()) {
+            Some(result) if args.len() >= template.params.len() => result,
+            _ => return Err(Diagnostic::new(span, TypeError::Ambiguous(name.clone())))
+        };
+        let mut map = HashMap::new();
+        for (param, arg) in std::iter::zip(&params, args) {
+            self.instantiate(param, arg, &mut map).map_err(|e| Diagnostic::new(span, e))?;
+        }
+        let kind = specialize(&template.kind, &map);
+        if polymorphic(&kind) {
+            return Err(Diagnostic::new(span, TypeError::Polymorphic(kind)));
+        }
+        let (params, _) = uncurry(&kind, template.params.len()).unwrap();
+        let id = format!("{}[{}]", name, params.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(", "));
+        if self.specialized.contains_key(&id) {
+            return Ok(id);
+        }
+        self.specialized.insert(id.clone(), kind.clone());
+
+        let template = self.templates.get(name).unwrap();
+        let (names, body, span) = (template.params.clone(), template.body.clone(), template.span);
+        let mut env = self.globals.clone();
+        for (param, kind) in std::iter::zip(&names, params) {
+            env.insert_type(param.clone(), kind);
+        }
+        let body = self.rewrite(&env, &map, body)?;
+        let func = names.into_iter().rev().fold(body, |func, param| Expression::Abstraction {
+            param, func: Box::new(func), span
+        });
+        self.functions.push((id.clone(), Expression::Annotation { expr: Box::new(func), kind, span }));
+        Ok(id)
+    }
+
+    /// Infer the type of an expression, which may call specialized functions.
+    fn infer(&self, env: &Context, expression: &Expression) -> Result<Type, TypeError> {
+        let mut env = env.clone();
+        for (id, kind) in &self.specialized {
+            env.insert_type(id.clone(), kind.clone());
+        }
+        env.infer(expression.clone())
+    }
+
+    /// Match a parameter type against the type of an argument, recording what its interfaces
+    /// and generics are instantiated to.
+    fn instantiate(&self, param: &Type, arg: &Type, map: &mut HashMap<Type, Type>) -> core::result::Result<(), TypeError> {
+        match (param, arg) {
+            (Type::Interface(..) | Type::Generic(_), arg) => {
+                if !self.globals.subtype(arg, param) {
+                    return Err(TypeError::Mismatch { expected: param.clone(), found: arg.clone() });
+                }
+                match map.get(param) {
+                    Some(kind) if kind != arg => Err(TypeError::Mismatch { expected: kind.clone(), found: arg.clone() }),
+                    _ => {
+                        if matches!(param, Type::Interface(..)) && !map.contains_key(&Type::Oneself) {
+                            map.insert(Type::Oneself, arg.clone());
+                        }
+                        map.insert(param.clone(), arg.clone());
+                        Ok(())
+                    }
+                }
+            },
+            (Type::List(param), Type::List(arg)) | (Type::Slice(param), Type::Slice(arg)) |
+            (Type::Slice(param), Type::List(arg)) | (Type::Slice(param), Type::Array(arg, _)) |
+            (Type::Array(param, _), Type::Array(arg, _)) => self.instantiate(param, arg, map),
+            (Type::Tuple(params, _), Type::Tuple(args, _)) => {
+                for (param, arg) in std::iter::zip(params, args) {
+                    self.instantiate(param, arg, map)?;
+                }
+                Ok(())
+            },
+            (Type::Struct(params), Type::Struct(args)) | (Type::Variant(params), Type::Variant(args)) => {
+                for (key, param) in params {
+                    if let Some(arg) = args.get(key) {
+                        self.instantiate(param, arg, map)?;
+                    }
+                }
+                Ok(())
+            },
+            (Type::Function(param_from, param_to, _), Type::Function(arg_from, arg_to, _)) => {
+                self.instantiate(param_from, arg_from, map)?;
+                self.instantiate(param_to, arg_to, map)
+            },
+            _ => Ok(())
+        }
+    }
+
+    /// Replace calls to polymorphic functions with calls to their specializations, and calls to
+    /// signatures with their implementations, given the types of the variables in scope.
+    fn rewrite(&mut self, env: &Context, map: &HashMap<Type, Type>, expression: Expression) -> Result<Expression, TypeError> {
+        let span = expression.span();
+        match expression {
+            Expression::Application { func, arg, span } => {
+                let (head, args) = Expression::Application { func, arg, span }.spine();
+                let mut rewritten = Vec::new();
+                for arg in args {
+                    rewritten.push(self.rewrite(env, map, arg)?);
+                }
+                let head = match head {
+                    Expression::Variable { id, span: head_span } if self.templates.contains_key(&id) && !env.contains_type(&id) => {
+                        let mut kinds = Vec::new();
+                        for arg in &rewritten {
+                            kinds.push(self.infer(env, arg)?);
+                        }
+                        Expression::Variable { id: self.specialize(&id, &kinds, span)?, span: head_span }
+                    },
+                    Expression::Variable { id, span: head_span } if env.overloaded(&Expression::Variable { id: id.clone(), span }).is_some() => {
+                        // arguments of unknown type are left to be resolved at runtime
+                        let kinds: Option<Vec<Type>> = rewritten.iter().map(|arg| self.infer(env, arg).ok()).collect();
+                        match kinds.and_then(|kinds| env.resolve(&id, &kinds))
+                            .and_then(|sig| env.get_func(&sig).map(|func| (sig, func))) {
+                            Some((sig, Implementation::Defined(func))) =>
+                                Expression::Annotation { expr: Box::new(func.clone()), kind: sig.kind(), span: head_span },
+                            _ => Expression::Variable { id, span: head_span }
+                        }
+                    },
+                    head => self.rewrite(env, map, head)?
+                };
+                Ok(rewritten.into_iter().fold(head, |func, arg| Expression::Application {
+                    func: Box::new(func), arg: Box::new(arg), span
+                }))
+            },
+            Expression::Variable { id, .. } if self.templates.contains_key(&id) && !env.contains_type(&id) =>
+                Err(Diagnostic::new(span, TypeError::Ambiguous(id))),
+            Expression::Variable { id, span } => Ok(Expression::Variable { id, span }),
+            Expression::Constant { term, span } => Ok(Expression::Constant { term, span }),
+            // annotated abstractions give their parameters types
+            Expression::Annotation { expr, kind, span } => {
+                let kind = specialize(&kind, map);
+                if polymorphic(&kind) {
+                    return Err(Diagnostic::new(span, TypeError::Polymorphic(kind)));
+                }
+                let (params, body) = parameters(&expr);
+                let mut env = env.clone();
+                if let Some((kinds, _)) = uncurry(&kind, params.len()) {
+                    for (param, kind) in std::iter::zip(&params, kinds) {
+                        env.insert_type(param.clone(), kind);
+                    }
+                }
+                let body = self.rewrite(&env, map, body)?;
+                let expr = params.into_iter().rev().fold(body, |func, param| Expression::Abstraction {
+                    param, func: Box::new(func), span
+                });
+                Ok(Expression::Annotation { expr: Box::new(expr), kind, span })
+            },
+            // note: the parameters of unannotated abstractions are of unknown type
+            Expression::Abstraction { param, func, span } => {
+                let mut env = env.clone();
+                env.insert_type(param.clone(), unknown(&param));
+                Ok(Expression::Abstraction { func: Box::new(self.rewrite(&env, map, *func)?), param, span })
+            },
+            Expression::Conditional { if_cond, if_then, if_else, span } => Ok(Expression::Conditional {
+                if_cond: Box::new(self.rewrite(env, map, *if_cond)?),
+                if_then: Box::new(self.rewrite(env, map, *if_then)?),
+                if_else: Box::new(self.rewrite(env, map, *if_else)?),
+                span
+            }),
+            Expression::Let { id, kind, value, body, span } => {
+                let kind = kind.map(|kind| specialize(&kind, map));
+                if let Some(kind) = &kind && polymorphic(kind) {
+                    return Err(Diagnostic::new(span, TypeError::Polymorphic(kind.clone())));
+                }
+                let value = self.rewrite(env, map, *value)?;
+                let mut body_env = env.clone();
+                let value_kind = match &kind {
+                    Some(kind) => kind.clone(),
+                    None => self.infer(env, &value).unwrap_or(unknown(&id))
+                };
+                body_env.insert_type(id.clone(), value_kind);
+                let body = self.rewrite(&body_env, map, *body)?;
+                Ok(Expression::Let { id, kind, value: Box::new(value), body: Box::new(body), span })
+            },
+            Expression::Fix { func, span } => Ok(Expression::Fix { func: Box::new(self.rewrite(env, map, *func)?), span }),
+            Expression::Case { expr, arms, span } => {
+                let expr = self.rewrite(env, map, *expr)?;
+                let mut rewritten = Vec::new();
+                let kind = self.infer(env, &expr).ok();
+                let patterns: Vec<Pattern> = arms.iter().map(|(pattern, _)| match pattern {
+                    Pattern::Member(id, kind) => Pattern::Member(id.clone(), specialize(kind, map)),
+                    pattern => pattern.clone()
+                }).collect();
+                // patterns over a scrutinee of unknown type bind variables of unknown type
+                let bindings = kind.and_then(|kind| env.cases(&kind, &patterns.iter().collect::<Vec<_>>()).ok());
+                for (i, (pattern, (_, body))) in std::iter::zip(patterns, arms).enumerate() {
+                    let mut env = env.clone();
+                    match &bindings {
+                        Some(bindings) => env.insert_type(bindings[i].0.clone(), bindings[i].1.clone()),
+                        None => env.insert_type(pattern.binding().clone(), unknown(pattern.binding()))
+                    };
+                    let body = self.rewrite(&env, map, body)?;
+                    rewritten.push((pattern, body));
+                }
+                Ok(Expression::Case { expr: Box::new(expr), arms: rewritten, span })
+            },
+            Expression::Is { expr, kind, span } => {
+                let kind = specialize(&kind, map);
+                if polymorphic(&kind) {
+                    return Err(Diagnostic::new(span, TypeError::Polymorphic(kind)));
+                }
+                Ok(Expression::Is { expr: Box::new(self.rewrite(env, map, *expr)?), kind, span })
+            },
+            Expression::List { data, span } => Ok(Expression::List {
+                data: data.into_iter().map(|expr| self.rewrite(env, map, expr)).collect::<Result<_, _>>()?, span }),
+            Expression::Array { data, span } => Ok(Expression::Array {
+                data: data.into_iter().map(|expr| self.rewrite(env, map, expr)).collect::<Result<_, _>>()?, span }),
+            Expression::Struct { data, span } => Ok(Expression::Struct {
+                data: data.into_iter().map(|(key, expr)| self.rewrite(env, map, expr).map(|expr| (key, expr)))
+                    .collect::<Result<_, _>>()?, span }),
+            Expression::Tuple { data, fields, span } => Ok(Expression::Tuple {
+                data: data.into_iter().map(|expr| self.rewrite(env, map, expr)).collect::<Result<_, _>>()?, fields, span }),
+            Expression::TypeAbstraction { param, bound, expr, span } => Ok(Expression::TypeAbstraction {
+                param, bound: specialize(&bound, map), expr: Box::new(self.rewrite(env, map, *expr)?), span }),
+            // interface objects dispatch at runtime: they cannot be specialized
+            Expression::Pack { witness, expr, kind, span } => {
+                let (witness, kind) = (specialize(&witness, map), specialize(&kind, map));
+                if let Some(kind) = [&witness, &kind].into_iter().find(|kind| polymorphic(kind)) {
+                    return Err(Diagnostic::new(span, TypeError::Polymorphic(kind.clone())));
+                }
+                Ok(Expression::Pack { witness, expr: Box::new(self.rewrite(env, map, *expr)?), kind, span })
+            },
+            Expression::Unpack { param, id, expr, body, span } => {
+                let mut body_env = env.clone();
+                body_env.insert_type(id.clone(), Type::Variable(param.clone()));
+                Ok(Expression::Unpack { param, id, expr: Box::new(self.rewrite(env, map, *expr)?),
+                    body: Box::new(self.rewrite(&body_env, map, *body)?), span })
+            },
+            Expression::TypeApplication { expr, kind, span } => Ok(Expression::TypeApplication {
+                expr: Box::new(self.rewrite(env, map, *expr)?), kind: specialize(&kind, map), span }),
+            Expression::Injection { label, expr, span } =>
+                Ok(Expression::Injection { label, expr: Box::new(self.rewrite(env, map, *expr)?), span }),
+            Expression::Access { expr, at, span } => Ok(Expression::Access {
+                expr: Box::new(self.rewrite(env, map, *expr)?),
+                at: match at {
+                    Accessor::Field(field) => Accessor::Field(field),
+                    Accessor::Index(index) => Accessor::Index(Box::new(self.rewrite(env, map, *index)?))
+                },
+                span
+            }),
+        }
+    }
+}
+
+/// Whether a type mentions interfaces, generics, or Self.
+fn polymorphic(kind: &Type) -> bool {
+    match kind {
+        Type::Interface(..) | Type::Generic(_) | Type::Oneself => true,
+        Type::List(data) | Type::Array(data, _) | Type::Slice(data) => polymorphic(data),
+        Type::Union(data) | Type::Tuple(data, _) => data.iter().any(polymorphic),
+        Type::Struct(data) | Type::Variant(data) => data.values().any(polymorphic),
+        Type::Function(from, to, _) => polymorphic(from) || polymorphic(to),
+        _ => false
+    }
+}
+
+/// The type of a variable whose type is not known: a type variable no source can name.
+fn unknown(id: &Identifier) -> Type {
+    Type::Variable(format!("'{}", id))
+}
+
+/// Replace the interfaces and generics of a type with what they are instantiated to.
+fn specialize(kind: &Type, map: &HashMap<Type, Type>) -> Type {
+    kind.replace(&|kind| map.get(kind).cloned())
+}
+
+/// Split a chain of abstractions into its parameters and body.
+fn parameters(expression: &Expression) -> (Vec<Identifier>, Expression) {
+    match expression {
+        Expression::Abstraction { param, func, .. } => {
+            let (mut params, body) = parameters(func);
+            params.insert(0, param.clone());
+            (params, body)
+        },
+        expr => (Vec::new(), expr.clone())
+    }
+}
@@ -0,0 +1,292 @@
+// Higher-rank polymorphism, after Dunfield and Krishnaswami's
+// "Complete and Easy Bidirectional Typechecking for Higher-Rank Polymorphism".
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use crate::ast::*;
+use crate::effects::Effects;
+use crate::error::*;
+
+/// An entry of the ordered context.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entry {
+    Universal(Identifier, Type),    // a type variable in scope, and its bound: a <: T
+    Existential(Identifier),        // an unsolved existential: ^a
+    Solved(Identifier, Type),       // a solved existential: ^a = t
+    Marker(Identifier),             // the start of the scope of an existential: |>^a
+}
+
+/// The ordered context of the algorithmic system: existentials stand in for the types polymorphic
+/// types are instantiated at, and are solved as checking goes on. Order is scope: an existential
+/// may only be solved with type variables and existentials that came before it.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Ordered {
+    entries: Vec<Entry>,
+    count: usize,
+}
+
+impl Ordered {
+    /// Whether the context is empty: as it is between judgements, every entry being scoped to one.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The position of an existential, solved or not.
+    fn position(&self, id: &Identifier) -> Option<usize> {
+        self.entries.iter().position(|entry| match entry {
+            Entry::Existential(other) | Entry::Solved(other, _) => other == id,
+            _ => false
+        })
+    }
+}
+
+impl Context {
+    fn fresh_existential(&self) -> Identifier {
+        let mut ordered = self.get_ordered().borrow_mut();
+        ordered.count += 1;
+        ordered.count.to_string()
+    }
+
+    /// The solution of an existential, if it has been solved.
+    pub fn solution(&self, id: &Identifier) -> Option<Type> {
+        self.get_ordered().borrow().entries.iter().find_map(|entry| match entry {
+            Entry::Solved(other, kind) if other == id => Some(kind.clone()),
+            _ => None
+        })
+    }
+
+    /// Apply the context to a type: replace every solved existential with its solution.
+    pub fn apply(&self, kind: &Type) -> Type {
+        kind.replace(&|kind| match kind {
+            Type::Existential(id) => self.solution(id).map(|kind| self.apply(&kind)),
+            _ => None
+        })
+    }
+
+    /// Solve an unsolved existential. The solution must be well-formed before it.
+    pub fn solve(&self, id: &Identifier, kind: Type) {
+        let mut ordered = self.get_ordered().borrow_mut();
+        if let Some(i) = ordered.position(id) {
+            ordered.entries[i] = Entry::Solved(id.clone(), kind);
+        }
+    }
+
+    /// Split an unsolved existential into two new ones in scope just before it:
+    /// for the parameter and result of the function it is then solved with.
+    pub fn split(&self, id: &Identifier) -> (Identifier, Identifier) {
+        let (from, to) = (self.fresh_existential(), self.fresh_existential());
+        let mut ordered = self.get_ordered().borrow_mut();
+        let i = ordered.position(id).unwrap_or(ordered.entries.len());
+        ordered.entries.insert(i, Entry::Existential(to.clone()));
+        ordered.entries.insert(i, Entry::Existential(from.clone()));
+        (from, to)
+    }
+
+    /// Run a judgement with a type variable in scope. It, and everything after it, is dropped afterwards.
+    pub fn universal<T>(&self, id: &Identifier, bound: &Type, f: impl FnOnce() -> T) -> T {
+        self.scoped(Entry::Universal(id.clone(), bound.clone()), f)
+    }
+
+    /// The bound of a type variable in scope: the innermost, should it be shadowed.
+    pub fn bound(&self, id: &Identifier) -> Option<Type> {
+        self.get_ordered().borrow().entries.iter().rev().find_map(|entry| match entry {
+            Entry::Universal(other, bound) if other == id => Some(bound.clone()),
+            _ => None
+        })
+    }
+
+    /// Run a judgement with a new existential in scope, under a marker. It, and everything after it,
+    /// is dropped afterwards: existentials instantiating a quantifier do not outlive the judgement.
+    pub fn marked<T>(&self, f: impl FnOnce(Type) -> T) -> T {
+        let id = self.fresh_existential();
+        self.scoped(Entry::Marker(id.clone()), || {
+            self.get_ordered().borrow_mut().entries.push(Entry::Existential(id.clone()));
+            f(Type::Existential(id))
+        })
+    }
+
+    fn scoped<T>(&self, entry: Entry, f: impl FnOnce() -> T) -> T {
+        self.get_ordered().borrow_mut().entries.push(entry.clone());
+        let result = f();
+        let mut ordered = self.get_ordered().borrow_mut();
+        if let Some(i) = ordered.entries.iter().rposition(|other| other == &entry) {
+            ordered.entries.truncate(i);
+        }
+        result
+    }
+
+    /// Run a judgement, unsolving whatever existentials it solved should it fail.
+    pub fn attempt(&self, f: impl FnOnce() -> bool) -> bool {
+        let unsolved = self.get_ordered().borrow().entries.iter().any(|entry| matches!(entry, Entry::Existential(_)));
+        if !unsolved {
+            return f();
+        }
+        let snapshot = self.get_ordered().borrow().clone();
+        let result = f();
+        if !result {
+            *self.get_ordered().borrow_mut() = snapshot;
+        }
+        result
+    }
+
+    /// Whether a type is well-formed before an existential: whether every type variable and existential
+    /// it refers to is in scope before it. Type variables bound outside of the ordered context always are.
+    fn before(&self, id: &Identifier, kind: &Type) -> bool {
+        let ordered = self.get_ordered().borrow();
+        let Some(at) = ordered.position(id) else { return false };
+        kind.free().iter().all(|var| ordered.entries.iter()
+            .position(|entry| matches!(entry, Entry::Universal(other, _) if other == var)).is_none_or(|i| i < at))
+            && existentials(kind).iter().all(|other| ordered.position(other).is_some_and(|i| i < at))
+    }
+
+    /// InstantiateL: solve an existential so that it is a subtype of a type.
+    pub fn instantiate_left(&self, id: &Identifier, kind: &Type) -> bool {
+        match kind {
+            // InstLReach: the later of two existentials is solved with the earlier
+            Type::Existential(other) if self.before(other, &Type::Existential(id.clone())) => {
+                self.solve(other, Type::Existential(id.clone()));
+                true
+            },
+            // InstLSolve
+            kind if monotype(kind) && self.before(id, kind) => {
+                self.solve(id, kind.clone());
+                true
+            },
+            // InstLArr
+            Type::Function(from, to, effects) => {
+                let (is_from, is_to) = self.split(id);
+                self.solve(id, function(&is_from, &is_to, effects.clone()));
+                self.instantiate_right(from, &is_from) && self.instantiate_left(&is_to, &self.apply(to))
+            },
+            // InstLAllR
+            Type::Forall(var, bound, body) => self.universal(var, bound, || self.instantiate_left(id, body)),
+            _ => false
+        }
+    }
+
+    /// InstantiateR: solve an existential so that a type is a subtype of it.
+    pub fn instantiate_right(&self, kind: &Type, id: &Identifier) -> bool {
+        match kind {
+            // InstRReach
+            Type::Existential(other) if self.before(other, &Type::Existential(id.clone())) => {
+                self.solve(other, Type::Existential(id.clone()));
+                true
+            },
+            // InstRSolve
+            kind if monotype(kind) && self.before(id, kind) => {
+                self.solve(id, kind.clone());
+                true
+            },
+            // InstRArr
+            Type::Function(from, to, effects) => {
+                let (of_from, of_to) = self.split(id);
+                self.solve(id, function(&of_from, &of_to, effects.clone()));
+                self.instantiate_left(&of_from, from) && self.instantiate_right(&self.apply(to), &of_to)
+            },
+            // InstRAllL: the quantifier is instantiated with a new existential, within its bound
+            Type::Forall(var, bound, body) => self.marked(|ex|
+                self.instantiate_right(&body.substitute(&HashMap::from([(var.clone(), ex.clone())])), id)
+                    && self.subtype(&ex, bound)),
+            _ => false
+        }
+    }
+
+    /// Apply the context to a type, and quantify over the unsolved existentials in it that are local to a marker:
+    /// they are dropped with it, and nothing constrains them. Existentials before the marker are left alone,
+    /// as they may yet be solved.
+    fn generalize(&self, marker: &Identifier, kind: &Type) -> Type {
+        let kind = self.apply(kind);
+        let mut local: Vec<Identifier> = Vec::new();
+        {
+            let ordered = self.get_ordered().borrow();
+            let start = ordered.entries.iter().position(|entry| entry == &Entry::Marker(marker.clone()));
+            for id in existentials(&kind) {
+                if ordered.position(&id) > start && !local.contains(&id) {
+                    local.push(id);
+                }
+            }
+        }
+        let mut avoid = names(&kind);
+        let vars: Vec<Identifier> = local.iter().map(|_| {
+            let var = Type::fresh(&String::from("a"), &avoid);
+            avoid.push(var.clone());
+            var
+        }).collect();
+        let body = kind.replace(&|kind| match kind {
+            Type::Existential(id) => local.iter().position(|other| other == id).map(|i| Type::Variable(vars[i].clone())),
+            _ => None
+        });
+        vars.into_iter().rev().fold(body, |body, var| Type::Forall(var, Box::new(Type::Empty), Box::new(body)))
+    }
+
+    /// The application judgement: the type of applying a function of some type to an argument.
+    /// Quantifiers are instantiated with new existentials, and unsolved existentials split into functions.
+    pub fn application(&self, kind: &Type, arg: Expression, span: Span) -> Result<Type, TypeError> {
+        match self.apply(kind) {
+            // ∀App: the existential is scoped to the application, and must be within the bound once the
+            // argument is checked. The result is generalized over whatever it leaves unsolved
+            Type::Forall(id, bound, body) => self.marked(|ex| {
+                let result = self.application(&body.substitute(&HashMap::from([(id, ex.clone())])), arg, span)?;
+                match (self.subtype(&ex, &bound), &ex) {
+                    (true, Type::Existential(marker)) => Ok(self.generalize(marker, &result)),
+                    _ => Err(Diagnostic::new(span, TypeError::Mismatch { expected: *bound, found: self.apply(&ex) }))
+                }
+            }),
+            // ^αApp
+            Type::Existential(id) => {
+                let (from, to) = self.split(&id);
+                self.solve(&id, function(&from, &to, Effects::new()));
+                self.check(arg, &Type::Existential(from))?;
+                Ok(self.apply(&Type::Existential(to)))
+            },
+            // →App
+            Type::Function(from, to, _) => {
+                self.check(arg, &from)?;
+                Ok(self.apply(&to))
+            },
+            kind => Err(Diagnostic::new(span, TypeError::NotAFunction(kind)))
+        }
+    }
+}
+
+/// The function type between two existentials.
+pub fn function(from: &Identifier, to: &Identifier, effects: Effects) -> Type {
+    Type::Function(Box::new(Type::Existential(from.clone())), Box::new(Type::Existential(to.clone())), effects)
+}
+
+/// The existentials occurring in a type.
+pub fn existentials(kind: &Type) -> Vec<Identifier> {
+    let result = RefCell::new(Vec::new());
+    // replace visits every part of a type that it is not given a replacement for
+    kind.replace(&|kind| {
+        if let Type::Existential(id) = kind {
+            result.borrow_mut().push(id.clone());
+        }
+        None
+    });
+    result.into_inner()
+}
+
+/// The type variables occurring in a type, free or bound.
+fn names(kind: &Type) -> Vec<Identifier> {
+    let result = RefCell::new(Vec::new());
+    kind.replace(&|kind| {
+        if let Type::Variable(id) | Type::Forall(id, ..) = kind {
+            result.borrow_mut().push(id.clone());
+        }
+        None
+    });
+    result.into_inner()
+}
+
+/// Whether a type is a monotype: whether it has no quantifiers. Existentials are only solved with monotypes.
+fn monotype(kind: &Type) -> bool {
+    let result = Cell::new(true);
+    kind.replace(&|kind| {
+        if let Type::Forall(..) = kind {
+            result.set(false);
+        }
+        None
+    });
+    result.get()
+}
@@ -1,112 +1,624 @@
+use std::cell::RefCell;
 use crate::ast::*;
+use crate::effects::*;
+use crate::error::*;
 use multipeek::multipeek;
 
 /// Parses a lambda-calculus-like language into an AST.
-pub fn parse_lambda(input: &str) -> Result<Expression> {
+pub fn parse_lambda(input: &str) -> Result<Expression, ParseError> {
     // this is kinda awful, i miss my simple nim pegs
     peg::parser! {
-        grammar lambda() for str {
-            rule ident() -> String = i:['a'..='z' | 'A'..='Z' | '0'..='9']+ {
-                i.iter().collect::<String>()
+        grammar lambda(scope: &RefCell<Vec<Identifier>>) for str {
+            // identifiers begin with a letter, so that numbers out of range are not taken for them
+            rule ident() -> String = i:$(['a'..='z' | 'A'..='Z'] ['a'..='z' | 'A'..='Z' | '0'..='9']*) {
+                i.to_string()
             }
-            rule bool() -> Expression = b:$("true" / "false") {
+            // accessed fields may also be the positions of tuple elements
+            rule accessed() -> String = f:$(['a'..='z' | 'A'..='Z' | '0'..='9']+) { f.to_string() }
+            rule bool() -> Term = b:$("true" / "false") {
                 match b {
-                    "true" => Expression::Constant { term: Term::Boolean(true) },
-                    "false" => Expression::Constant { term: Term::Boolean(false) },
-                    _ => Expression::Constant { term: Term::Unit() }
+                    "true" => Term::Boolean(true),
+                    "false" => Term::Boolean(false),
+                    _ => Term::Unit()
                 }
             }
-            rule num() -> Expression = p:"-"? c:['0'..='9']+ {
-                let value = c.iter().collect::<String>().parse::<usize>().unwrap();
-                Expression::Constant {
-                    term: if p.is_some() {
-                        Term::Integer(-isize::try_from(value).unwrap())
-                    } else {
-                        Term::Natural(value)
-                    }
+            // negatives are parsed with their sign, so as to include the least integer
+            rule num() -> Term = p:"-"? c:$(['0'..='9']+) {?
+                match p {
+                    Some(_) => format!("-{}", c).parse::<isize>().map(Term::Integer).or(Err("integer")),
+                    None => c.parse::<usize>().map(Term::Natural).or(Err("natural number"))
                 }
             }
-            rule cons() -> Expression = c:(bool() / num())
+            rule float() -> Term = f:$("-"? ['0'..='9']+ "." ['0'..='9']+) {
+                Term::Float(f.parse::<f32>().unwrap())
+            }
+            // escapes are the same as in lex()
+            rule string() -> Term = "\"" c:(("\\" c:[_] {? escape(c).ok_or("escape sequence") }) / [^ '"' | '\\'])* "\"" {
+                Term::String(c.into_iter().collect())
+            }
+            rule unit() -> Term = "(" " "* ")" {
+                Term::Unit()
+            }
+            rule comma() = " "* "," " "*
+            rule list() -> Term = "[" " "* l:(literal() ** comma()) " "* "]" {
+                Term::List(l)
+            }
+            rule array() -> Term = "#[" " "* l:(literal() ** comma()) " "* "]" {
+                Term::Array(l)
+            }
+            rule field() -> (Identifier, Term) = i:ident() " "* "=" " "* t:literal() {
+                (i, t)
+            }
+            rule structure() -> Term = "{" " "* f:(field() ** comma()) " "* "}" {
+                Term::Struct(f.into_iter().collect())
+            }
+            // at least one comma: (1) is just 1, and (1,) is a tuple
+            rule element() -> (Option<Identifier>, Term) = i:(i:ident() " "* ":" " "* { i })? t:literal() {
+                (i, t)
+            }
+            rule tuple() -> Term
+            = "(" " "* h:element() comma() t:(element() ** comma()) comma()? " "* ")" {
+                let (fields, data): (Vec<_>, Vec<_>) = std::iter::once(h).chain(t).unzip();
+                Term::Tuple(data, fields)
+            }
+            rule literal() -> Term
+            = float() / num() / bool() / string() / unit() / list() / array() / structure() / tuple()
+            rule cons() -> Expression = s:position!() t:literal() e:position!() {
+                Expression::Constant { term: t, span: Span { start: s, end: e } }
+            }
+            // compounds of expressions: compounds of only literals are parsed as constants, by cons()
+            rule item() -> (Option<Identifier>, Expression) = i:(i:ident() " "* ":" " "* { i })? x:expr() {
+                (i, x)
+            }
+            rule aggregate() -> Expression
+            = s:position!() "[" " "* l:(expr() ** comma()) " "* "]" e:position!() {
+                Expression::List { data: l, span: Span { start: s, end: e } }
+            }
+            / s:position!() "#[" " "* l:(expr() ** comma()) " "* "]" e:position!() {
+                Expression::Array { data: l, span: Span { start: s, end: e } }
+            }
+            / s:position!() "{" " "* f:((i:ident() " "* "=" " "* x:expr() { (i, x) }) ** comma()) " "* "}" e:position!() {
+                Expression::Struct { data: f.into_iter().collect(), span: Span { start: s, end: e } }
+            }
+            / s:position!() "(" " "* h:item() comma() t:(item() ** comma()) comma()? " "* ")" e:position!() {
+                let (fields, data) = std::iter::once(h).chain(t).unzip();
+                Expression::Tuple { data, fields, span: Span { start: s, end: e } }
+            }
+            // types are written exactly as they are displayed
             rule primitive() -> Type
-            = k:$("empty" / "unit" / "bool" / "nat" / "int") {
+            = k:$("empty" / "unit" / "bool" / "nat" / "int" / "float" / "str" / "Self" / "⊤" / "⊥")
+              !['a'..='z' | 'A'..='Z' | '0'..='9'] {
                 match k {
-                    "empty" => Type::Empty,
+                    "empty" | "⊤" => Type::Empty,
+                    "⊥" => Type::Error,
                     "unit" => Type::Unit,
                     "bool" => Type::Boolean,
                     "nat" => Type::Natural,
                     "int" => Type::Integer,
+                    "float" => Type::Float,
+                    "str" => Type::String,
+                    "Self" => Type::Oneself,
                     _ => Type::Empty
                 }
             }
-            // fixme: brackets are necessary here
-            rule function() -> Type = "(" f:kind() " "* "->" " "* t:kind() ")" {
-                Type::Function(Box::new(f), Box::new(t))
+            rule label() -> Identifier = i:ident() " "* ":" " "* { i }
+            rule signature() -> Signature
+            = "func" " "+ n:ident() "(" " "* f:kind() " "* ")" " "* ":" " "* t:kind() {
+                Signature { name: n, from: f, to: t }
+            }
+            rule compound() -> Type
+            = "list[" " "* k:kind() " "* "]" { Type::List(Box::new(k)) }
+            / "array[" " "* k:kind() comma() n:$(['0'..='9']+) " "* "]" {?
+                n.parse::<usize>().map(|n| Type::Array(Box::new(k), n)).or(Err("array length"))
+            }
+            / "slice[" " "* k:kind() " "* "]" { Type::Slice(Box::new(k)) }
+            / "union[" " "* k:(kind() ** comma()) " "* "]" { Type::Union(k) }
+            / "struct[" " "* f:((l:label() k:kind() { (l, k) }) ** comma()) " "* "]" {
+                Type::Struct(f.into_iter().collect())
+            }
+            / "<" " "* f:((l:label() k:kind() { (l, k) }) ** comma()) " "* ">" {
+                Type::Variant(f.into_iter().collect())
+            }
+            / "tuple[" " "* f:((l:label()? k:kind() { (k, l) }) ** comma()) " "* "]" {
+                let (data, fields) = f.into_iter().unzip();
+                Type::Tuple(data, fields)
+            }
+            / "interface[" " "* s:(signature() ** comma()) k:(" "+ "for" " "+ k:kind() { k })? " "* "]" {
+                Type::Interface(s, k.map(Box::new))
             }
+            // generic[] is fully generic
+            / "generic[" " "* k:(kind() ** comma()) " "* "]" {
+                Type::Generic(if k.is_empty() { None } else { Some(k) })
+            }
+            // any other name is a type variable, which must be bound by an enclosing quantifier or type abstraction
+            rule variable() -> Identifier = i:$(['a'..='z' | 'A'..='Z'] ['a'..='z' | 'A'..='Z' | '0'..='9']*) {
+                i.to_string()
+            }
+            rule bound_variable() -> Type = i:variable() {?
+                if scope.borrow().contains(&i) { Ok(Type::Variable(i)) } else { Err("type") }
+            }
+            rule enter(ids: &[Identifier]) = {? scope.borrow_mut().extend(ids.iter().cloned()); Ok(()) }
+            rule leave(ids: &[Identifier]) = {? leave(scope, ids); Ok(()) }
+            rule scoped<T>(ids: &[Identifier], r: rule<T>) -> T = enter(ids) x:r()? leave(ids) {? x.ok_or("scope") }
+            rule simple() -> Type = compound() / primitive() / "(" " "* k:kind() " "* ")" { k } / bound_variable()
+            rule effect() -> Effect
+            = "io" { Effect::Io } / "state" { Effect::State } / "exn" { Effect::Exn } / "div" { Effect::Div }
+            // effectful functions list their effects on the arrow: a -[io, exn]-> b
+            rule arrow() -> Effects
+            = "->" { Effects::new() }
+            / "-[" " "* e:(effect() ** comma()) " "* "]->" { e.into_iter().collect() }
+            // the arrow is right-associative: a -> b -> c is a -> (b -> c)
+            // quantifiers extend as far right as possible: forall a. a -> a is forall a. (a -> a)
             rule kind() -> Type
-             = k:(function() / primitive()) {
-                k
+            = "forall" " "+ i:variable() b:bound() " "* "." " "* t:scoped(std::slice::from_ref(&i), <kind()>) {
+                Type::Forall(i, Box::new(b), Box::new(t))
             }
+            / f:simple() " "* a:arrow() " "* t:kind() {
+                Type::Function(Box::new(f), Box::new(t), a)
+            } / simple()
             rule ann() -> Expression
-            = e:(bracketed() / (cond() / abs() / app() / cons() / var())) " "* ":" " "* k:kind() {
+            = s:position!() e:(bracketed() / (letrec() / unpack() / binding() / fix() / cond() / case() / inj() / tabs() / abs() / test() / access() / app() / cons() / pack() / aggregate() / var())) " "* ":" " "* k:kind() f:position!() {
                 Expression::Annotation {
                     expr: Box::new(e),
-                    kind: k
+                    kind: k,
+                    span: Span { start: s, end: f }
                 }
             }
             rule var() -> Expression
-            = v:ident() {
+            = s:position!() v:ident() e:position!() {
                 Expression::Variable {
-                    id: v
+                    id: v,
+                    span: Span { start: s, end: e }
                 }
             }
             rule abs() -> Expression
-            = ("λ" / "lambda ") " "* p:ident() " "* "." " "* f:expr() {
+            = s:position!() ("λ" / "lambda ") " "* p:ident() " "* "." " "* f:expr() e:position!() {
                 Expression::Abstraction {
                     param: p,
-                    func: Box::new(f)
+                    func: Box::new(f),
+                    span: Span { start: s, end: e }
+                }
+            }
+            // type variables are bounded by the empty type, unless otherwise specified: forall a <: T. a
+            rule bound() -> Type = " "* "<:" " "* k:kind() { k } / { Type::Empty }
+            // existential packages: {*int, 3} as interface[func show(Self): str]
+            rule pack() -> Expression
+            = s:position!() "{" " "* "*" " "* w:kind() comma() x:expr() " "* "}" " "+ "as" " "+ k:kind() e:position!() {
+                Expression::Pack {
+                    witness: w,
+                    expr: Box::new(x),
+                    kind: k,
+                    span: Span { start: s, end: e }
+                }
+            }
+            rule unpack() -> Expression
+            = s:position!() "let" " "+ "{" " "* p:ident() comma() i:ident() " "* "}" " "* "=" " "* v:expr() " "+ "in" " "+
+              b:scoped(std::slice::from_ref(&p), <expr()>) e:position!() {
+                Expression::Unpack {
+                    param: p,
+                    id: i,
+                    expr: Box::new(v),
+                    body: Box::new(b),
+                    span: Span { start: s, end: e }
+                }
+            }
+            rule tabs() -> Expression
+            = s:position!() ("Λ" / "Lambda ") " "* p:ident() b:bound() " "* "." " "* f:scoped(std::slice::from_ref(&p), <expr()>) e:position!() {
+                Expression::TypeAbstraction {
+                    param: p,
+                    bound: b,
+                    expr: Box::new(f),
+                    span: Span { start: s, end: e }
                 }
             }
             // fixme: more cases should parse, but how?
             rule app() -> Expression
-            = "(" f:expr() ")" " "* a:expr() {
+            = s:position!() "(" f:expr() ")" " "* a:expr() e:position!() {
                 Expression::Application {
                     func: Box::new(f),
-                    arg: Box::new(a)
+                    arg: Box::new(a),
+                    span: Span { start: s, end: e }
                 }
             }
             rule cond() -> Expression
-            = "if" " "+ c:expr() " "+ "then" " "+ t:expr() " "+ "else" " "+ e:expr() {
+            = s:position!() "if" " "+ c:expr() " "+ "then" " "+ t:expr() " "+ "else" " "+ e:expr() f:position!() {
                 Expression::Conditional {
                     if_cond: Box::new(c),
                     if_then: Box::new(t),
-                    if_else: Box::new(e)
+                    if_else: Box::new(e),
+                    span: Span { start: s, end: f }
+                }
+            }
+            rule binding() -> Expression
+            = s:position!() "let" " "+ i:ident() " "* k:(":" " "* k:kind() " "* { k })? "=" " "* v:expr() " "+ "in" " "+ b:expr() e:position!() {
+                Expression::Let {
+                    id: i,
+                    kind: k,
+                    value: Box::new(v),
+                    body: Box::new(b),
+                    span: Span { start: s, end: e }
+                }
+            }
+            rule fix() -> Expression
+            = s:position!() "fix" " "+ f:expr() e:position!() {
+                Expression::Fix {
+                    func: Box::new(f),
+                    span: Span { start: s, end: e }
                 }
             }
+            // letrec f: T = e1 in e2 is let f: T = fix ((λf. e1): (T -> T)) in e2
+            rule letrec() -> Expression
+            = s:position!() "letrec" " "+ i:ident() " "* ":" " "* k:kind() " "* "=" " "* v:expr() " "+ "in" " "+ b:expr() e:position!() {
+                let span = Span { start: s, end: e };
+                let func = Expression::Annotation {
+                    expr: Box::new(Expression::Abstraction { param: i.clone(), func: Box::new(v), span }),
+                    kind: Type::Function(Box::new(k.clone()), Box::new(k.clone()), Effects::new()),
+                    span
+                };
+                Expression::Let {
+                    id: i,
+                    kind: Some(k),
+                    value: Box::new(Expression::Fix { func: Box::new(func), span }),
+                    body: Box::new(b),
+                    span
+                }
+            }
+            rule pattern() -> Pattern
+            = i:ident() " "* ":" " "* k:kind() { Pattern::Member(i, k) }
+            / "<" " "* l:ident() " "* "=" " "* i:ident() " "* ">" { Pattern::Label(l, i) }
+            rule arm() -> (Pattern, Expression) = p:pattern() " "* "=>" " "* b:expr() { (p, b) }
+            // case e of x: int => e1 | y: bool => e2
+            rule case() -> Expression
+            = s:position!() "case" " "+ c:expr() " "+ "of" " "+ a:(arm() ++ (" "* "|" " "*)) e:position!() {
+                Expression::Case {
+                    expr: Box::new(c),
+                    arms: a,
+                    span: Span { start: s, end: e }
+                }
+            }
+            // x is int: type tests bind tighter than anything but accesses
+            rule test() -> Expression
+            = s:position!() x:(access() / "(" " "* x:expr() " "* ")" { x } / cons() / aggregate() / var()) " "+ "is" " "+ k:kind() e:position!() {
+                Expression::Is {
+                    expr: Box::new(x),
+                    kind: k,
+                    span: Span { start: s, end: e }
+                }
+            }
+            // <some = e>
+            rule inj() -> Expression
+            = s:position!() "<" " "* l:ident() " "* "=" " "* x:expr() " "* ">" e:position!() {
+                Expression::Injection {
+                    label: l,
+                    expr: Box::new(x),
+                    span: Span { start: s, end: e }
+                }
+            }
+            // note: a bare (non-primitive) identifier in brackets is an index: type variables are applied parenthesized, f[(a)]
+            rule accessor() -> Postfix
+            = "." f:accessed() { Postfix::Access(Accessor::Field(f)) }
+            / "[" " "* !(!primitive() ident() " "* "]") k:kind() " "* "]" { Postfix::Apply(k) }
+            / "[" " "* i:expr() " "* "]" { Postfix::Access(Accessor::Index(Box::new(i))) }
+            // s.field, t.0, a[i], id[int]: accessors bind tighter than anything else
+            rule access() -> Expression
+            = s:position!() e:("(" " "* e:expr() " "* ")" { e } / cons() / aggregate() / var())
+              a:(a:accessor() f:position!() { (a, f) })+ {
+                a.into_iter().fold(e, |expr, (at, f)| postfix(expr, at, Span { start: s, end: f }))
+            }
             rule unbracketed() -> Expression
-            = e:(cond() / ann() / abs() / app() / cons() / var()) {
+            = e:(letrec() / unpack() / binding() / fix() / cond() / case() / ann() / inj() / tabs() / abs() / test() / access() / app() / cons() / pack() / aggregate() / var()) {
                 e
             }
             rule bracketed() -> Expression
-            = "(" " "* e:(cond() / ann() / abs() / app() / cons() / var()) " "* ")" {
+            = "(" " "* e:(letrec() / unpack() / binding() / fix() / cond() / case() / ann() / inj() / tabs() / abs() / test() / access() / app() / cons() / pack() / aggregate() / var()) " "* ")" {
                 e
             }
+            // ann() parses a whole subexpression before failing, so without memoizing this is exponential in nesting
+            #[cache]
             pub rule expr() -> Expression
             // what the fuck
             // why doesn't = " "* e:(unbracketed() / bracketed()) " "* work
             = e:(unbracketed() / bracketed()) {
                 e
             }
+            pub rule program() -> Expression = [' ' | '\t' | '\n' | '\r']* e:expr() {
+                e
+            }
         }
     }
-    Ok(lambda::expr(input.trim())?)
+    lambda::program(input.trim_end(), &RefCell::new(Vec::new())).map_err(|e| Diagnostic::new(
+        Span { start: e.location.offset, end: e.location.offset + 1 },
+        ParseError::Expected(e.expected.tokens().collect())))
+}
+
+/// Parses a nim-like whitespace-oriented language into a Program.
+pub fn parse_file(input: &str) -> Result<Program, ParseError> {
+    // peg only parses slices of Copy elements, so we parse over references
+    peg::parser! {
+        grammar file<'a>(spans: &[Span], scope: &RefCell<Vec<Identifier>>) for [&'a Token] {
+            rule keyword(k: &'static str) = [Token::Keyword(x) if x == k]
+            rule separator(s: &'static str) = [Token::Separator(x) if x == s]
+            rule operator(o: &'static str) = [Token::Operator(x) if x == o]
+            rule ident() -> Identifier = [Token::Identifier(i)] { i.clone() }
+            rule word(w: &'static str) = [Token::Identifier(x) if x == w]
+            rule primitive() -> Type = [Token::Identifier(k)] {?
+                match k.as_str() {
+                    "empty" => Ok(Type::Empty),
+                    "unit" => Ok(Type::Unit),
+                    "bool" => Ok(Type::Boolean),
+                    "nat" => Ok(Type::Natural),
+                    "int" => Ok(Type::Integer),
+                    "float" => Ok(Type::Float),
+                    "str" => Ok(Type::String),
+                    "Self" => Ok(Type::Oneself),
+                    _ => Err("primitive type")
+                }
+            }
+            rule label() -> Identifier = i:ident() separator(":") { i }
+            rule signature() -> Signature
+            = keyword("func") n:ident() separator("(") f:kind() separator(")") separator(":") t:kind() {
+                Signature { name: n, from: f, to: t }
+            }
+            rule compound() -> Type
+            = word("list") separator("[") k:kind() separator("]") { Type::List(Box::new(k)) }
+            / word("array") separator("[") k:kind() separator(",") [Token::Value(n)] separator("]") {?
+                n.parse::<usize>().map(|n| Type::Array(Box::new(k), n)).or(Err("array length"))
+            }
+            / word("slice") separator("[") k:kind() separator("]") { Type::Slice(Box::new(k)) }
+            / word("union") separator("[") k:(kind() ** separator(",")) separator("]") { Type::Union(k) }
+            / word("struct") separator("[") f:((l:label() k:kind() { (l, k) }) ** separator(",")) separator("]") {
+                Type::Struct(f.into_iter().collect())
+            }
+            / operator("<") f:((l:label() k:kind() { (l, k) }) ** separator(",")) operator(">") {
+                Type::Variant(f.into_iter().collect())
+            }
+            / word("tuple") separator("[") f:((l:label()? k:kind() { (k, l) }) ** separator(",")) separator("]") {
+                let (data, fields) = f.into_iter().unzip();
+                Type::Tuple(data, fields)
+            }
+            / word("interface") separator("[") s:(signature() ** separator(","))
+              k:(word("for") k:kind() { k })? separator("]") {
+                Type::Interface(s, k.map(Box::new))
+            }
+            / word("generic") separator("[") k:(kind() ** separator(",")) separator("]") {
+                Type::Generic(if k.is_empty() { None } else { Some(k) })
+            }
+            rule effect() -> Effect = [Token::Identifier(e)] {?
+                match e.as_str() {
+                    "io" => Ok(Effect::Io),
+                    "state" => Ok(Effect::State),
+                    "exn" => Ok(Effect::Exn),
+                    "div" => Ok(Effect::Div),
+                    _ => Err("effect")
+                }
+            }
+            rule arrow() -> Effects
+            = operator("->") { Effects::new() }
+            / operator("-") separator("[") e:(effect() ** separator(",")) separator("]") operator("->") {
+                e.into_iter().collect()
+            }
+            rule kind() -> Type
+            = word("forall") i:ident() b:bound() separator(".")
+              enter(std::slice::from_ref(&i)) t:kind()? leave(std::slice::from_ref(&i)) {?
+                t.map(|t| Type::Forall(i, Box::new(b), Box::new(t))).ok_or("type")
+            }
+            / f:simple() a:arrow() t:kind() {
+                Type::Function(Box::new(f), Box::new(t), a)
+            } / simple()
+            // type variables must be bound by an enclosing quantifier or function type parameter
+            rule bound_variable() -> Type = i:ident() {?
+                if scope.borrow().contains(&i) { Ok(Type::Variable(i)) } else { Err("type") }
+            }
+            rule enter(ids: &[Identifier]) = {? scope.borrow_mut().extend(ids.iter().cloned()); Ok(()) }
+            rule leave(ids: &[Identifier]) = {? leave(scope, ids); Ok(()) }
+            rule simple() -> Type
+            = compound() / primitive() / separator("(") k:kind() separator(")") { k } / bound_variable()
+            rule value() -> Expression = s:position!() [Token::Value(v)] {?
+                let span = cover(spans, s, s + 1);
+                match v.as_str() {
+                    "true" => Ok(Expression::Constant { term: Term::Boolean(true), span }),
+                    "false" => Ok(Expression::Constant { term: Term::Boolean(false), span }),
+                    _ if v.contains('.') => v.parse::<f32>().map(|x| Expression::Constant { term: Term::Float(x), span })
+                        .or(Err("float")),
+                    _ => v.parse::<usize>().map(|x| Expression::Constant { term: Term::Natural(x), span })
+                        .or(Err("natural number"))
+                }
+            }
+            rule negative() -> Expression = s:position!() operator("-") [Token::Value(v)] {?
+                let span = cover(spans, s, s + 2);
+                match v.contains('.') {
+                    true => format!("-{}", v).parse::<f32>().map(|x| Expression::Constant { term: Term::Float(x), span })
+                        .or(Err("float")),
+                    false => format!("-{}", v).parse::<isize>().map(|x| Expression::Constant { term: Term::Integer(x), span })
+                        .or(Err("integer"))
+                }
+            }
+            rule string() -> Expression = s:position!() [Token::String(v)] {
+                Expression::Constant { term: Term::String(v.clone()), span: cover(spans, s, s + 1) }
+            }
+            rule var() -> Expression = s:position!() v:ident() {
+                Expression::Variable { id: v, span: cover(spans, s, s + 1) }
+            }
+            rule injection() -> Expression
+            = s:position!() operator("<") l:ident() operator("=") x:expr() operator(">") e:position!() {
+                Expression::Injection { label: l, expr: Box::new(x), span: cover(spans, s, e) }
+            }
+            rule unit() -> Expression = s:position!() separator("(") separator(")") {
+                Expression::Constant { term: Term::Unit(), span: cover(spans, s, s + 2) }
+            }
+            // lists are @[...] and arrays [...], as in nim: tuples need at least one comma
+            rule item() -> (Option<Identifier>, Expression) = i:label()? x:expr() { (i, x) }
+            rule aggregate() -> Expression
+            = s:position!() operator("@") separator("[") l:(expr() ** separator(",")) separator("]") e:position!() {
+                Expression::List { data: l, span: cover(spans, s, e) }
+            }
+            / s:position!() separator("[") l:(expr() ** separator(",")) separator("]") e:position!() {
+                Expression::Array { data: l, span: cover(spans, s, e) }
+            }
+            / s:position!() separator("{") f:((i:ident() operator("=") x:expr() { (i, x) }) ** separator(",")) separator("}") e:position!() {
+                Expression::Struct { data: f.into_iter().collect(), span: cover(spans, s, e) }
+            }
+            / s:position!() separator("(") h:item() separator(",") t:(item() ** separator(",")) separator(",")? separator(")") e:position!() {
+                let (fields, data) = std::iter::once(h).chain(t).unzip();
+                Expression::Tuple { data, fields, span: cover(spans, s, e) }
+            }
+            rule primary() -> Expression
+            = value() / negative() / string() / var() / injection() / unit() / aggregate() / separator("(") e:expr() separator(")") { e }
+            rule field() -> Identifier = ident() / [Token::Value(v)] { v.clone() }
+            rule postfix() -> Postfix
+            = separator("(") a:(expr() ++ separator(",")) separator(")") { Postfix::Call(a) }
+            / separator(".") f:field() { Postfix::Access(Accessor::Field(f)) }
+            / separator("[") !(!primitive() ident() separator("]")) k:kind() separator("]") { Postfix::Apply(k) }
+            / separator("[") i:expr() separator("]") { Postfix::Access(Accessor::Index(Box::new(i))) }
+            // multiple arguments are curried: f(a, b) is ((f a) b)
+            rule call() -> Expression
+            = s:position!() f:primary() p:(p:postfix() e:position!() { (p, e) })* {
+                p.into_iter().fold(f, |expr, (p, e)| postfix(expr, p, cover(spans, s, e)))
+            }
+            rule test() -> Expression = s:position!() x:call() word("is") k:kind() e:position!() {
+                Expression::Is { expr: Box::new(x), kind: k, span: cover(spans, s, e) }
+            }
+            rule expr() -> Expression = test() / call()
+            rule cond() -> Expression
+            = s:position!() keyword("if") c:expr() separator(":") t:block()
+              keyword("else") separator(":") e:block() f:position!() {
+                Expression::Conditional {
+                    if_cond: Box::new(c),
+                    if_then: Box::new(t),
+                    if_else: Box::new(e),
+                    span: cover(spans, s, f)
+                }
+            }
+            rule pattern() -> Pattern
+            = i:ident() separator(":") k:kind() { Pattern::Member(i, k) }
+            / operator("<") l:ident() operator("=") i:ident() operator(">") { Pattern::Label(l, i) }
+            rule arm() -> (Pattern, Expression) = keyword("of") p:pattern() separator(":") b:block() { (p, b) }
+            rule case() -> Expression
+            = s:position!() keyword("case") c:expr() separator(":") [Token::ScopeBegin] a:arm()+ [Token::ScopeEnd] f:position!() {
+                Expression::Case {
+                    expr: Box::new(c),
+                    arms: a,
+                    span: cover(spans, s, f)
+                }
+            }
+            rule stmt() -> Expression = cond() / case() / e:expr() [Token::ExprEnd] { e }
+            rule block() -> Expression = [Token::ScopeBegin] s:stmt() [Token::ScopeEnd] { s } / stmt()
+            // multiple parameters are curried: func f(a, b): A -> B -> C
+            // type parameters are quantified over: func id[a](x): a -> a is of type forall a. a -> a
+            // and may be bounded: func f[a <: T](x): a -> a is of type forall a <: T. a -> a
+            rule bound() -> Type = operator("<") separator(":") k:kind() { k } / { Type::Empty }
+            rule param() -> (Identifier, Type) = i:ident() b:bound() { (i, b) }
+            rule params() -> Vec<(Identifier, Type)>
+            = separator("[") t:(param() ++ separator(",")) separator("]") { t } / { Vec::new() }
+            rule signature_body() -> (Type, Expression) = separator(":") k:kind() operator("=") b:block() { (k, b) }
+            // type parameters are in scope in the function's type and body, but not in their bounds
+            rule function() -> (Identifier, Expression)
+            = s:position!() keyword("func") n:ident() t:params() separator("(") p:(ident() ++ separator(",")) separator(")")
+              enter(&names(&t)) d:signature_body()? leave(&names(&t)) e:position!() {?
+                let (k, b) = d.ok_or("function")?;
+                let span = cover(spans, s, e);
+                let func = p.into_iter().rev().fold(b, |func, param| Expression::Abstraction {
+                    param,
+                    func: Box::new(func),
+                    span
+                });
+                let func = Expression::Annotation { expr: Box::new(func), kind: k, span };
+                Ok((n, t.into_iter().rev().fold(func, |func, (param, bound)| match func {
+                    Expression::Annotation { ref kind, .. } => Expression::Annotation {
+                        kind: Type::Forall(param.clone(), Box::new(bound.clone()), Box::new(kind.clone())),
+                        expr: Box::new(Expression::TypeAbstraction { param, bound, expr: Box::new(func), span }),
+                        span
+                    },
+                    func => func
+                })))
+            }
+            // methods of an implementation take the types of the signatures they implement:
+            // impl interface[func show(Self): str] for int: func show(x) = ...
+            rule method() -> (Identifier, Expression)
+            = s:position!() keyword("func") n:ident() separator("(") p:(ident() ++ separator(",")) separator(")")
+              operator("=") b:block() e:position!() {
+                let span = cover(spans, s, e);
+                (n, p.into_iter().rev().fold(b, |func, param| Expression::Abstraction { param, func: Box::new(func), span }))
+            }
+            rule instance() -> Instance
+            = s:position!() keyword("impl") i:kind() word("for") k:kind() separator(":")
+              [Token::ScopeBegin] m:method()+ [Token::ScopeEnd] e:position!() {
+                Instance { interface: i, kind: k, methods: m, span: cover(spans, s, e) }
+            }
+            rule definition() -> Definition = f:function() { Definition::Function(f) } / i:instance() { Definition::Instance(i) }
+            pub rule program() -> Program = d:definition()* e:stmt()* {
+                let mut program = Program { functions: Vec::new(), instances: Vec::new(), expressions: e };
+                for definition in d {
+                    match definition {
+                        Definition::Function(f) => program.functions.push(f),
+                        Definition::Instance(i) => program.instances.push(i)
+                    }
+                }
+                program
+            }
+        }
+    }
+    let tokens = lex(input)?;
+    let spans: Vec<Span> = tokens.iter().map(|(_, span)| *span).collect();
+    let tokens: Vec<&Token> = tokens.iter().map(|(token, _)| token).collect();
+    file::program(&tokens, &spans, &RefCell::new(Vec::new())).map_err(|e| Diagnostic::new(
+        spans.get(e.location).copied().unwrap_or(Span { start: input.len(), end: input.len() }),
+        ParseError::Expected(e.expected.tokens().collect())))
+}
+
+/// Take type variables out of scope, once the quantifier binding them is parsed.
+fn leave(scope: &RefCell<Vec<Identifier>>, ids: &[Identifier]) {
+    let mut scope = scope.borrow_mut();
+    let len = scope.len() - ids.len();
+    scope.truncate(len);
+}
+
+/// The names of type parameters.
+fn names(params: &[(Identifier, Type)]) -> Vec<Identifier> {
+    params.iter().map(|(id, _)| id.clone()).collect()
+}
+
+/// Top-level definitions of a file: functions and interface implementations, in any order.
+enum Definition {
+    Function((Identifier, Expression)),
+    Instance(Instance),
+}
+
+/// Calls, accesses, and type applications following a primary expression: `f[int](a).field[i]`.
+enum Postfix {
+    Call(Vec<Expression>),
+    Access(Accessor),
+    Apply(Type),
+}
+
+/// Apply a postfix to the expression it follows.
+fn postfix(expr: Expression, postfix: Postfix, span: Span) -> Expression {
+    match postfix {
+        Postfix::Call(args) => args.into_iter().fold(expr, |func, arg| Expression::Application {
+            func: Box::new(func),
+            arg: Box::new(arg),
+            span
+        }),
+        Postfix::Access(at) => Expression::Access { expr: Box::new(expr), at, span },
+        Postfix::Apply(kind) => Expression::TypeApplication { expr: Box::new(expr), kind, span },
+    }
+}
+
+/// The span covering tokens `start` up to (but not including) `end`.
+// note: trailing ExprEnds and ScopeEnds are zero-width and sit on the following line, so skip them
+fn cover(spans: &[Span], start: usize, end: usize) -> Span {
+    let last = spans[start..end.max(start + 1)].iter().rev()
+        .find(|span| span.start != span.end).unwrap_or(&spans[start]);
+    Span { start: spans[start].start, end: last.end }
 }
 
 const operators: [char; 17] =
     ['=', '+', '-', '*', '/', '<', '>', '@', '$', '~', '&', '%', '|', '!', '?', '^', '\\'];
 const brackets: [char; 6] = ['(', ')', '{', '}', '[', ']'];
 const special: [char; 7] = ['.', ',', ':', ';', '`', '\'', '"'];
-const keywords: [&str; 3] = ["if", "else", "func"];
+const keywords: [&str; 6] = ["if", "else", "func", "case", "of", "impl"];
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     Operator(String),
     Keyword(String),
@@ -122,8 +634,11 @@ pub enum Token {
     ExprEnd,    // ;
 }
 
-/// Properly lexes a whitespace-oriented language into a series of tokens.
-pub fn lex(input: &str) -> Result<Vec<Token>> {
+/// Properly lexes a whitespace-oriented language into a series of tokens (and their spans).
+/// Indentation follows the offside rule: a deeper line opens a scope, a line at the same level
+/// ends the previous expression, and a shallower line ends the expression and closes every
+/// scope down to a matching level. Blank lines, comments, and lines within brackets are ignored.
+pub fn lex(input: &str) -> Result<Vec<(Token, Span)>, ParseError> {
     enum State {
         Default,
         Char,
@@ -132,142 +647,147 @@ pub fn lex(input: &str) -> Result<Vec<Token>> {
         Comment,
     }
     struct Indentation {
-        blank: bool,    // is the line entirely whitespace so far?
-        level: usize,   // current indentation level
-        count: usize,   // current whitespace count
+        blank: bool,        // is the line entirely whitespace so far?
+        levels: Vec<usize>, // stack of enclosing indentation levels
+        count: usize,       // current whitespace count
+        depth: usize,       // current bracket depth: newlines within brackets are ignored
     }
 
     let mut state = State::Default;
-    let mut indent = Indentation { blank: true, level: 0, count: 0 };
+    let mut indent = Indentation { blank: true, levels: vec![0], count: 0, depth: 0 };
     let mut buffer = String::new();
+    let mut start = 0; // where the buffer (or string, or char) began
     let mut result = Vec::new();
+    let length = input.len();
 
     // .next() advances the iterator, .peek() does not
-    let mut input = multipeek(input.chars()); // multipeek my beloved
-    while let Some(c) = input.next() {
+    let mut input = multipeek(input.char_indices()); // multipeek my beloved
+    while let Some((i, c)) = input.next() {
+        let here = Span { start: i, end: i + c.len_utf8() };
         match state {
-            State::Default => match c {
-                ' ' if indent.blank => indent.count += 1,
-                ' ' if !buffer.is_empty() => {
-                    result.push(parse_token(&buffer)?);
-                    buffer.clear();
-                },
-                ' ' => {
-                    // hmm
-                    todo!()
-                },
-                '\n' => {
-                    if let Some(previous) = result.last() {
-                        match previous {
-                            // same scope, no seperator
-                            Token::Operator(_) => (),
-                            // do we have this tbh????
-                            Token::Keyword(_) => return Err("lines shouldn't end with a keyword i think".into()),
-                            // i think uhh
-                            Token::Identifier(_) | Token::Value(_) | Token::Char(_) | Token::String(_) =>
-                                result.push(Token::ExprEnd),
-                            // always scope??
-                            Token::Separator(_) => result.push(Token::ScopeBegin),
-                            // idk
-                            Token::Comment(_) | Token::ScopeBegin | Token::ScopeEnd | Token::ExprEnd =>
-                                return Err("uhh idk always scope lol".into()),
-                        }
-                    }
-                    todo!()
-                },
-                '\t' => return Err("Tabs are not supported!".into()),
-                _ if indent.blank => {
+            State::Default => {
+                // the first character of a line: compare against the enclosing indentation
+                if indent.blank && !matches!(c, ' ' | '\n' | '\t' | '#') {
                     indent.blank = false;
-                    // indentation check
-                    todo!();
-                    buffer.push(c);
-                },
-                '\'' => {
-                    result.push(parse_token(&buffer)?);
-                    buffer.clear();
-                    if input.peek_nth(0) == Some(&'\\') || input.peek_nth(1) == Some(&'\'') {
-                        state = State::Char;
-                    } else {
-                        result.push(Token::Separator("'".to_string()));
-                    }
-                },
-                '"' => {
-                    if input.peek_nth(0) == Some(&'\"') && input.peek_nth(1) == Some(&'\"') {
-                        state = State::MultiLineString;
-                        input.next();
-                        input.next();
-                    } else {
-                        state = State::String;
-                    }
-                },
-                '#' => {
-                    state = State::Comment;
-                    result.push(parse_token(&buffer)?);
-                    buffer.clear();
-                },
-                _ if brackets.contains(&c) || special.contains(&c) => {
-                    if !buffer.is_empty() {
-                        result.push(parse_token(&buffer)?);
-                        buffer.clear();
-                    }
-                    result.push(Token::Separator(c.to_string()));
-                    if indent.blank {
-                        indent.blank = false;
+                    let level = *indent.levels.last().unwrap();
+                    let point = Span { start: i, end: i };
+                    if indent.count > level {
+                        if result.is_empty() {
+                            return Err(Diagnostic::new(here, ParseError::UnexpectedIndentation));
+                        }
+                        indent.levels.push(indent.count);
+                        result.push((Token::ScopeBegin, point));
+                    } else if !result.is_empty() {
+                        result.push((Token::ExprEnd, point));
+                        while indent.count < *indent.levels.last().unwrap() {
+                            indent.levels.pop();
+                            result.push((Token::ScopeEnd, point));
+                        }
+                        if indent.count != *indent.levels.last().unwrap() {
+                            return Err(Diagnostic::new(here, ParseError::InconsistentIndentation(indent.count)));
+                        }
                     }
                 }
-                _ => buffer.push(c)
-            },
-            State::Char => match c {
-                '\\' => {
-                    match input.next() {
-                        Some('\\') => result.push(Token::Char('\\')),
-                        Some('0') => result.push(Token::Char('\0')),
-                        Some('n') => result.push(Token::Char('\n')),
-                        Some('r') => result.push(Token::Char('\r')),
-                        Some('t') => result.push(Token::Char('\t')),
-                        Some('\"') => result.push(Token::Char('\"')),
-                        Some('\'') => result.push(Token::Char('\'')),
-                        _ => return Err("Invalid string escape sequence!".into()),
-                    }
-                    state = State::Default;
-                    if input.next() != Some('\'') {
-                        return Err("Invalid character sequence!".into())
+                match c {
+                    ' ' if indent.blank => indent.count += 1,
+                    ' ' => flush(&mut buffer, &mut result, Span { start, end: i })?,
+                    '\n' => {
+                        flush(&mut buffer, &mut result, Span { start, end: i })?;
+                        if indent.depth == 0 {
+                            indent.blank = true;
+                            indent.count = 0;
+                        }
+                    },
+                    '\t' => return Err(Diagnostic::new(here, ParseError::Tab)),
+                    '\'' => {
+                        flush(&mut buffer, &mut result, Span { start, end: i })?;
+                        if input.peek_nth(0).map(|x| x.1) == Some('\\') || input.peek_nth(1).map(|x| x.1) == Some('\'') {
+                            state = State::Char;
+                            start = i;
+                        } else {
+                            result.push((Token::Separator("'".to_string()), here));
+                        }
+                    },
+                    '"' => {
+                        flush(&mut buffer, &mut result, Span { start, end: i })?;
+                        start = i;
+                        if input.peek_nth(0).map(|x| x.1) == Some('\"') && input.peek_nth(1).map(|x| x.1) == Some('\"') {
+                            state = State::MultiLineString;
+                            input.next();
+                            input.next();
+                        } else {
+                            state = State::String;
+                        }
+                    },
+                    '#' => {
+                        flush(&mut buffer, &mut result, Span { start, end: i })?;
+                        state = State::Comment;
+                    },
+                    // decimal points are part of numbers, but not of the positions of nested tuple accesses
+                    '.' if !buffer.is_empty() && buffer.chars().all(|c| c.is_ascii_digit())
+                        && input.peek_nth(0).is_some_and(|x| x.1.is_ascii_digit())
+                        && !matches!(result.last(), Some((Token::Separator(x), _)) if x == ".") => buffer.push(c),
+                    _ if brackets.contains(&c) || special.contains(&c) => {
+                        flush(&mut buffer, &mut result, Span { start, end: i })?;
+                        match c {
+                            '(' | '[' | '{' => indent.depth += 1,
+                            ')' | ']' | '}' if indent.depth == 0 =>
+                                return Err(Diagnostic::new(here, ParseError::UnbalancedBracket(c))),
+                            ')' | ']' | '}' => indent.depth -= 1,
+                            _ => ()
+                        }
+                        result.push((Token::Separator(c.to_string()), here));
+                    },
+                    _ => {
+                        // operators and words may abut: `int->int` is three tokens
+                        // and angle brackets stand alone: `<a: int>->int` is `>` then `->`
+                        if let Some(last) = buffer.chars().last() &&
+                            (operators.contains(&last) != operators.contains(&c) || matches!(last, '<' | '>')) {
+                            flush(&mut buffer, &mut result, Span { start, end: i })?;
+                        }
+                        if buffer.is_empty() {
+                            start = i;
+                        }
+                        buffer.push(c)
                     }
-                },
-                '\'' => {
-                    result.push(Token::Char('\0'));
-                    state = State::Default;
                 }
-                _ => {
-                    result.push(Token::Char(c));
-                    state = State::Default;
-                    if input.next() != Some('\'') {
-                        return Err("Invalid character sequence!".into())
+            },
+            State::Char => {
+                let token = match c {
+                    '\\' => match input.next().and_then(|x| escape(x.1)) {
+                        Some(c) => Token::Char(c),
+                        None => return Err(Diagnostic::new(here, ParseError::InvalidEscape)),
+                    },
+                    '\'' => Token::Char('\0'),
+                    _ => Token::Char(c)
+                };
+                state = State::Default;
+                // the empty character '' has already consumed its closing quote
+                let end = match c {
+                    '\'' => i + 1,
+                    _ => match input.next() {
+                        Some((j, '\'')) => j + 1,
+                        _ => return Err(Diagnostic::new(Span { start, end: i + 1 }, ParseError::InvalidCharacter))
                     }
-                }
+                };
+                result.push((token, Span { start, end }));
             },
             State::String => match c {
-                '\\' => match input.next() {
-                    Some('\\') => buffer.push('\\'),
-                    Some('0') => buffer.push('\0'),
-                    Some('n') => buffer.push('\n'),
-                    Some('r') => buffer.push('\r'),
-                    Some('t') => buffer.push('\t'),
-                    Some('\"') => buffer.push('\"'),
-                    Some('\'') => buffer.push('\''),
-                    _ => return Err("Invalid string escape sequence!".into()),
+                '\\' => match input.next().and_then(|x| escape(x.1)) {
+                    Some(c) => buffer.push(c),
+                    None => return Err(Diagnostic::new(here, ParseError::InvalidEscape)),
                 },
                 '\"' => {
                     state = State::Default;
-                    result.push(Token::String(buffer.to_string()));
+                    result.push((Token::String(buffer.to_string()), Span { start, end: i + 1 }));
                     buffer.clear();
                 }
                 _ => buffer.push(c)
             },
             State::MultiLineString => match c {
-                '\"' if input.peek_nth(0) == Some(&'"') && input.peek_nth(1) == Some(&'"') => {
+                '\"' if input.peek_nth(0).map(|x| x.1) == Some('"') && input.peek_nth(1).map(|x| x.1) == Some('"') => {
                     state = State::Default;
-                    result.push(Token::String(buffer.to_string()));
+                    result.push((Token::String(buffer.to_string()), Span { start, end: i + 3 }));
                     buffer.clear();
                     input.next();
                     input.next();
@@ -279,15 +799,63 @@ pub fn lex(input: &str) -> Result<Vec<Token>> {
                     state = State::Default;
                     // result.push(Token::Comment(buffer.to_string()));
                     buffer.clear();
+                    if indent.depth == 0 {
+                        indent.blank = true;
+                        indent.count = 0;
+                    }
                 },
                 _ => buffer.push(c)
             },
         }
     }
+
+    let point = Span { start: length, end: length };
+    match state {
+        State::Default | State::Comment => (),
+        _ => return Err(Diagnostic::new(Span { start, end: length }, ParseError::UnterminatedLiteral))
+    }
+    if let State::Default = state {
+        flush(&mut buffer, &mut result, Span { start, end: length })?;
+    }
+    if indent.depth != 0 {
+        return Err(Diagnostic::new(point, ParseError::UnclosedBracket));
+    }
+    // close out the last line and every scope still open
+    if !result.is_empty() {
+        result.push((Token::ExprEnd, point));
+    }
+    while indent.levels.len() > 1 {
+        indent.levels.pop();
+        result.push((Token::ScopeEnd, point));
+    }
     Ok(result)
 }
 
-fn parse_token(token: &str) -> Result<Token> {
+/// The character an escape sequence `\\c` stands for, shared by character and string literals.
+fn escape(c: char) -> Option<char> {
+    match c {
+        '\\' => Some('\\'),
+        '0' => Some('\0'),
+        'n' => Some('\n'),
+        'r' => Some('\r'),
+        't' => Some('\t'),
+        '\"' => Some('\"'),
+        '\'' => Some('\''),
+        _ => None
+    }
+}
+
+/// Push the buffer (if any) onto the token stream as a single token.
+fn flush(buffer: &mut String, result: &mut Vec<(Token, Span)>, span: Span) -> Result<(), ParseError> {
+    if !buffer.is_empty() {
+        let token = parse_token(buffer).map_err(|e| Diagnostic::new(span, e))?;
+        result.push((token, span));
+        buffer.clear();
+    }
+    Ok(())
+}
+
+fn parse_token(token: &str) -> core::result::Result<Token, ParseError> {
     if keywords.contains(&token) {
         Ok(Token::Keyword(token.to_string()))
     } else if is_operator(token) {
@@ -297,7 +865,7 @@ fn parse_token(token: &str) -> Result<Token> {
     } else if is_identifier(token) {
         Ok(Token::Identifier(token.to_string()))
     } else {
-        Err("Could not parse token!".into())
+        Err(ParseError::InvalidToken(token.to_string()))
     }
 }
 
@@ -315,13 +883,12 @@ fn is_value(token: &str) -> bool {
         return true;
     }
     // fixme: hex literals etc
-    for c in token.chars() {
-        // note size annotations are separately lexed
-        if !c.is_numeric() {
-            return false;
-        }
+    // note size annotations are separately lexed
+    match token.split_once('.') {
+        Some((whole, fraction)) => !whole.is_empty() && !fraction.is_empty() &&
+            whole.chars().chain(fraction.chars()).all(|c| c.is_numeric()),
+        None => token.chars().all(|c| c.is_numeric())
     }
-    true
 }
 
 fn is_identifier(token: &str) -> bool {
@@ -0,0 +1,129 @@
+use crate::ast::*;
+use crate::effects::*;
+use crate::error::*;
+
+/// Built-in operations. These are implemented natively and registered in a context by signature:
+/// multiple parameters are curried, i.e. `add: int -> int -> int`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Primitive {
+    Add, Sub, Mul, Div, Neg,        // arithmetic: overloaded on nat, int, float
+    Eq, Lt, Le, Gt, Ge,             // comparison: eq is also defined on bool and str
+    Not, And, Or,                   // logic
+    Print,                          // io: writes a string to stdout
+}
+
+const arithmetic: [(&str, Primitive); 4] =
+    [("add", Primitive::Add), ("sub", Primitive::Sub), ("mul", Primitive::Mul), ("div", Primitive::Div)];
+const comparison: [(&str, Primitive); 5] =
+    [("eq", Primitive::Eq), ("lt", Primitive::Lt), ("le", Primitive::Le), ("gt", Primitive::Gt), ("ge", Primitive::Ge)];
+
+impl Primitive {
+    /// Every primitive, with the signatures it implements.
+    pub fn signatures() -> Vec<(Signature, Primitive)> {
+        let binary = |name: &str, from: &Type, to: &Type| Signature {
+            name: name.to_string(),
+            from: from.clone(),
+            to: Type::Function(Box::new(from.clone()), Box::new(to.clone()), Effects::new())
+        };
+        let mut result = Vec::new();
+        for kind in [Type::Natural, Type::Integer, Type::Float] {
+            for (name, op) in arithmetic {
+                result.push((binary(name, &kind, &kind), op));
+            }
+            for (name, op) in comparison {
+                result.push((binary(name, &kind, &Type::Boolean), op));
+            }
+        }
+        for kind in [Type::Integer, Type::Float] {
+            result.push((Signature { name: "neg".to_string(), from: kind.clone(), to: kind }, Primitive::Neg));
+        }
+        for kind in [Type::Boolean, Type::String] {
+            result.push((binary("eq", &kind, &Type::Boolean), Primitive::Eq));
+        }
+        result.push((Signature { name: "not".to_string(), from: Type::Boolean, to: Type::Boolean }, Primitive::Not));
+        result.push((binary("and", &Type::Boolean, &Type::Boolean), Primitive::And));
+        result.push((binary("or", &Type::Boolean, &Type::Boolean), Primitive::Or));
+        result.push((Signature { name: "print".to_string(), from: Type::String, to: Type::Unit }, Primitive::Print));
+        result
+    }
+
+    /// The number of (curried) arguments the primitive takes.
+    pub fn arity(&self) -> usize {
+        match self {
+            Primitive::Neg | Primitive::Not | Primitive::Print => 1,
+            _ => 2
+        }
+    }
+
+    /// The effects of (fully) applying the primitive.
+    pub fn effects(&self) -> Effects {
+        match self {
+            Primitive::Print => Effects::from([Effect::Io]),
+            Primitive::Div => Effects::from([Effect::Exn]),
+            _ => Effects::new()
+        }
+    }
+
+    /// Natively evaluate the primitive. Arguments must already be of the signature's types.
+    pub fn apply(&self, args: &[Term]) -> core::result::Result<Term, RuntimeError> {
+        use Term::*;
+        match (self, args) {
+            (Primitive::Neg, [Integer(x)]) => x.checked_neg().map(Integer).ok_or(RuntimeError::Overflow),
+            (Primitive::Neg, [Float(x)]) => Ok(Float(-x)),
+            (Primitive::Not, [Boolean(x)]) => Ok(Boolean(!x)),
+            (Primitive::And, [Boolean(x), Boolean(y)]) => Ok(Boolean(*x && *y)),
+            (Primitive::Or, [Boolean(x), Boolean(y)]) => Ok(Boolean(*x || *y)),
+            (Primitive::Print, [String(x)]) => {
+                println!("{}", x);
+                Ok(Unit())
+            },
+
+            (Primitive::Div, [Natural(_), Natural(0)]) | (Primitive::Div, [Integer(_), Integer(0)]) =>
+                Err(RuntimeError::DivisionByZero),
+            (Primitive::Add, [Natural(x), Natural(y)]) => x.checked_add(*y).map(Natural).ok_or(RuntimeError::Overflow),
+            (Primitive::Sub, [Natural(x), Natural(y)]) => x.checked_sub(*y).map(Natural).ok_or(RuntimeError::Overflow),
+            (Primitive::Mul, [Natural(x), Natural(y)]) => x.checked_mul(*y).map(Natural).ok_or(RuntimeError::Overflow),
+            (Primitive::Div, [Natural(x), Natural(y)]) => Ok(Natural(x / y)),
+            (Primitive::Add, [Integer(x), Integer(y)]) => x.checked_add(*y).map(Integer).ok_or(RuntimeError::Overflow),
+            (Primitive::Sub, [Integer(x), Integer(y)]) => x.checked_sub(*y).map(Integer).ok_or(RuntimeError::Overflow),
+            (Primitive::Mul, [Integer(x), Integer(y)]) => x.checked_mul(*y).map(Integer).ok_or(RuntimeError::Overflow),
+            (Primitive::Div, [Integer(x), Integer(y)]) => x.checked_div(*y).map(Integer).ok_or(RuntimeError::Overflow),
+            (Primitive::Add, [Float(x), Float(y)]) => Ok(Float(x + y)),
+            (Primitive::Sub, [Float(x), Float(y)]) => Ok(Float(x - y)),
+            (Primitive::Mul, [Float(x), Float(y)]) => Ok(Float(x * y)),
+            (Primitive::Div, [Float(x), Float(y)]) => Ok(Float(x / y)),
+
+            (Primitive::Eq, [x, y]) => Ok(Boolean(x == y)),
+            (Primitive::Lt, [x, y]) => compare(x, y).map(|x| Boolean(x.is_lt())),
+            (Primitive::Le, [x, y]) => compare(x, y).map(|x| Boolean(x.is_le())),
+            (Primitive::Gt, [x, y]) => compare(x, y).map(|x| Boolean(x.is_gt())),
+            (Primitive::Ge, [x, y]) => compare(x, y).map(|x| Boolean(x.is_ge())),
+            _ => Err(RuntimeError::InvalidArguments(*self, args.to_vec()))
+        }
+    }
+}
+
+/// Order two numbers of the same type.
+fn compare(x: &Term, y: &Term) -> core::result::Result<std::cmp::Ordering, RuntimeError> {
+    match (x, y) {
+        (Term::Natural(x), Term::Natural(y)) => Ok(x.cmp(y)),
+        (Term::Integer(x), Term::Integer(y)) => Ok(x.cmp(y)),
+        (Term::Float(x), Term::Float(y)) => x.partial_cmp(y).ok_or(RuntimeError::Unordered),
+        _ => Err(RuntimeError::Unordered)
+    }
+}
+
+impl Term {
+    /// Coerce a term to a supertype for native evaluation: naturals are integers,
+    /// and packages are opened unless an interface is expected.
+    pub fn coerce(self, kind: &Type) -> Term {
+        match (self, kind) {
+            (Term::Package(_, term), kind) if !matches!(kind, Type::Interface(..)) => term.coerce(kind),
+            (Term::Natural(x), Type::Integer) => match isize::try_from(x) {
+                Ok(x) => Term::Integer(x),
+                Err(_) => Term::Natural(x)
+            },
+            (term, _) => term
+        }
+    }
+}
@@ -1,33 +1,189 @@
+use std::collections::HashMap;
 use crate::ast::*;
+use crate::bidirectional::uncurry;
+use crate::error::*;
 
 impl Context {
     /// Evaluates an expression given a context (of variables) to a term, or fails.
-    pub fn execute(&self, expression: Expression) -> Result<Term> {
+    pub fn execute(&self, expression: Expression) -> Result<Term, RuntimeError> {
+        let span = expression.span();
         match expression {
-            Expression::Annotation { expr, .. } => self.execute(*expr),
-            Expression::Constant { term } => Ok(term),
-            Expression::Variable { id } => match self.get_term(&id) {
+            // annotations give values their static types, so that overloads resolve as they were checked:
+            // annotated abstractions coerce their parameters and results, and annotated naturals may be integers
+            Expression::Annotation { expr, kind, .. } => match (*expr, kind) {
+                (Expression::Abstraction { param, func, span }, Type::Function(from, to, _)) => {
+                    let value = Expression::Annotation { expr: Box::new(Expression::Variable { id: param.clone(), span }), kind: *from, span };
+                    let body = Expression::Annotation { expr: func, kind: *to, span };
+                    let func = Expression::Let { id: param.clone(), kind: None, value: Box::new(value), body: Box::new(body), span };
+                    Ok(Term::Closure { param, func: Box::new(func), env: self.get_terms().clone() })
+                },
+                (expr, kind) => match self.execute(expr)? {
+                    term @ Term::Natural(_) => Ok(term.coerce(&kind)),
+                    term => Ok(term)
+                }
+            },
+            Expression::Constant { term, .. } => Ok(term),
+            Expression::Variable { id, .. } => match self.get_term(&id) {
                 Some(term) => Ok(term.clone()),
-                None => Err(format!("no such variable in context {self:?}").into())
+                None if self.overloaded(&Expression::Variable { id: id.clone(), span }).is_some() =>
+                    Err(Diagnostic::new(span, RuntimeError::Unsaturated(id))),
+                None => Err(Diagnostic::new(span, RuntimeError::Unbound(id)))
+            },
+            // abstractions capture the environment they are created in
+            Expression::Abstraction { param, func, .. } =>
+                Ok(Term::Closure { param, func, env: self.get_terms().clone() }),
+            // overloaded signatures are resolved by the runtime types of all their arguments
+            Expression::Application { func, arg, span } if self.overloaded(func.head()).is_some() => {
+                let (head, args) = Expression::Application { func, arg, span }.spine();
+                let id = self.overloaded(&head).unwrap();
+                let mut terms = Vec::new();
+                for arg in args {
+                    terms.push(self.execute(arg)?);
+                }
+                let kinds: Option<Vec<Type>> = terms.iter().map(|term| self.convert(term).ok()).collect();
+                let sig = match kinds.and_then(|kinds| self.resolve(&id, &kinds)) {
+                    Some(sig) => sig,
+                    None => return Err(Diagnostic::new(span, RuntimeError::NoOverload(id, terms)))
+                };
+                let (params, _) = uncurry(&sig.kind(), terms.len()).unwrap();
+                let terms: Vec<Term> = std::iter::zip(terms, &params).map(|(term, kind)| term.coerce(kind)).collect();
+                match self.get_func(&sig) {
+                    Some(Implementation::Primitive(op)) if op.arity() == terms.len() =>
+                        op.apply(&terms).map_err(|e| Diagnostic::new(span, e)),
+                    Some(Implementation::Primitive(_)) => Err(Diagnostic::new(span, RuntimeError::Unsaturated(id))),
+                    Some(Implementation::Defined(func)) => self.execute(terms.into_iter().fold(func.clone(),
+                        |func, term| Expression::Application {
+                            func: Box::new(func),
+                            arg: Box::new(Expression::Constant { term, span }),
+                            span
+                        })),
+                    Some(Implementation::Bound) | None => Err(Diagnostic::new(span, RuntimeError::NoOverload(id, terms)))
+                }
             },
-            Expression::Abstraction { param, func } =>
-                Err(format!("attempting to execute an abstraction ({}){}", param, func).into()),
-            Expression::Application { func, arg } => match *func {
-                Expression::Abstraction { param, func } => {
+            Expression::Application { func, arg, .. } => match self.execute(*func)? {
+                Term::Closure { param, func, env } => {
                     let value = self.execute(*arg)?;
                     let mut context = self.clone();
+                    context.set_terms(env);
                     context.insert_term(param, value);
-                    return context.execute(*func);
-                }
-                _ => Err(format!("attempting to execute an application to non-abstraction {}", *func).into())
+                    context.execute(*func)
+                },
+                term => Err(Diagnostic::new(span, RuntimeError::NotAFunction(term)))
             },
-            Expression::Conditional { if_cond, if_then, if_else } => {
+            // variables narrowed by a type test are unwrapped, as they would be by a case
+            Expression::Conditional { if_cond, if_then, if_else, .. } => {
+                let cond_span = if_cond.span();
+                let mut context = self.clone();
+                if let Expression::Is { expr, .. } = &*if_cond && let Expression::Variable { id, .. } = &**expr
+                    && let Some(Term::Union(data)) = self.get_term(id) {
+                    context.insert_term(id.clone(), *data.clone());
+                }
                 match self.execute(*if_cond)? {
-                    Term::Boolean(true) => self.execute(*if_then),
-                    Term::Boolean(false) => self.execute(*if_else),
-                    term => Err(format!("invalid type {} for a conditional", &term.convert()?).into())
+                    Term::Boolean(true) => context.execute(*if_then),
+                    Term::Boolean(false) => context.execute(*if_else),
+                    term => Err(Diagnostic::new(cond_span, RuntimeError::NotABoolean(term)))
+                }
+            },
+            Expression::Let { id, kind, value, body, .. } => {
+                let value = match (self.execute(*value)?, kind) {
+                    (term @ Term::Natural(_), Some(kind)) => term.coerce(&kind),
+                    (term, _) => term
+                };
+                let mut context = self.clone();
+                context.insert_term(id, value);
+                context.execute(*body)
+            },
+            Expression::Access { expr, at: Accessor::Field(field), .. } => match self.execute(*expr)? {
+                Term::Struct(data) if data.contains_key(&field) => Ok(data.get(&field).unwrap().clone()),
+                Term::Tuple(data, labels) if let Some(i) = Accessor::position(&field, &labels) => Ok(data[i].clone()),
+                term => Err(Diagnostic::new(span, RuntimeError::NoField(term, field)))
+            },
+            Expression::Access { expr, at: Accessor::Index(index), .. } => {
+                let index_span = index.span();
+                let data = match self.execute(*expr)? {
+                    Term::List(data) | Term::Array(data) => data,
+                    term => return Err(Diagnostic::new(span, RuntimeError::NotIndexable(term)))
+                };
+                match self.execute(*index)? {
+                    Term::Natural(i) if i < data.len() => Ok(data[i].clone()),
+                    Term::Natural(i) => Err(Diagnostic::new(index_span, RuntimeError::OutOfBounds { index: i, len: data.len() })),
+                    term => Err(Diagnostic::new(index_span, RuntimeError::InvalidIndex(term)))
+                }
+            },
+            // the type of a value is the type of its (unwrapped) term: closures are of no type
+            Expression::Is { expr, kind, .. } => {
+                let term = match self.execute(*expr)? {
+                    Term::Union(data) => *data,
+                    term => term
+                };
+                Ok(Term::Boolean(self.convert(&term).is_ok_and(|term| self.subtype(&term, &kind))))
+            },
+            Expression::Injection { label, expr, .. } => Ok(Term::Variant(label, Box::new(self.execute(*expr)?))),
+            // types are erased: type abstractions are evaluated as their bodies
+            Expression::TypeAbstraction { expr, .. } | Expression::TypeApplication { expr, .. } => self.execute(*expr),
+            // packages carry their witness: the signatures of which method calls are dispatched to
+            // note: packages are not nested, repacking keeps the original witness
+            Expression::Pack { witness, expr, .. } => match self.execute(*expr)? {
+                term @ Term::Package(..) => Ok(term),
+                term => Ok(Term::Package(witness, Box::new(term)))
+            },
+            // unpacked packages are left packed, to be dispatched on
+            Expression::Unpack { id, expr, body, .. } => {
+                let value = self.execute(*expr)?;
+                let mut context = self.clone();
+                context.insert_term(id, value);
+                context.execute(*body)
+            },
+            // compounds are evaluated left to right
+            Expression::List { data, .. } =>
+                data.into_iter().map(|expr| self.execute(expr)).collect::<Result<_, _>>().map(Term::List),
+            Expression::Array { data, .. } =>
+                data.into_iter().map(|expr| self.execute(expr)).collect::<Result<_, _>>().map(Term::Array),
+            Expression::Struct { data, .. } => data.into_iter().map(|(key, expr)| self.execute(expr).map(|term| (key, term)))
+                .collect::<Result<_, _>>().map(Term::Struct),
+            Expression::Tuple { data, fields, .. } => data.into_iter().map(|expr| self.execute(expr))
+                .collect::<Result<_, _>>().map(|data| Term::Tuple(data, fields)),
+            // arms are tried in order: the first whose type (or label) the value is of is taken
+            Expression::Case { expr, arms, .. } => {
+                let term = match self.execute(*expr)? {
+                    Term::Union(data) => *data,
+                    term => term
+                };
+                let kind = self.convert(&term).ok();
+                for (pattern, body) in arms {
+                    let value = match (pattern, &term) {
+                        (Pattern::Member(id, of), term) if kind.as_ref().is_some_and(|kind| self.subtype(kind, &of)) =>
+                            (id, term.clone()),
+                        (Pattern::Label(label, id), Term::Variant(tag, data)) if &label == tag => (id, *data.clone()),
+                        _ => continue
+                    };
+                    let mut context = self.clone();
+                    context.insert_term(value.0, value.1);
+                    return context.execute(body);
                 }
-            }
+                Err(Diagnostic::new(span, RuntimeError::NoMatch(term)))
+            },
+            // fixpoints are of functions, and taken in the environment they are defined in: fix (λf. t) is t
+            // with f bound to a closure taking the fixpoint again once applied, i.e. fix g = g (λx. (fix g) x)
+            Expression::Fix { func, .. } => match self.execute(*func)? {
+                Term::Closure { param, func, env } => {
+                    let (id, arg) = (String::from("f"), String::from("x"));
+                    let fix = Term::Closure {
+                        param: arg.clone(),
+                        func: Box::new(Expression::Application {
+                            func: Box::new(Expression::Fix { func: Box::new(Expression::Variable { id: id.clone(), span }), span }),
+                            arg: Box::new(Expression::Variable { id: arg, span }),
+                            span
+                        }),
+                        env: HashMap::from([(id, Term::Closure { param: param.clone(), func: func.clone(), env: env.clone() })])
+                    };
+                    let mut context = self.clone();
+                    context.set_terms(env);
+                    context.insert_term(param, fix);
+                    context.execute(*func)
+                },
+                term => Err(Diagnostic::new(span, RuntimeError::NotAFunction(term)))
+            },
         }
     }
 }
@@ -0,0 +1,451 @@
+use std::collections::HashMap;
+use crate::ast::*;
+use crate::bidirectional::uncurry;
+use crate::effects::*;
+use crate::error::*;
+
+/// The type systems expressions can be checked and inferred with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Engine {
+    #[default]
+    Bidirectional,  // abstractions need annotations: see `bidirectional`
+    Unification,    // Hindley-Milner: principal types without annotations
+}
+
+/// A polymorphic type: the variables are universally quantified.
+#[derive(Debug, Clone)]
+struct Scheme {
+    vars: Vec<Identifier>,
+    kind: Type
+}
+
+/// The variables bound by abstractions and lets, to their (possibly polymorphic) types.
+type Environment = HashMap<Identifier, Scheme>;
+
+/// The state of an inference: the substitution solved for so far, a source of fresh variables,
+/// and the effects of the function body being inferred.
+// note: constraints are solved as soon as they are generated, i.e. algorithm J
+// note: there are no effect variables: functions of unknown type are assumed to be pure
+struct Unifier<'a> {
+    context: &'a Context,
+    substitution: HashMap<Identifier, Type>,
+    count: usize,
+    effects: Effects
+}
+
+impl Context {
+    /// Checking judgement, by the chosen engine.
+    pub fn check_with(&self, engine: Engine, expression: Expression, target: &Type) -> Result<(), TypeError> {
+        match engine {
+            Engine::Bidirectional => self.check(expression, target),
+            Engine::Unification => {
+                let span = expression.span();
+                let mut unifier = Unifier::new(self);
+                let result = unifier.infer(&Environment::new(), expression)?;
+                unifier.constrain(&result, target).map_err(|e| Diagnostic::new(span, e))
+            }
+        }
+    }
+
+    /// Inference judgement, by the chosen engine.
+    pub fn infer_with(&self, engine: Engine, expression: Expression) -> Result<Type, TypeError> {
+        match engine {
+            Engine::Bidirectional => self.infer(expression),
+            Engine::Unification => self.principal(expression)
+        }
+    }
+
+    /// Infer the principal type of an expression by unification: every other type the expression
+    /// could be given is an instance of it. Unconstrained types are left as variables `a`, `b`, ...
+    pub fn principal(&self, expression: Expression) -> Result<Type, TypeError> {
+        let mut unifier = Unifier::new(self);
+        let result = unifier.infer(&Environment::new(), expression)?;
+        Ok(normalize(&unifier.apply(&result)))
+    }
+}
+
+impl<'a> Unifier<'a> {
+    fn new(context: &'a Context) -> Self {
+        Unifier { context, substitution: HashMap::new(), count: 0, effects: Effects::new() }
+    }
+
+    /// A type variable distinct from every other.
+    // note: the quote keeps these from colliding with user-written names
+    fn fresh(&mut self) -> Type {
+        self.count += 1;
+        Type::Variable(format!("'{}", self.count))
+    }
+
+    /// Instantiate the outermost quantifiers of a type with fresh variables.
+    // note: bounds are not tracked by unification
+    fn specialize(&mut self, kind: Type) -> Type {
+        match kind {
+            Type::Forall(id, _, body) => {
+                let body = body.substitute(&HashMap::from([(id, self.fresh())]));
+                self.specialize(body)
+            },
+            kind => kind
+        }
+    }
+
+    /// Apply the substitution so far to a type, until only unsolved variables remain.
+    // note: only the variables of the type are substituted, lest quantifiers be renamed needlessly
+    fn apply(&self, kind: &Type) -> Type {
+        let free = kind.free();
+        let map: HashMap<Identifier, Type> = self.substitution.iter().filter(|(id, _)| free.contains(id))
+            .map(|(id, kind)| (id.clone(), kind.clone())).collect();
+        let result = kind.substitute(&map);
+        match result == *kind {
+            true => result,
+            false => self.apply(&result)
+        }
+    }
+
+    /// Solve the constraint that `is` may be used where `of` is expected.
+    /// Fresh variables unify with anything (but themselves, infinitely): otherwise types must be subtypes.
+    /// Variables written in types are rigid: they are equal only to themselves.
+    fn constrain(&mut self, is: &Type, of: &Type) -> core::result::Result<(), TypeError> {
+        let (is, of) = (self.apply(is), self.apply(of));
+        match (&is, &of) {
+            (Type::Variable(a), Type::Variable(b)) if a == b => Ok(()),
+            (Type::Variable(id), kind) if flexible(id) => self.solve(id, kind),
+            (kind, Type::Variable(id)) if flexible(id) => self.solve(id, kind),
+            // polymorphic types are checked against with their variables rigid, and used at fresh instances
+            (is, Type::Forall(_, _, body)) => self.constrain(is, body),
+            (Type::Forall(..), of) => {
+                let is = self.specialize(is.clone());
+                self.constrain(&is, of)
+            },
+            (Type::Function(is_from, is_to, is_effects), Type::Function(of_from, of_to, of_effects)) => {
+                self.constrain(of_from, is_from)?;
+                self.constrain(is_to, of_to)?;
+                match is_effects.is_subset(of_effects) {
+                    true => Ok(()),
+                    false => Err(TypeError::Mismatch { expected: of, found: is })
+                }
+            },
+            (Type::List(is), Type::List(of)) | (Type::Slice(is), Type::Slice(of)) |
+            (Type::List(is), Type::Slice(of)) | (Type::Array(is, _), Type::Slice(of)) => self.constrain(is, of),
+            (Type::Array(is_data, is_size), Type::Array(of_data, of_size)) if is_size == of_size =>
+                self.constrain(is_data, of_data),
+            (Type::Tuple(is_data, is_fields), Type::Tuple(of_data, of_fields))
+                if is_data.len() == of_data.len() && is_fields == of_fields => {
+                for (is, of) in std::iter::zip(is_data, of_data) {
+                    self.constrain(is, of)?;
+                }
+                Ok(())
+            },
+            (Type::Struct(is_data), Type::Struct(of_data)) if of_data.keys().all(|key| is_data.contains_key(key)) => {
+                for (key, of) in of_data {
+                    self.constrain(&is_data[key], of)?;
+                }
+                Ok(())
+            },
+            (Type::Variant(is_data), Type::Variant(of_data)) if is_data.keys().all(|key| of_data.contains_key(key)) => {
+                for (key, is) in is_data {
+                    self.constrain(is, &of_data[key])?;
+                }
+                Ok(())
+            },
+            _ => match self.context.subtype(&is, &of) {
+                true => Ok(()),
+                false => Err(TypeError::Mismatch { expected: of, found: is })
+            }
+        }
+    }
+
+    /// Solve a fresh variable.
+    fn solve(&mut self, id: &Identifier, kind: &Type) -> core::result::Result<(), TypeError> {
+        match kind.free().contains(id) {
+            true => Err(TypeError::Infinite(id.clone(), kind.clone())),
+            false => {
+                self.substitution.insert(id.clone(), kind.clone());
+                Ok(())
+            }
+        }
+    }
+
+    /// Quantify over the variables of a type that are not bound in the environment.
+    fn generalize(&self, env: &Environment, kind: &Type) -> Scheme {
+        let kind = self.apply(kind);
+        let bound: Vec<Identifier> = env.values()
+            .flat_map(|scheme| self.apply(&scheme.kind).free().into_iter()
+                .filter(|id| !scheme.vars.contains(id)).collect::<Vec<_>>())
+            .collect();
+        let vars = kind.free().into_iter().filter(|id| flexible(id) && !bound.contains(id)).collect();
+        Scheme { vars, kind }
+    }
+
+    /// Replace the quantified variables of a scheme with fresh ones.
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let map: HashMap<Identifier, Type> = scheme.vars.iter().map(|id| (id.clone(), self.fresh())).collect();
+        scheme.kind.substitute(&map)
+    }
+
+    /// The one type every element of a list or array is of: empty lists are of any element type.
+    fn elements(&mut self, env: &Environment, data: Vec<Expression>) -> Result<Type, TypeError> {
+        let result = self.fresh();
+        for expr in data {
+            let span = expr.span();
+            let kind = self.infer(env, expr)?;
+            self.constrain(&kind, &result).map_err(|e| Diagnostic::new(span, e))?;
+        }
+        Ok(result)
+    }
+
+    /// The type of an abstraction over a parameter of the given type:
+    /// the effects of the body are latent in it.
+    fn abstraction(&mut self, env: &Environment, param: Identifier, from: Type, body: Expression) -> Result<Type, TypeError> {
+        let mut env = env.clone();
+        env.insert(param, Scheme { vars: Vec::new(), kind: from.clone() });
+        let outer = std::mem::take(&mut self.effects);
+        let to = self.infer(&env, body);
+        let effects = std::mem::replace(&mut self.effects, outer);
+        Ok(Type::Function(Box::new(from), Box::new(to?), effects))
+    }
+
+    /// Generate (and solve) the constraints on the type of an expression.
+    fn infer(&mut self, env: &Environment, expression: Expression) -> Result<Type, TypeError> {
+        let span = expression.span();
+        match expression {
+            // the rigid variables of a polymorphic annotation must not escape into the environment
+            // annotated abstractions take their parameters at the annotated type
+            Expression::Annotation { expr, kind, .. } => {
+                let result = match (*expr, &kind) {
+                    (Expression::Abstraction { param, func, .. }, Type::Function(from, ..)) =>
+                        self.abstraction(env, param, *from.clone(), *func)?,
+                    (expr, _) => self.infer(env, expr)?
+                };
+                self.constrain(&result, &kind).map_err(|e| Diagnostic::new(span, e))?;
+                let mut quantified = Vec::new();
+                let mut body = &kind;
+                while let Type::Forall(id, _, inner) = body {
+                    quantified.push(id);
+                    body = inner;
+                }
+                match env.values().any(|scheme| self.apply(&scheme.kind).free().iter().any(|id| quantified.contains(&id))) {
+                    true => Err(Diagnostic::new(span, TypeError::Mismatch { expected: kind, found: self.apply(&result) })),
+                    false => Ok(kind)
+                }
+            },
+            // empty lists and arrays are of any element type
+            Expression::Constant { term: Term::List(data), .. } if data.is_empty() => Ok(Type::List(Box::new(self.fresh()))),
+            Expression::Constant { term: Term::Array(data), .. } if data.is_empty() => Ok(Type::Array(Box::new(self.fresh()), 0)),
+            Expression::Constant { term, .. } => self.context.convert(&term).map_err(|e| Diagnostic::new(span, e)),
+            Expression::Variable { id, .. } => match env.get(&id) {
+                Some(scheme) => Ok(self.instantiate(scheme)),
+                None => self.context.infer(Expression::Variable { id, span })
+            },
+            // overloaded signatures are resolved once the types of all their arguments are known
+            Expression::Application { func, arg, span } if let Some(id) = self.context.overloaded(func.head())
+                && !env.contains_key(&id) && self.context.get_sigs(&id).len() > 1 => {
+                let (_, args) = Expression::Application { func, arg, span }.spine();
+                let mut kinds = Vec::new();
+                for arg in args {
+                    let kind = self.infer(env, arg)?;
+                    kinds.push(self.apply(&kind));
+                }
+                if kinds.iter().any(|kind| !kind.free().is_empty()) {
+                    return Err(Diagnostic::new(span, TypeError::Ambiguous(id)));
+                }
+                match self.context.resolve(&id, &kinds) {
+                    Some(sig) => {
+                        let kind = self.context.signature(&sig);
+                        self.effects.extend(latent(&kind, kinds.len()));
+                        Ok(uncurry(&kind, kinds.len()).unwrap().1)
+                    },
+                    None => Err(Diagnostic::new(span, TypeError::NoOverload(id, kinds)))
+                }
+            },
+            Expression::Application { func, arg, .. } => {
+                let func = self.infer(env, *func)?;
+                let func = self.apply(&func);
+                let func = self.specialize(func);
+                if !matches!(func, Type::Function(..) | Type::Variable(_)) {
+                    return Err(Diagnostic::new(span, TypeError::NotAFunction(func)));
+                }
+                let arg = self.infer(env, *arg)?;
+                let result = self.fresh();
+                let effects = latent(&func, 1);
+                self.effects.extend(effects.clone());
+                let expected = Type::Function(Box::new(arg), Box::new(result.clone()), effects);
+                self.constrain(&func, &expected).map_err(|e| Diagnostic::new(span, e))?;
+                Ok(result)
+            },
+            Expression::Abstraction { param, func, .. } => {
+                let from = self.fresh();
+                self.abstraction(env, param, from, *func)
+            },
+            Expression::Conditional { if_cond, if_then, if_else, .. } => {
+                let cond_span = if_cond.span();
+                let cond = self.infer(env, *if_cond)?;
+                self.constrain(&cond, &Type::Boolean).map_err(|e| Diagnostic::new(cond_span, e))?;
+                let if_then = self.infer(env, *if_then)?;
+                let if_else = self.infer(env, *if_else)?;
+                let (if_then, if_else) = (self.apply(&if_then), self.apply(&if_else));
+                // either branch may be the subtype of the other: the result is the greater, whichever comes first
+                let (lesser, greater) = match self.context.subtype(&if_then, &if_else) {
+                    true => (&if_then, &if_else),
+                    false => (&if_else, &if_then)
+                };
+                match self.constrain(lesser, greater) {
+                    Ok(()) => Ok(greater.clone()),
+                    Err(TypeError::Mismatch { .. }) => Err(Diagnostic::new(span,
+                        TypeError::BranchMismatch(self.apply(&if_then), self.apply(&if_else)))),
+                    Err(e) => Err(Diagnostic::new(span, e))
+                }
+            },
+            // let-polymorphism: the value is generalized before the body sees it
+            Expression::Let { id, kind, value, body, .. } => {
+                let value_span = value.span();
+                let mut result = self.infer(env, *value)?;
+                if let Some(kind) = kind {
+                    self.constrain(&result, &kind).map_err(|e| Diagnostic::new(value_span, e))?;
+                    result = kind;
+                }
+                let scheme = self.generalize(env, &result);
+                let mut env = env.clone();
+                env.insert(id, scheme);
+                self.infer(&env, *body)
+            },
+            // annotated functions call themselves divergently: any other fixpoint is taken to diverge
+            Expression::Fix { func, .. } => {
+                let annotated = matches!(&*func, Expression::Annotation { expr, kind: Type::Function(..), .. }
+                    if matches!(**expr, Expression::Abstraction { .. }));
+                let func = self.infer(env, recursive(*func))?;
+                let func = self.apply(&func);
+                let func = self.specialize(func);
+                if !matches!(func, Type::Function(..) | Type::Variable(_)) {
+                    return Err(Diagnostic::new(span, TypeError::NotAFunction(func)));
+                }
+                let result = self.fresh();
+                let effects = latent(&func, 1);
+                self.effects.extend(effects.clone());
+                let expected = Type::Function(Box::new(result.clone()), Box::new(result.clone()), effects);
+                self.constrain(&func, &expected).map_err(|e| Diagnostic::new(span, e))?;
+                match annotated {
+                    true => Ok(result),
+                    false => Ok(divergent(&self.apply(&result)))
+                }
+            },
+            // note: type tests do not narrow variables here
+            Expression::Is { expr, .. } => self.infer(env, *expr).map(|_| Type::Boolean),
+            // type abstractions are erased: their bodies are generalized by let like any other
+            Expression::TypeAbstraction { expr, .. } => self.infer(env, *expr),
+            // packages are of the type they are packed as: unpacking gives their contents an unknown type
+            Expression::Pack { witness, expr, kind, .. } => {
+                let found = self.infer(env, *expr)?;
+                self.constrain(&found, &witness).map_err(|e| Diagnostic::new(span, e))?;
+                Ok(kind)
+            },
+            Expression::Unpack { id, expr, body, .. } => {
+                self.infer(env, *expr)?;
+                let mut env = env.clone();
+                env.insert(id, Scheme { vars: Vec::new(), kind: self.fresh() });
+                self.infer(&env, *body)
+            },
+            Expression::TypeApplication { expr, kind, .. } => match self.infer(env, *expr)? {
+                Type::Forall(id, _, body) => Ok(body.substitute(&HashMap::from([(id, kind)]))),
+                found => Ok(found)
+            },
+            // elements must all be of one type: unlike checking, they are not joined
+            Expression::List { data, .. } => self.elements(env, data).map(|kind| Type::List(Box::new(kind))),
+            Expression::Array { data, .. } => {
+                let len = data.len();
+                self.elements(env, data).map(|kind| Type::Array(Box::new(kind), len))
+            },
+            Expression::Struct { data, .. } => {
+                let mut result = std::collections::BTreeMap::new();
+                for (key, expr) in data {
+                    result.insert(key, self.infer(env, expr)?);
+                }
+                Ok(Type::Struct(result))
+            },
+            Expression::Tuple { data, fields, .. } => {
+                let mut result = Vec::new();
+                for expr in data {
+                    result.push(self.infer(env, expr)?);
+                }
+                Ok(Type::Tuple(result, fields))
+            },
+            Expression::Injection { label, expr, .. } => {
+                let kind = self.infer(env, *expr)?;
+                Ok(Type::Variant(std::collections::BTreeMap::from([(label, kind)])))
+            },
+            // a scrutinee of unknown type is the union of the patterns' types,
+            // with labels gathered into one variant of alternatives of unknown type
+            Expression::Case { expr, arms, .. } => {
+                let kind = self.infer(env, *expr)?;
+                if let Type::Variable(_) = self.apply(&kind) {
+                    let mut members = Vec::new();
+                    let mut labels = std::collections::BTreeMap::new();
+                    for (pattern, _) in &arms {
+                        match pattern {
+                            Pattern::Member(_, kind) => members.push(kind.clone()),
+                            Pattern::Label(label, _) if !labels.contains_key(label) => {
+                                labels.insert(label.clone(), self.fresh());
+                            },
+                            Pattern::Label(..) => ()
+                        }
+                    }
+                    if !labels.is_empty() {
+                        members.push(Type::Variant(labels));
+                    }
+                    let expected = match members.len() {
+                        1 => members.remove(0),
+                        _ => Type::Union(members)
+                    };
+                    self.constrain(&kind, &expected).map_err(|e| Diagnostic::new(span, e))?;
+                }
+                let patterns: Vec<&Pattern> = arms.iter().map(|(pattern, _)| pattern).collect();
+                let bindings = self.context.cases(&self.apply(&kind), &patterns).map_err(|e| Diagnostic::new(span, e))?;
+                let result = self.fresh();
+                for ((id, kind), (_, body)) in std::iter::zip(bindings, arms) {
+                    let body_span = body.span();
+                    let mut env = env.clone();
+                    env.insert(id, Scheme { vars: Vec::new(), kind });
+                    let body = self.infer(&env, body)?;
+                    self.constrain(&body, &result).map_err(|e| Diagnostic::new(body_span, e))?;
+                }
+                Ok(result)
+            },
+            // there are no row variables: the type of the accessed expression must be known
+            Expression::Access { expr, at: Accessor::Field(field), .. } => {
+                let kind = self.infer(env, *expr)?;
+                match self.apply(&kind) {
+                    Type::Struct(data) if data.contains_key(&field) => Ok(data.get(&field).unwrap().clone()),
+                    Type::Tuple(data, labels) if let Some(i) = Accessor::position(&field, &labels) => Ok(data[i].clone()),
+                    kind => Err(Diagnostic::new(span, TypeError::NoField(kind, field)))
+                }
+            },
+            Expression::Access { expr, at: Accessor::Index(index), .. } => {
+                let kind = self.infer(env, *expr)?;
+                let index_span = index.span();
+                let index = self.infer(env, *index)?;
+                self.constrain(&index, &Type::Natural).map_err(|e| Diagnostic::new(index_span, e))?;
+                self.effects.insert(Effect::Exn);
+                match self.apply(&kind) {
+                    Type::List(data) | Type::Array(data, _) | Type::Slice(data) => Ok(*data),
+                    kind => Err(Diagnostic::new(span, TypeError::NotIndexable(kind)))
+                }
+            }
+        }
+    }
+}
+
+/// Whether a variable is a fresh one, solved for by unification, rather than one written in a type.
+fn flexible(id: &Identifier) -> bool {
+    id.starts_with('\'')
+}
+
+/// Rename the fresh variables of a type to `a`, `b`, `c`... in order of appearance.
+fn normalize(kind: &Type) -> Type {
+    let free = kind.free();
+    let mut names = (0..).map(|i: usize| match i {
+        0..26 => char::from(b'a' + i as u8).to_string(),
+        _ => format!("t{}", i)
+    }).filter(|name| !free.contains(name));
+    let map = free.iter().filter(|id| id.starts_with('\''))
+        .map(|id| (id.clone(), Type::Variable(names.next().unwrap()))).collect();
+    kind.substitute(&map)
+}
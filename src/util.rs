@@ -1,6 +1,7 @@
 #![allow(non_snake_case, non_upper_case_globals)]
 
 use crate::ast::*;
+use crate::effects::*;
 
 // intentionally small: i want to run into errors
 /// assumption: the count is instantiated to zero
@@ -14,28 +15,30 @@ pub fn unique_ident(count: &mut u8) -> String {
 }
 
 pub fn Ann(expr: Expression, kind: Type) -> Expression {
-    Expression::Annotation { expr: Box::new(expr), kind }
+    Expression::Annotation { expr: Box::new(expr), kind, span: Span::default() }
 }
 
 pub fn Const(term: Term) -> Expression {
-    Expression::Constant { term }
+    Expression::Constant { term, span: Span::default() }
 }
 
 pub fn Var(id: &str) -> Expression {
-    Expression::Variable { id: String::from(id) }
+    Expression::Variable { id: String::from(id), span: Span::default() }
 }
 
 pub fn Abs(param: &str, func: Expression) -> Expression {
     Expression::Abstraction {
         param: String::from(param),
-        func: Box::new(func)
+        func: Box::new(func),
+        span: Span::default()
     }
 }
 
 pub fn App(func: Expression, arg: Expression) -> Expression {
     Expression::Application {
         func: Box::new(func),
-        arg: Box::new(arg)
+        arg: Box::new(arg),
+        span: Span::default()
     }
 }
 
@@ -43,12 +46,62 @@ pub fn Cond(if_cond: Expression, if_then: Expression, if_else: Expression) -> Ex
     Expression::Conditional {
         if_cond: Box::new(if_cond),
         if_then: Box::new(if_then),
-        if_else: Box::new(if_else)
+        if_else: Box::new(if_else),
+        span: Span::default()
+    }
+}
+
+pub fn Let(id: &str, kind: Option<Type>, value: Expression, body: Expression) -> Expression {
+    Expression::Let {
+        id: String::from(id),
+        kind,
+        value: Box::new(value),
+        body: Box::new(body),
+        span: Span::default()
+    }
+}
+
+pub fn Fix(func: Expression) -> Expression {
+    Expression::Fix {
+        func: Box::new(func),
+        span: Span::default()
+    }
+}
+
+pub fn Access(expr: Expression, field: &str) -> Expression {
+    Expression::Access {
+        expr: Box::new(expr),
+        at: Accessor::Field(String::from(field)),
+        span: Span::default()
     }
 }
 
+pub fn Index(expr: Expression, index: Expression) -> Expression {
+    Expression::Access {
+        expr: Box::new(expr),
+        at: Accessor::Index(Box::new(index)),
+        span: Span::default()
+    }
+}
+
+pub fn Case(expr: Expression, arms: Vec<(Pattern, Expression)>) -> Expression {
+    Expression::Case {
+        expr: Box::new(expr),
+        arms,
+        span: Span::default()
+    }
+}
+
+pub fn Member(id: &str, kind: Type) -> Pattern {
+    Pattern::Member(String::from(id), kind)
+}
+
 pub fn Func(from: Type, to: Type) -> Type {
-    Type::Function(Box::new(from), Box::new(to))
+    Type::Function(Box::new(from), Box::new(to), Effects::new())
+}
+
+pub fn Effectful(from: Type, to: Type, effects: &[Effect]) -> Type {
+    Type::Function(Box::new(from), Box::new(to), effects.iter().copied().collect())
 }
 
 pub const Empty: Type = Type::Empty;
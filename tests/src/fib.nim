@@ -1,6 +1,6 @@
 # fake nim
 
-func fib(x): int -> int =
+func fib(x): int -[div]-> int =
   if eq(x, 0):
     0
   else:
@@ -1,7 +1,10 @@
 #![allow(non_upper_case_globals)]
 
+use std::collections::BTreeMap;
 use chrysanthemum::ast::*;
 use chrysanthemum::bidirectional::*;
+use chrysanthemum::effects::*;
+use chrysanthemum::error::*;
 use chrysanthemum::parser::*;
 use chrysanthemum::util::*;
 
@@ -39,7 +42,7 @@ fn test_inference() {
     assert_eq!(context.infer(parse_lambda(correct_cond_abs).unwrap()).unwrap(), Func(Bool, Int));
     assert_eq!(context.infer(parse_lambda(correct_cond).unwrap()).unwrap(), Nat);
     assert!(context.infer(parse_lambda(not_inferrable).unwrap()).is_err());
-    assert!(context.infer(parse_lambda(incorrect_branches).unwrap()).is_err());
+    assert_eq!(context.infer(parse_lambda(incorrect_branches).unwrap()).unwrap(), Type::Union(vec![Bool, Int]));
     assert!(context.infer(parse_lambda(incorrect_cond_abs).unwrap()).is_err());
 }
 
@@ -62,3 +65,308 @@ fn test_checking() {
     assert!(context.check(parse_lambda(not_inferrable).unwrap(), &Func(Bool, Func(Nat, Func(Nat, Nat)))).is_ok());
     assert!(context.check(parse_lambda(not_inferrable).unwrap(), &Func(Bool, Func(Unit, Func(Unit, Unit)))).is_ok());
 }
+
+#[test]
+fn test_diagnostics() {
+    let source = "func f(x): bool -> bool =\n  if x:\n    yak\n  else:\n    x\n";
+    let func = parse_file(source).unwrap().functions.remove(0).1;
+    let error = Context::new().check(func, &Func(Bool, Bool)).unwrap_err();
+    assert_eq!(error.error, TypeError::Unbound(String::from("yak")));
+    assert_eq!(error.render("test.nim", source),
+        "test.nim:3:5: error: failed to find variable yak in context\n  |\n3 |     yak\n  |     ^^^");
+
+    let source = "(λx. x): (int -> fake)";
+    let error = parse_lambda(source).unwrap_err();
+    assert!(matches!(error.error, ParseError::Expected(_)));
+    assert!(error.render("stdin", source).starts_with("stdin:1:"));
+}
+
+#[test]
+fn test_errors() {
+    let context = Context::new();
+    assert_eq!(context.infer(parse_lambda(not_inferrable).unwrap()).unwrap_err().error,
+        TypeError::CannotInferAbstraction);
+    assert_eq!(context.check(parse_lambda(incorrect_branches).unwrap(), &Unit).unwrap_err().error,
+        TypeError::Mismatch { expected: Unit, found: Bool });
+    assert_eq!(context.check(parse_lambda(basic_abstraction).unwrap(), &Func(Bool, Bool)).unwrap_err().error,
+        TypeError::Mismatch { expected: Func(Bool, Bool), found: Func(Int, Int) });
+    assert_eq!(context.infer(App(Const(Term::Natural(413)), Const(Term::Natural(612)))).unwrap_err().error,
+        TypeError::NotAFunction(Nat));
+    assert_eq!(context.check(Abs("x", Var("x")), &Int).unwrap_err().error,
+        TypeError::NonFunctionAbstraction(Int));
+}
+
+#[test]
+fn test_typing_context() {
+    let mut context = Context::new();
+    context.insert_type(String::from("f"), Func(Int, Bool));
+    context.insert_type(String::from("x"), Nat);
+    assert_eq!(context.infer(Var("f")).unwrap(), Func(Int, Bool));
+    assert_eq!(context.infer(App(Var("f"), Var("x"))).unwrap(), Bool);
+    assert!(context.check(Var("x"), &Int).is_ok());
+    assert!(context.check(Var("x"), &Bool).is_err());
+
+    // parameters of function type
+    let apply = "(λf. λx. (f) x): ((int -> int) -> (int -> int))";
+    assert_eq!(Context::new().infer(parse_lambda(apply).unwrap()).unwrap(), Func(Func(Int, Int), Func(Int, Int)));
+    let twice = "(λf. λx. (f) (f) x): ((int -> int) -> (int -> int))";
+    assert!(Context::new().check(parse_lambda(twice).unwrap(), &Func(Func(Int, Int), Func(Int, Int))).is_ok());
+    let wrong = "(λf. λx. (f) x): ((int -> bool) -> (int -> int))";
+    assert!(Context::new().infer(parse_lambda(wrong).unwrap()).is_err());
+}
+
+#[test]
+fn test_bindings() {
+    let context = Context::new();
+    assert_eq!(context.infer(parse_lambda("let x = 1 in x").unwrap()).unwrap(), Nat);
+    assert_eq!(context.infer(parse_lambda("let x: int = 1 in x").unwrap()).unwrap(), Int);
+    assert_eq!(context.infer(parse_lambda("let f: (int -> bool) = λx. true in (f) 2").unwrap()).unwrap(), Bool);
+    assert!(context.check(parse_lambda("let x = true in if x then 1 else 0").unwrap(), &Int).is_ok());
+    assert!(context.check(parse_lambda("let x = 1 in if x then 1 else 0").unwrap(), &Int).is_err());
+    assert!(context.infer(parse_lambda("let x: bool = 1 in x").unwrap()).is_err());
+    // shadowing
+    assert_eq!(context.infer(parse_lambda("let x = 1 in let x = true in x").unwrap()).unwrap(), Bool);
+    assert_eq!(context.infer(parse_lambda("let x = 1 in y").unwrap()).unwrap_err().error,
+        TypeError::Unbound(String::from("y")));
+}
+
+#[test]
+fn test_recursion() {
+    let context = Context::new();
+    let flip = "letrec f: (bool -[div]-> bool) = λx. if x then (f) false else true in (f) true";
+    assert_eq!(context.infer(parse_lambda(flip).unwrap()).unwrap(), Bool);
+    assert!(context.check(Fix(Abs("f", Abs("x", App(Var("f"), Var("x"))))), &Effectful(Int, Int, &[Effect::Div])).is_ok());
+    assert_eq!(context.infer(Fix(Ann(Abs("x", Var("x")), Func(Int, Int)))).unwrap(), Int);
+    assert_eq!(context.infer(Fix(Ann(Abs("x", Const(Term::Boolean(true))), Func(Int, Bool)))).unwrap_err().error,
+        TypeError::Mismatch { expected: Int, found: Bool });
+    assert_eq!(context.infer(Fix(Const(Term::Natural(1)))).unwrap_err().error, TypeError::NotAFunction(Nat));
+}
+
+#[test]
+fn test_primitives() {
+    let context = Context::new();
+    let add = |x, y| App(App(Var("add"), x), y);
+    assert_eq!(context.infer(add(Const(Term::Natural(1)), Const(Term::Natural(2)))).unwrap(), Nat);
+    assert_eq!(context.infer(add(Const(Term::Natural(1)), Const(Term::Integer(2)))).unwrap(), Int);
+    assert_eq!(context.infer(App(App(Var("eq"), Const(Term::Boolean(true))), Const(Term::Boolean(false)))).unwrap(), Bool);
+    assert_eq!(context.infer(App(Var("add"), Const(Term::Integer(1)))).unwrap(), Func(Int, Int));
+    assert!(context.check(add(Const(Term::Natural(1)), Const(Term::Natural(2))), &Int).is_ok());
+    assert!(context.check(Var("not"), &Func(Bool, Bool)).is_ok());
+    assert_eq!(context.infer(Var("not")).unwrap(), Func(Bool, Bool));
+    assert_eq!(context.infer(Var("add")).unwrap_err().error, TypeError::Ambiguous(String::from("add")));
+    assert_eq!(context.infer(add(Const(Term::Boolean(true)), Const(Term::Natural(2)))).unwrap_err().error,
+        TypeError::NoOverload(String::from("add"), vec![Bool, Nat]));
+
+    // bound variables shadow primitives
+    let mut context = Context::new();
+    context.insert_type(String::from("add"), Func(Bool, Bool));
+    assert_eq!(context.infer(App(Var("add"), Const(Term::Boolean(true)))).unwrap(), Bool);
+}
+
+#[test]
+fn test_access() {
+    let mut context = Context::new();
+    let point = Type::Struct(BTreeMap::from([(String::from("x"), Int), (String::from("y"), Bool)]));
+    let pair = Type::Tuple(vec![Nat, Bool], vec![None, Some(String::from("flag"))]);
+    context.insert_type(String::from("s"), point.clone());
+    context.insert_type(String::from("t"), pair.clone());
+    context.insert_type(String::from("a"), Type::Array(Box::new(Int), 3));
+    context.insert_type(String::from("l"), Type::List(Box::new(pair.clone())));
+
+    assert_eq!(context.infer(parse_lambda("s.x").unwrap()).unwrap(), Int);
+    assert_eq!(context.infer(parse_lambda("t.0").unwrap()).unwrap(), Nat);
+    assert_eq!(context.infer(parse_lambda("t.1").unwrap()).unwrap(), Bool);
+    assert_eq!(context.infer(parse_lambda("t.flag").unwrap()).unwrap(), Bool);
+    assert_eq!(context.infer(parse_lambda("a[2]").unwrap()).unwrap(), Int);
+    assert_eq!(context.infer(parse_lambda("l[0].flag").unwrap()).unwrap(), Bool);
+    assert!(context.check(parse_lambda("if t.flag then s.x else a[0]").unwrap(), &Int).is_ok());
+    assert!(context.check(parse_lambda("t.0").unwrap(), &Int).is_ok());
+    assert!(context.check(parse_lambda("s.y").unwrap(), &Int).is_err());
+
+    assert_eq!(context.infer(parse_lambda("s.z").unwrap()).unwrap_err().error,
+        TypeError::NoField(point.clone(), String::from("z")));
+    assert_eq!(context.infer(parse_lambda("t.2").unwrap()).unwrap_err().error,
+        TypeError::NoField(pair, String::from("2")));
+    assert_eq!(context.infer(parse_lambda("s[0]").unwrap()).unwrap_err().error, TypeError::NotIndexable(point));
+    assert_eq!(context.infer(parse_lambda("a[true]").unwrap()).unwrap_err().error,
+        TypeError::Mismatch { expected: Nat, found: Bool });
+}
+
+#[test]
+fn test_joins() {
+    let mut context = Context::new();
+    context.insert_type(String::from("c"), Bool);
+    let infer = |source: &str| context.infer(parse_lambda(source).unwrap()).unwrap();
+    assert_eq!(infer("if c then 1 else -1"), Int);
+    assert_eq!(infer("if c then -1 else 1"), Int);
+    assert_eq!(infer("if c then 1 else true"), Type::Union(vec![Nat, Bool]));
+    assert_eq!(infer("if c then 1 else if c then true else -1"), Type::Union(vec![Bool, Int]));
+    assert_eq!(infer("if c then {x = 1, y = true} else {x = -1, z = ()}"),
+        Type::Struct(BTreeMap::from([(String::from("x"), Int)])));
+    assert_eq!(infer("if c then #[1, 2] else #[3]"), Type::Slice(Box::new(Nat)));
+    assert_eq!(infer("[1, -1, 2]"), Type::List(Box::new(Int)));
+    assert_eq!(infer("[[1], [true]]"), Type::List(Box::new(Type::List(Box::new(Type::Union(vec![Nat, Bool]))))));
+    assert_eq!(infer("(1, 2)"), Type::Tuple(vec![Nat, Nat], vec![None, None]));
+    assert_eq!(infer("if c then (1, true) else (-1, false)"), Type::Tuple(vec![Int, Bool], vec![None, None]));
+
+    // functions join contravariantly in their domains
+    let nat_int = Func(Nat, Int);
+    let int_nat = Func(Int, Nat);
+    assert_eq!(context.join(&nat_int, &int_nat), Func(Nat, Int));
+    assert_eq!(context.meet(&nat_int, &int_nat), Func(Int, Nat));
+    assert_eq!(context.join(&Func(Int, Bool), &Func(Bool, Bool)), Func(Type::Error, Bool));
+    assert_eq!(context.meet(&Func(Int, Bool), &Func(Bool, Bool)), Func(Type::Union(vec![Int, Bool]), Bool));
+
+    // empty is the top type, and error the bottom
+    for kind in [Nat, Type::Union(vec![Int, Bool]), Func(Int, Int), Empty, Type::Error] {
+        assert_eq!(context.join(&kind, &Empty), Empty);
+        assert_eq!(context.join(&kind, &Type::Error), kind);
+        assert_eq!(context.meet(&kind, &Empty), kind);
+        assert_eq!(context.meet(&kind, &Type::Error), Type::Error);
+    }
+    assert_eq!(context.meet(&Bool, &Nat), Type::Error);
+    assert_eq!(context.meet(&Type::Union(vec![Nat, Bool]), &Type::Union(vec![Int, Unit])), Nat);
+    assert!(context.subtype(&Nat, &Type::Union(vec![Int, Bool])));
+    assert!(context.subtype(&Type::Union(vec![Nat, Bool]), &Type::Union(vec![Int, Bool, Unit])));
+    assert!(!context.subtype(&Type::Union(vec![Int, Bool]), &Type::Union(vec![Nat, Bool])));
+}
+
+#[test]
+fn test_cases() {
+    let mut context = Context::new();
+    context.insert_type(String::from("u"), Type::Union(vec![Int, Bool]));
+    context.insert_type(String::from("n"), Nat);
+    let infer = |source: &str| context.infer(parse_lambda(source).unwrap());
+    assert_eq!(infer("case u of x: int => x | b: bool => if b then 1 else 0").unwrap(), Int);
+    assert_eq!(infer("case u of x: int => x | b: bool => b").unwrap(), Type::Union(vec![Int, Bool]));
+    // patterns are narrowed to what the scrutinee could be
+    assert_eq!(infer("case n of x: int => x").unwrap(), Nat);
+    assert_eq!(infer("case u of x: nat => x | y: empty => y").unwrap(), Type::Union(vec![Int, Bool]));
+    assert!(context.check(parse_lambda("case u of x: int => x | b: bool => 1").unwrap(), &Int).is_ok());
+    assert_eq!(context.check(parse_lambda("case u of x: int => x | b: bool => b").unwrap(), &Int).unwrap_err().error,
+        TypeError::Mismatch { expected: Int, found: Bool });
+
+    // every member must be covered, and every arm reachable
+    assert_eq!(infer("case u of x: int => x").unwrap_err().error, TypeError::NonExhaustive(vec![Bool]));
+    assert_eq!(infer("case u of x: nat => x | b: bool => 1").unwrap_err().error, TypeError::NonExhaustive(vec![Int]));
+    assert_eq!(infer("case u of x: int => x | b: bool => 1 | s: str => 2").unwrap_err().error,
+        TypeError::Unreachable(Member("s", Type::String)));
+
+    // union introduction
+    let union = Type::Union(vec![Int, Bool]);
+    assert!(context.check(Const(Union(Term::Boolean(true))), &union).is_ok());
+    assert!(context.check(Const(Union(Term::Natural(1))), &union).is_ok());
+    assert!(context.check(Const(Term::Natural(1)), &union).is_ok());
+    assert!(context.check(parse_lambda("if true then 1 else false").unwrap(), &union).is_ok());
+    assert_eq!(context.check(Const(Union(Term::Unit())), &union).unwrap_err().error,
+        TypeError::Mismatch { expected: union.clone(), found: Unit });
+    assert_eq!(context.infer(Const(Union(Term::Unit()))).unwrap_err().error, TypeError::CannotInferUnion);
+    assert!(context.check(parse_lambda("(λx. case x of i: int => 0 | b: bool => 1): (union[int, bool] -> nat)").unwrap(),
+        &Func(union, Nat)).is_ok());
+}
+
+#[test]
+fn test_narrowing() {
+    let mut context = Context::new();
+    context.insert_type(String::from("u"), Type::Union(vec![Int, Bool, Type::String]));
+    context.insert_type(String::from("n"), Nat);
+    let infer = |source: &str| context.infer(parse_lambda(source).unwrap());
+    assert_eq!(infer("u is int").unwrap(), Bool);
+    assert_eq!(infer("if u is int then u else 0").unwrap(), Int);
+    assert_eq!(infer("if u is int then true else u").unwrap(), Type::Union(vec![Bool, Type::String]));
+    assert_eq!(infer("if u is int then 0 else if u is bool then 1 else u").unwrap(), Type::Union(vec![Nat, Type::String]));
+    assert_eq!(infer("if u is union[int, bool] then u else u").unwrap(), Type::Union(vec![Int, Bool, Type::String]));
+    // narrowing meets: a nat tested to be an int is still a nat
+    assert_eq!(infer("if n is int then n else 0").unwrap(), Nat);
+    assert!(context.check(parse_lambda("if u is int then ((add) u) 1 else 0").unwrap(), &Int).is_ok());
+    assert!(context.check(parse_lambda("if u is bool then if u then 1 else 0 else 2").unwrap(), &Nat).is_ok());
+    assert_eq!(context.check(parse_lambda("if u is int then 0 else ((add) u) 1").unwrap(), &Int).unwrap_err().error,
+        TypeError::NoOverload(String::from("add"), vec![Type::Union(vec![Bool, Type::String]), Nat]));
+    // only variables are narrowed
+    assert!(infer("if (((add) 1) 2) is int then u else 0").is_ok());
+    assert!(context.check(parse_lambda("(λx. if x is int then x else 0): (union[int, bool] -> int)").unwrap(),
+        &Func(Type::Union(vec![Int, Bool]), Int)).is_ok());
+    // and narrowing holds under annotated functions, whose bodies' effects are inferred narrowed too
+    assert_eq!(infer("(λv. if v is bool then 0 else ((add) v) 1): (union[int, bool] -> int)").unwrap(),
+        Func(Type::Union(vec![Int, Bool]), Int));
+    assert!(infer("(λv. if v is int then ((div) v) 2 else 0): (union[int, bool] -[exn]-> int)").is_ok());
+    assert_eq!(infer("(λv. if v is int then ((div) v) 2 else 0): (union[int, bool] -> int)").unwrap_err().error,
+        TypeError::UndeclaredEffects(Effects::from([Effect::Exn])));
+}
+
+#[test]
+fn test_compounds() {
+    let mut context = Context::new();
+    context.insert_type(String::from("x"), Int);
+    context.insert_type(String::from("y"), Bool);
+    let infer = |source: &str| context.infer(parse_lambda(source).unwrap());
+    let check = |source: &str, kind: &Type| context.check(parse_lambda(source).unwrap(), kind);
+    let list = |kind: Type| Type::List(Box::new(kind));
+    // heterogeneous elements are joined
+    assert_eq!(infer("[x, 1]").unwrap(), list(Int));
+    assert_eq!(infer("[x, y]").unwrap(), list(Type::Union(vec![Int, Bool])));
+    assert_eq!(infer("#[x, 1, -2]").unwrap(), Type::Array(Box::new(Int), 3));
+    assert_eq!(infer("{a = x, b = [y]}").unwrap(), Type::Struct(BTreeMap::from([
+        (String::from("a"), Int), (String::from("b"), list(Bool))])));
+    assert_eq!(infer("(x, flag: y)").unwrap(), Type::Tuple(vec![Int, Bool], vec![None, Some(String::from("flag"))]));
+    assert_eq!(infer("[x, ((add) x) 1][0]").unwrap(), Int);
+    assert_eq!(infer("[1, true]").unwrap(), list(Type::Union(vec![Nat, Bool])));
+
+    // components are checked against their expected types
+    assert!(check("[x, 1]", &list(Int)).is_ok());
+    assert!(check("#[x, 1]", &Type::Slice(Box::new(Int))).is_ok());
+    assert_eq!(check("[x, y]", &list(Int)).unwrap_err().error, TypeError::Mismatch { expected: Int, found: Bool });
+    assert_eq!(check("#[x, 1]", &Type::Array(Box::new(Int), 3)).unwrap_err().error,
+        TypeError::Mismatch { expected: Type::Array(Box::new(Int), 3), found: Type::Array(Box::new(Int), 2) });
+    assert!(check("{a = x, b = y}", &Type::Struct(BTreeMap::from([(String::from("a"), Int)]))).is_ok());
+    assert!(check("(x, 1)", &Type::Tuple(vec![Int, Int], vec![None, None])).is_ok());
+    assert!(context.check(parse_lambda("λz. [z, ((add) z) 1]").unwrap(), &Func(Int, list(Int))).is_ok());
+
+    // empty lists and arrays only check
+    assert!(check("[]", &list(Int)).is_ok());
+    assert!(check("#[]", &Type::Array(Box::new(Bool), 0)).is_ok());
+    assert!(check("[[], [1]]", &list(list(Nat))).is_ok());
+    assert!(check("{a = []}", &Type::Struct(BTreeMap::from([(String::from("a"), list(Int))]))).is_ok());
+    assert_eq!(infer("[]").unwrap_err().error, TypeError::CannotInferEmpty);
+    assert_eq!(Term::List(vec![Term::Natural(1), Term::Boolean(true)]).convert().unwrap_err(),
+        TypeError::Mismatch { expected: Nat, found: Bool });
+}
+
+#[test]
+fn test_variants() {
+    let variant = |data: Vec<(&str, Type)>| Type::Variant(data.into_iter().map(|(k, v)| (String::from(k), v)).collect());
+    let option = variant(vec![("some", Int), ("none", Unit)]);
+    let mut context = Context::new();
+    context.insert_type(String::from("o"), option.clone());
+    let infer = |source: &str| context.infer(parse_lambda(source).unwrap());
+
+    // injections are of their own alternative, and check against any variant with it
+    assert_eq!(infer("<some = 3>").unwrap(), variant(vec![("some", Nat)]));
+    assert!(context.check(parse_lambda("<some = 3>").unwrap(), &option).is_ok());
+    assert!(context.check(parse_lambda("<none = ()>").unwrap(), &option).is_ok());
+    assert_eq!(context.check(parse_lambda("<some = true>").unwrap(), &option).unwrap_err().error,
+        TypeError::Mismatch { expected: Int, found: Bool });
+    assert_eq!(context.check(parse_lambda("<other = 1>").unwrap(), &option).unwrap_err().error,
+        TypeError::NoField(option.clone(), String::from("other")));
+    assert_eq!(infer("if true then <some = 1> else <none = ()>").unwrap(), variant(vec![("some", Nat), ("none", Unit)]));
+    assert_eq!(infer("if true then <some = 1> else <some = -1>").unwrap(), variant(vec![("some", Int)]));
+
+    // width subtyping is opposite to structs: more alternatives is a supertype
+    assert!(context.subtype(&variant(vec![("some", Nat)]), &option));
+    assert!(!context.subtype(&option, &variant(vec![("some", Int)])));
+    assert!(context.subtype(&option, &variant(vec![("some", Int), ("none", Unit), ("error", Type::String)])));
+    assert!(!context.subtype(&option, &variant(vec![("some", Nat), ("none", Unit)])));
+    assert_eq!(context.meet(&option, &variant(vec![("some", Nat), ("error", Unit)])), variant(vec![("some", Nat)]));
+
+    // cases bind the injected value, and must cover every label
+    assert_eq!(infer("case o of <some = x> => x | <none = y> => 0").unwrap(), Int);
+    assert_eq!(infer("case o of <none = y> => y | <some = x> => x").unwrap(), Type::Union(vec![Unit, Int]));
+    assert_eq!(infer("case o of <some = x> => x").unwrap_err().error,
+        TypeError::NonExhaustive(vec![variant(vec![("none", Unit)])]));
+    assert_eq!(infer("case o of <some = x> => x | <none = y> => 0 | <error = e> => 1").unwrap_err().error,
+        TypeError::Unreachable(Pattern::Label(String::from("error"), String::from("e"))));
+    // member patterns may match alternatives too
+    assert_eq!(infer("case o of <some = x> => x | y: <none: unit> => 0").unwrap(), Int);
+    assert!(context.check(parse_lambda("(λx. case x of <some = n> => n | <none = y> => 0): (<some: int, none: unit> -> int)").unwrap(),
+        &Func(option, Int)).is_ok());
+}
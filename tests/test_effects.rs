@@ -0,0 +1,113 @@
+use chrysanthemum::ast::*;
+use chrysanthemum::effects::*;
+use chrysanthemum::error::*;
+use chrysanthemum::parser::*;
+use chrysanthemum::util::*;
+
+#[test]
+fn test_effect_inference() {
+    let mut context = Context::new();
+    context.insert_type(String::from("a"), Type::Array(Box::new(Nat), 2));
+    context.insert_type(String::from("log"), Effectful(Type::String, Unit, &[Effect::Io]));
+    let effects = |source: &str| context.effects(&parse_lambda(source).unwrap()).unwrap();
+    assert_eq!(effects("413"), Effects::new());
+    assert_eq!(effects("((add) 1) 2"), Effects::new());
+    assert_eq!(effects(r#"(print) "hello""#), Effects::from([Effect::Io]));
+    assert_eq!(effects(r#"(log) "hello""#), Effects::from([Effect::Io]));
+    assert_eq!(effects("((div) 1) 0"), Effects::from([Effect::Exn]));
+    assert_eq!(effects(r#"if true then (print) "a" else a[2]"#), Effects::from([Effect::Io, Effect::Exn]));
+    // abstractions are values: their effects are had only when applied
+    assert_eq!(effects(r#"λx. (print) "hello""#), Effects::new());
+    assert_eq!(effects(r#"let f: (unit -[io]-> unit) = λx. (print) "hello" in f"#), Effects::new());
+    assert_eq!(effects(r#"let f: (unit -[io]-> unit) = λx. (print) "hello" in (f) ()"#), Effects::from([Effect::Io]));
+    // partially applied primitives have their effects on the last arrow
+    assert_eq!(context.infer(parse_lambda("(div) 1").unwrap()).unwrap(), Effectful(Nat, Nat, &[Effect::Exn]));
+    assert_eq!(context.infer(parse_lambda("print").unwrap()).unwrap(), Effectful(Type::String, Unit, &[Effect::Io]));
+}
+
+#[test]
+fn test_effect_checking() {
+    let context = Context::new();
+    let check = |source: &str| context.check(parse_lambda(source).unwrap(), &Empty);
+    assert!(check(r#"(λx. (print) x): (str -[io]-> unit)"#).is_ok());
+    assert!(check(r#"(λx. (print) x): (str -[io, exn]-> unit)"#).is_ok());
+    assert!(check("(λx. ((div) x) 2): (nat -[exn]-> nat)").is_ok());
+    assert!(check("(λx. λy. ((div) x) y): (nat -> nat -[exn]-> nat)").is_ok());
+    assert_eq!(check(r#"(λx. (print) x): (str -> unit)"#).unwrap_err().error,
+        TypeError::UndeclaredEffects(Effects::from([Effect::Io])));
+    assert_eq!(check("(λx. ((div) x) 2): (nat -[io]-> nat)").unwrap_err().error,
+        TypeError::UndeclaredEffects(Effects::from([Effect::Exn])));
+    // effects are checked where they are had, not where the function is created
+    assert_eq!(check("(λx. λy. ((div) x) y): (nat -[exn]-> nat -> nat)").unwrap_err().error,
+        TypeError::UndeclaredEffects(Effects::from([Effect::Exn])));
+
+    // subeffecting: pure functions may be used where effectful ones are expected, but not vice versa
+    let pure = Func(Int, Int);
+    let io = Effectful(Int, Int, &[Effect::Io]);
+    assert!(context.subtype(&pure, &io));
+    assert!(!context.subtype(&io, &pure));
+    assert!(context.subtype(&Effectful(Int, Int, &[Effect::Io]), &Effectful(Nat, Int, &[Effect::Io, Effect::State])));
+    assert!(check("((λf. (f) 1): ((int -[io]-> int) -[io]-> int)) ((λx. x): (int -> int))").is_ok());
+    assert!(check(r#"((λf. (f) 1): ((int -> int) -> int)) ((λx. (λy. x) ((print) "a")): (int -[io]-> int))"#).is_err());
+    // applying an effectful function has its effects
+    assert_eq!(check(r#"(λf. (f) 1): ((int -[io]-> int) -> int)"#).unwrap_err().error,
+        TypeError::UndeclaredEffects(Effects::from([Effect::Io])));
+}
+
+#[test]
+fn test_divergence() {
+    let context = Context::new();
+    let infer = |source: &str| context.infer(parse_lambda(source).unwrap());
+    // recursive functions may not terminate: they must declare so
+    let loops = "letrec f: (nat -> nat) = λx. (f) x in (f) 1";
+    assert_eq!(infer(loops).unwrap_err().error, TypeError::UndeclaredEffects(Effects::from([Effect::Div])));
+    assert_eq!(infer("letrec f: (nat -[div]-> nat) = λx. (f) x in f").unwrap(), Effectful(Nat, Nat, &[Effect::Div]));
+    assert_eq!(context.effects(&parse_lambda("letrec f: (nat -[div]-> nat) = λx. (f) x in (f) 1").unwrap()).unwrap(),
+        Effects::from([Effect::Div]));
+    // ...as must their callers, though functions that only may recur need not
+    assert!(infer("letrec f: (nat -[div]-> nat) = λx. (f) x in (λy. (f) y): (nat -> nat)").is_err());
+    assert_eq!(infer("letrec f: (nat -> nat) = λx. x in (f) 1").unwrap(), Nat);
+    // fixpoints of anything but functions may diverge when taken
+    assert_eq!(context.effects(&parse_lambda("fix ((λx. x): (nat -> nat))").unwrap()).unwrap(), Effects::from([Effect::Div]));
+    assert_eq!(context.effects(&parse_lambda("fix ((λf. λx. (f) x): ((nat -[div]-> nat) -> (nat -[div]-> nat)))").unwrap()).unwrap(),
+        Effects::new());
+    // in files, for recursive functions and groups of them alike
+    let program = "func count(n): nat -> nat =\n  if eq(n, 0):\n    0\n  else:\n    count(sub(n, 1))\n\ncount(3)\n";
+    for expr in parse_file(program).unwrap().lower() {
+        assert_eq!(context.check(expr, &Nat).unwrap_err().error, TypeError::UndeclaredEffects(Effects::from([Effect::Div])));
+    }
+    let program = "func ping(n): nat -> nat =\n  pong(n)\n\nfunc pong(n): nat -[div]-> nat =\n  ping(n)\n\nping(3)\n";
+    for expr in parse_file(program).unwrap().lower() {
+        assert_eq!(context.check(expr, &Nat).unwrap_err().error, TypeError::UndeclaredEffects(Effects::from([Effect::Div])));
+    }
+    // and with unification
+    assert!(matches!(context.principal(parse_lambda(loops).unwrap()).unwrap_err().error, TypeError::Mismatch { .. }));
+}
+
+#[test]
+fn test_effect_unification() {
+    let context = Context::new();
+    let principal = |source: &str| context.principal(parse_lambda(source).unwrap());
+    assert_eq!(principal(r#"λx. (print) x"#).unwrap(), Effectful(Type::String, Unit, &[Effect::Io]));
+    assert_eq!(principal("λx. λy. ((div) 9) 2").unwrap(), Func(Type::Variable(String::from("a")),
+        Effectful(Type::Variable(String::from("b")), Nat, &[Effect::Exn])));
+    assert_eq!(principal(r#"(λx. (print) x): (str -[io, state]-> unit)"#).unwrap(),
+        Effectful(Type::String, Unit, &[Effect::Io, Effect::State]));
+    assert!(matches!(principal(r#"(λx. (print) x): (str -> unit)"#).unwrap_err().error, TypeError::Mismatch { .. }));
+}
+
+#[test]
+fn test_effect_programs() {
+    let context = Context::new();
+    let program = "func half(x): nat -[exn]-> nat =\n  div(x, 2)\n\nhalf(half(9))\n";
+    for expr in parse_file(program).unwrap().lower() {
+        assert!(context.check(expr.clone(), &Nat).is_ok());
+        assert_eq!(context.effects(&expr).unwrap(), Effects::from([Effect::Exn]));
+        assert_eq!(context.execute(expr).unwrap(), Term::Natural(2));
+    }
+    let program = "func half(x): nat -> nat =\n  div(x, 2)\n\nhalf(9)\n";
+    for expr in parse_file(program).unwrap().lower() {
+        assert_eq!(context.check(expr, &Nat).unwrap_err().error,
+            TypeError::UndeclaredEffects(Effects::from([Effect::Exn])));
+    }
+}
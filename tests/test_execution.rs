@@ -1,4 +1,7 @@
+use std::collections::BTreeMap;
 use chrysanthemum::ast::*;
+use chrysanthemum::error::*;
+use chrysanthemum::parser::*;
 use chrysanthemum::simple::*;
 use chrysanthemum::util::*;
 
@@ -8,7 +11,9 @@ fn test_simple() {
     assert_eq!(context.execute(Const(Term::Boolean(false))).unwrap(), Term::Boolean(false));
     assert_eq!(context.execute(Const(Term::Natural(123))).unwrap(), Term::Natural(123));
     assert_eq!(context.execute(Const(Term::Integer(123))).unwrap(), Term::Integer(123));
-    assert!(context.execute(Var("x")).is_err());
+    assert_eq!(context.execute(Var("x")).unwrap_err().error, RuntimeError::Unbound(String::from("x")));
+    assert_eq!(context.execute(Cond(Const(Term::Natural(1)), Var("x"), Var("x"))).unwrap_err().error,
+        RuntimeError::NotABoolean(Term::Natural(1)));
 }
 
 #[test]
@@ -22,3 +27,236 @@ fn test_complex() {
     assert_eq!(context.execute(App(Abs("z", Cond(Const(Term::Boolean(false)),
         Var("x"), Var("z"))), Const(Term::Integer(1025)))).unwrap(), Term::Integer(1025));
 }
+
+#[test]
+fn test_files() {
+    let program = "func negate(x): bool -> bool =\n  if x:\n    false\n  else:\n    true\n\nnegate(negate(false))\n";
+    let context = Context::new();
+    for expr in parse_file(program).unwrap().lower() {
+        assert!(context.check(expr.clone(), &Bool).is_ok());
+        assert_eq!(context.execute(expr).unwrap(), Term::Boolean(false));
+    }
+    let program = "func hi(s): str -> bool =\n  eq(s, \"hi\")\n\nhi(\"hi\")\nadd(1.5, 2.5)\n";
+    let results: Vec<Term> = parse_file(program).unwrap().lower().into_iter().map(|expr| {
+        assert!(context.infer(expr.clone()).is_ok());
+        context.execute(expr).unwrap()
+    }).collect();
+    assert_eq!(results, vec![Term::Boolean(true), Term::Float(4.0)]);
+    // definitions are inlined without capture by the parameters of their callers
+    let program = "func negate(x): bool -> bool =\n  not(x)\n\nfunc apply(not): bool -> bool =\n  negate(not)\n\napply(true)\n";
+    for expr in parse_file(program).unwrap().lower() {
+        assert!(context.check(expr.clone(), &Bool).is_ok());
+        assert_eq!(context.execute(expr).unwrap(), Term::Boolean(false));
+    }
+}
+
+#[test]
+fn test_bindings() {
+    let context = Context::new();
+    assert_eq!(context.execute(Let("x", None, Const(Term::Natural(413)), Var("x"))).unwrap(), Term::Natural(413));
+    assert_eq!(context.execute(Let("x", Some(Int), Const(Term::Integer(1)),
+        Let("y", None, Const(Term::Boolean(false)), Cond(Var("y"), Const(Term::Integer(0)), Var("x"))))).unwrap(),
+        Term::Integer(1));
+    assert_eq!(context.execute(Let("x", None, Const(Term::Natural(1)),
+        App(Abs("x", Var("x")), Const(Term::Natural(2))))).unwrap(), Term::Natural(2));
+}
+
+#[test]
+fn test_recursion() {
+    let context = Context::new();
+    let flip = "letrec f: (bool -> bool) = λx. if x then (f) false else true in (f) true";
+    assert_eq!(context.execute(parse_lambda(flip).unwrap()).unwrap(), Term::Boolean(true));
+    // recursive functions are scoped where they are defined, not where they recur
+    let shadowed = "let n = 10 in letrec f: nat -[div]-> nat = λk. if ((eq) k) 0 then n else let n = 0 in (f) ((sub) k) 1 in (f) 2";
+    assert_eq!(context.infer(parse_lambda(shadowed).unwrap()).unwrap(), Nat);
+    assert_eq!(context.execute(parse_lambda(shadowed).unwrap()).unwrap(), Term::Natural(10));
+    let program = "func f(x): bool -[div]-> bool =\n  if x:\n    f(false)\n  else:\n    false\n\nf(true)\n";
+    for expr in parse_file(program).unwrap().lower() {
+        assert!(context.check(expr.clone(), &Bool).is_ok());
+        assert_eq!(context.execute(expr).unwrap(), Term::Boolean(false));
+    }
+    // functions may call those defined after them, and each other
+    let program = "func a(x): nat -> nat =\n  b(x)\n\nfunc b(x): nat -> nat =\n  add(x, 1)\n\na(1)\n";
+    for expr in parse_file(program).unwrap().lower() {
+        assert!(context.check(expr.clone(), &Nat).is_ok());
+        assert_eq!(context.execute(expr).unwrap(), Term::Natural(2));
+    }
+    let program = "func even(n): nat -[div]-> bool =\n  if eq(n, 0):\n    true\n  else:\n    odd(sub(n, 1))\n\n\
+        func odd(n): nat -[div]-> bool =\n  if eq(n, 0):\n    false\n  else:\n    even(sub(n, 1))\n\neven(10)\nodd(10)\n";
+    let results: Vec<Term> = parse_file(program).unwrap().lower().into_iter().map(|expr| {
+        assert_eq!(context.infer(expr.clone()).unwrap(), Bool);
+        context.execute(expr).unwrap()
+    }).collect();
+    assert_eq!(results, vec![Term::Boolean(true), Term::Boolean(false)]);
+}
+
+#[test]
+fn test_primitives() {
+    let context = Context::new();
+    let add = |x, y| App(App(Var("add"), x), y);
+    assert_eq!(context.execute(add(Const(Term::Natural(1)), Const(Term::Natural(2)))).unwrap(), Term::Natural(3));
+    assert_eq!(context.execute(add(Const(Term::Natural(1)), Const(Term::Integer(-2)))).unwrap(), Term::Integer(-1));
+    assert_eq!(context.execute(App(App(Var("lt"), Const(Term::Float(0.5))), Const(Term::Float(1.5)))).unwrap(),
+        Term::Boolean(true));
+    assert_eq!(context.execute(App(Var("not"), Const(Term::Boolean(true)))).unwrap(), Term::Boolean(false));
+    assert_eq!(context.execute(App(App(Var("div"), Const(Term::Natural(1))), Const(Term::Natural(0)))).unwrap_err().error,
+        RuntimeError::DivisionByZero);
+    assert_eq!(context.execute(App(App(Var("sub"), Const(Term::Natural(0))), Const(Term::Natural(1)))).unwrap_err().error,
+        RuntimeError::Overflow);
+    assert_eq!(context.execute(App(Var("add"), Const(Term::Natural(1)))).unwrap_err().error,
+        RuntimeError::Unsaturated(String::from("add")));
+    assert_eq!(context.execute(App(Var("not"), Const(Term::Natural(1)))).unwrap_err().error,
+        RuntimeError::NoOverload(String::from("not"), vec![Term::Natural(1)]));
+
+    let program = parse_file(include_str!("src/fib.nim")).unwrap();
+    let fib = Program { functions: program.functions, instances: vec![], expressions: vec![App(Var("fib"), Const(Term::Natural(5)))] };
+    for expr in fib.lower() {
+        assert!(context.check(expr.clone(), &Int).is_ok());
+        assert_eq!(context.execute(expr).unwrap(), Term::Integer(5));
+    }
+    // overloads are resolved by the types values were checked at, not by the terms they evaluate to
+    let subtracted = parse_lambda("((λx. ((sub) x) 1): (int -> int)) 0").unwrap();
+    assert_eq!(context.infer(subtracted.clone()).unwrap(), Int);
+    assert_eq!(context.execute(subtracted).unwrap(), Term::Integer(-1));
+    assert_eq!(context.execute(parse_lambda("((λx. ((div) x) 2): (int -[exn]-> int)) 3").unwrap()).unwrap(), Term::Integer(1));
+    assert_eq!(context.execute(parse_lambda("let x: int = 0 in ((sub) x) 1").unwrap()).unwrap(), Term::Integer(-1));
+}
+
+#[test]
+fn test_closures() {
+    let context = Context::new();
+    let curried = "((λx. λy. x) 1) 2";
+    assert_eq!(context.execute(parse_lambda(curried).unwrap()).unwrap(), Term::Natural(1));
+    // functions are values: they can be bound, passed, and returned
+    let bound = "let f = λx. if x then false else true in (f) true";
+    assert_eq!(context.execute(parse_lambda(bound).unwrap()).unwrap(), Term::Boolean(false));
+    let passed = "((λf. λx. (f) (f) x) λy. if y then false else true) false";
+    assert_eq!(context.execute(parse_lambda(passed).unwrap()).unwrap(), Term::Boolean(false));
+    // closures capture their environment at creation
+    let captured = "let x = 1 in let f = λy. x in let x = 2 in (f) 3";
+    assert_eq!(context.execute(parse_lambda(captured).unwrap()).unwrap(), Term::Natural(1));
+    assert!(matches!(context.execute(Abs("x", Var("x"))).unwrap(), Term::Closure { .. }));
+    assert_eq!(context.execute(App(Const(Term::Natural(1)), Const(Term::Natural(2)))).unwrap_err().error,
+        RuntimeError::NotAFunction(Term::Natural(1)));
+}
+
+#[test]
+fn test_access() {
+    let mut context = Context::new();
+    context.insert_term(String::from("s"), Term::Struct(BTreeMap::from([
+        (String::from("x"), Term::Integer(-1)), (String::from("y"), Term::Boolean(true))])));
+    context.insert_term(String::from("t"), Term::Tuple(vec![Term::Natural(0), Term::Boolean(false)],
+        vec![None, Some(String::from("flag"))]));
+    context.insert_term(String::from("a"), Term::Array(vec![Term::Natural(1), Term::Natural(2)]));
+    context.insert_term(String::from("l"), Term::List(vec![]));
+
+    assert_eq!(context.execute(parse_lambda("s.x").unwrap()).unwrap(), Term::Integer(-1));
+    assert_eq!(context.execute(parse_lambda("t.0").unwrap()).unwrap(), Term::Natural(0));
+    assert_eq!(context.execute(parse_lambda("t.flag").unwrap()).unwrap(), Term::Boolean(false));
+    assert_eq!(context.execute(parse_lambda("a[1]").unwrap()).unwrap(), Term::Natural(2));
+    assert_eq!(context.execute(parse_lambda("if s.y then a[t.0] else 0").unwrap()).unwrap(), Term::Natural(1));
+
+    assert_eq!(context.execute(parse_lambda("a[2]").unwrap()).unwrap_err().error,
+        RuntimeError::OutOfBounds { index: 2, len: 2 });
+    assert_eq!(context.execute(parse_lambda("l[0]").unwrap()).unwrap_err().error,
+        RuntimeError::OutOfBounds { index: 0, len: 0 });
+    assert_eq!(context.execute(parse_lambda("a[-1]").unwrap()).unwrap_err().error,
+        RuntimeError::InvalidIndex(Term::Integer(-1)));
+    assert_eq!(context.execute(parse_lambda("t.2").unwrap()).unwrap_err().error,
+        RuntimeError::NoField(context.get_term(&String::from("t")).unwrap().clone(), String::from("2")));
+    assert_eq!(context.execute(parse_lambda("t[0]").unwrap()).unwrap_err().error,
+        RuntimeError::NotIndexable(context.get_term(&String::from("t")).unwrap().clone()));
+}
+
+#[test]
+fn test_literals() {
+    let context = Context::new();
+    assert_eq!(context.execute(parse_lambda("{x = 1.5, y = \"two\"}.y").unwrap()).unwrap(), Term::String(String::from("two")));
+    assert_eq!(context.execute(parse_lambda("#[(0, name: 3)][0].name").unwrap()).unwrap(), Term::Natural(3));
+    assert_eq!(context.execute(parse_lambda("[[], [()]][1][0]").unwrap()).unwrap(), Term::Unit());
+}
+
+#[test]
+fn test_cases() {
+    let mut context = Context::new();
+    let case = "case u of x: int => ((add) x) 1 | b: bool => if b then 0 else 1";
+    context.insert_term(String::from("u"), Union(Term::Boolean(true)));
+    assert_eq!(context.execute(parse_lambda(case).unwrap()).unwrap(), Term::Natural(0));
+    context.insert_term(String::from("u"), Union(Term::Integer(-2)));
+    assert_eq!(context.execute(parse_lambda(case).unwrap()).unwrap(), Term::Integer(-1));
+    // untagged: the value itself is dispatched on, and arms are tried in order
+    context.insert_term(String::from("u"), Term::Natural(2));
+    assert_eq!(context.execute(parse_lambda(case).unwrap()).unwrap(), Term::Natural(3));
+    assert_eq!(context.execute(parse_lambda("case u of x: nat => 1 | y: int => 2").unwrap()).unwrap(), Term::Natural(1));
+    assert_eq!(context.execute(parse_lambda("case u of y: int => 2 | x: nat => 1").unwrap()).unwrap(), Term::Natural(2));
+    assert_eq!(context.execute(parse_lambda("case u of b: bool => b").unwrap()).unwrap_err().error,
+        RuntimeError::NoMatch(Term::Natural(2)));
+
+    let program = "func f(x): union[int, bool] -> int =\n  case x:\n    of i: int:\n      i\n    of b: bool:\n      if b:\n        1\n      else:\n        0\n\nf(true)\nf(-3)\n";
+    let results: Vec<Term> = parse_file(program).unwrap().lower().into_iter().map(|expr| {
+        assert!(context.check(expr.clone(), &Int).is_ok());
+        context.execute(expr).unwrap()
+    }).collect();
+    assert_eq!(results, vec![Term::Integer(1), Term::Integer(-3)]);
+}
+
+#[test]
+fn test_type_tests() {
+    let mut context = Context::new();
+    let test = "if u is int then ((add) u) 1 else 0";
+    context.insert_term(String::from("u"), Union(Term::Integer(-2)));
+    assert_eq!(context.execute(parse_lambda(test).unwrap()).unwrap(), Term::Integer(-1));
+    context.insert_term(String::from("u"), Union(Term::Boolean(true)));
+    assert_eq!(context.execute(parse_lambda(test).unwrap()).unwrap(), Term::Natural(0));
+    // values are tested by subtyping: naturals are integers, but not the other way around
+    assert_eq!(context.execute(parse_lambda("3 is int").unwrap()).unwrap(), Term::Boolean(true));
+    assert_eq!(context.execute(parse_lambda("-3 is nat").unwrap()).unwrap(), Term::Boolean(false));
+    assert_eq!(context.execute(parse_lambda("(λx. x) is int").unwrap()).unwrap(), Term::Boolean(false));
+
+    let program = "func f(x): union[int, bool] -> int =\n  if x is int:\n    x\n  else:\n    0\n\nf(-3)\nf(false)\n";
+    let results: Vec<Term> = parse_file(program).unwrap().lower().into_iter().map(|expr| {
+        assert!(context.check(expr.clone(), &Int).is_ok());
+        context.execute(expr).unwrap()
+    }).collect();
+    assert_eq!(results, vec![Term::Integer(-3), Term::Integer(0)]);
+}
+
+#[test]
+fn test_compounds() {
+    let mut context = Context::new();
+    context.insert_term(String::from("x"), Term::Natural(1));
+    let execute = |source: &str| context.execute(parse_lambda(source).unwrap()).unwrap();
+    assert_eq!(execute("[x, ((add) x) 1]"), Term::List(vec![Term::Natural(1), Term::Natural(2)]));
+    assert_eq!(execute("#[x]"), Term::Array(vec![Term::Natural(1)]));
+    assert_eq!(execute("{a = x, b = (not) true}"), Term::Struct(BTreeMap::from([
+        (String::from("a"), Term::Natural(1)), (String::from("b"), Term::Boolean(false))])));
+    assert_eq!(execute("(x, flag: false)"), Term::Tuple(vec![Term::Natural(1), Term::Boolean(false)],
+        vec![None, Some(String::from("flag"))]));
+    assert_eq!(execute("{a = [x, 2]}.a[1]"), Term::Natural(2));
+
+    let program = "func pair(x): int -> list[int] =\n  @[x, -1]\n\npair(3)[1]\n(a: pair(1), b: [true, false]).b[0]\n";
+    let results: Vec<Term> = parse_file(program).unwrap().lower().into_iter().map(|expr| {
+        assert!(context.infer(expr.clone()).is_ok());
+        context.execute(expr).unwrap()
+    }).collect();
+    assert_eq!(results, vec![Term::Integer(-1), Term::Boolean(true)]);
+}
+
+#[test]
+fn test_variants() {
+    let context = Context::new();
+    let get = "(λo. case o of <some = x> => x | <none = y> => 0): (<some: nat, none: unit> -> nat)";
+    assert_eq!(context.execute(parse_lambda(&format!("({}) <some = 3>", get)).unwrap()).unwrap(), Term::Natural(3));
+    assert_eq!(context.execute(parse_lambda(&format!("({}) <none = ()>", get)).unwrap()).unwrap(), Term::Natural(0));
+    assert_eq!(context.execute(parse_lambda("<some = ((add) 1) 2>").unwrap()).unwrap(),
+        Term::Variant(String::from("some"), Box::new(Term::Natural(3))));
+    assert_eq!(context.execute(parse_lambda("case <other = 1> of <some = x> => x").unwrap()).unwrap_err().error,
+        RuntimeError::NoMatch(Term::Variant(String::from("other"), Box::new(Term::Natural(1)))));
+
+    let program = "func get(o): <some: nat, none: unit> -> nat =\n  case o:\n    of <some = x>:\n      x\n    of <none = y>:\n      0\n\nget(<some = 3>)\nget(<none = ()>)\n";
+    let results: Vec<Term> = parse_file(program).unwrap().lower().into_iter().map(|expr| {
+        assert!(context.check(expr.clone(), &Nat).is_ok());
+        context.execute(expr).unwrap()
+    }).collect();
+    assert_eq!(results, vec![Term::Natural(3), Term::Natural(0)]);
+}
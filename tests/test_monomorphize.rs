@@ -0,0 +1,102 @@
+#![allow(non_upper_case_globals)]
+
+use chrysanthemum::ast::*;
+use chrysanthemum::error::*;
+use chrysanthemum::parser::*;
+use chrysanthemum::util::*;
+
+const program: &str = "
+func same(x, y): interface[func eq(Self): Self -> bool] -> interface[func eq(Self): Self -> bool] -> bool =
+  eq(x, y)
+
+func pick(c, x, y): bool -> generic[nat, int] -> generic[nat, int] -> generic[nat, int] =
+  if c:
+    x
+  else:
+    y
+
+same(1, 2)
+same(true, true)
+same(3, 3)
+pick(false, -1, -2)
+";
+
+#[test]
+fn test_specialization() {
+    let context = Context::new();
+    let result = context.monomorphize(&parse_file(program).unwrap()).unwrap();
+    let names: Vec<&str> = result.functions.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, vec!["same[nat, nat]", "same[bool, bool]", "pick[bool, int, int]"]);
+    for (_, func) in &result.functions {
+        let printed = func.to_string();
+        assert!(!printed.contains("interface") && !printed.contains("generic") && !printed.contains("Self"), "{}", printed);
+    }
+    assert_eq!(result.functions[0].1, Ann(Abs("x", Abs("y", App(App(Var("eq"), Var("x")), Var("y")))),
+        Func(Nat, Func(Nat, Bool))));
+
+    let results = [Term::Boolean(false), Term::Boolean(true), Term::Boolean(true), Term::Integer(-2)];
+    for (expr, result) in std::iter::zip(result.lower(), results) {
+        assert!(context.infer(expr.clone()).is_ok());
+        assert_eq!(context.execute(expr).unwrap(), result);
+    }
+}
+
+#[test]
+fn test_implementations() {
+    let mut context = Context::new();
+    let double = Ann(Abs("x", App(App(Var("add"), Var("x")), Var("x"))), Func(Nat, Nat));
+    context.insert_func(Signature { name: String::from("double"), from: Nat, to: Nat },
+        Implementation::Defined(double.clone()));
+    let source = "func quad(x): interface[func double(Self): Self] -> interface[func double(Self): Self] =\n  double(double(x))\n\nquad(3)\n";
+    let result = context.monomorphize(&parse_file(source).unwrap()).unwrap();
+    // calls to signatures are replaced with their implementations
+    assert_eq!(result.functions, vec![(String::from("quad[nat]"),
+        Ann(Abs("x", App(Ann(double.clone(), Func(Nat, Nat)), App(Ann(double, Func(Nat, Nat)), Var("x")))), Func(Nat, Nat)))]);
+    assert_eq!(context.execute(result.lower().remove(0)).unwrap(), Term::Natural(12));
+}
+
+#[test]
+fn test_instances() {
+    let mut context = Context::new();
+    let source = "func same(x, y): interface[func eq(Self): Self -> bool] -> interface[func eq(Self): Self -> bool] -> bool =\n  \
+        eq(x, y)\n\nimpl interface[func twins(Self): bool] for nat:\n  func twins(x) =\n    same(x, x)\n\ntwins(2)\n";
+    let result = context.monomorphize(&parse_file(source).unwrap()).unwrap();
+    // methods are specialized to the signatures they implement, with Self replaced by the implementing type
+    assert_eq!(result.functions.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), vec!["same[nat, nat]"]);
+    assert_eq!(result.instances[0].interface,
+        Type::Interface(vec![Signature { name: String::from("twins"), from: Nat, to: Bool }], None));
+    assert_eq!(result.instances[0].methods, vec![(String::from("twins"), Abs("x", App(App(Var("same[nat, nat]"), Var("x")), Var("x"))))]);
+    for instance in result.instances() {
+        context.implement(instance).unwrap();
+    }
+    assert_eq!(context.execute(result.lower().remove(0)).unwrap(), Term::Boolean(true));
+}
+
+#[test]
+fn test_program_implementations() {
+    let context = Context::new();
+    let source = "impl interface[func double(Self): Self] for nat:\n  func double(x) =\n    add(x, x)\n\n\
+        func quad(x): interface[func double(Self): Self] -> interface[func double(Self): Self] =\n  double(double(x))\n\nquad(3)\n";
+    // the only implementation is the program's own
+    let result = context.monomorphize(&parse_file(source).unwrap()).unwrap();
+    assert_eq!(result.functions.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), vec!["quad[nat]"]);
+    assert_eq!(context.execute(result.lower().remove(0)).unwrap(), Term::Natural(12));
+}
+
+#[test]
+fn test_monomorphization_errors() {
+    let context = Context::new();
+    let monomorphize = |expr: &str| context.monomorphize(&parse_file(&format!("{}{}\n", program, expr)).unwrap());
+    assert_eq!(monomorphize("same(1, true)").unwrap_err().error, TypeError::Mismatch { expected: Nat, found: Bool });
+    assert_eq!(monomorphize("pick(true, true, false)").unwrap_err().error,
+        TypeError::Mismatch { expected: Type::Generic(Some(vec![Nat, Int])), found: Bool });
+    assert_eq!(monomorphize("pick").unwrap_err().error, TypeError::Ambiguous(String::from("pick")));
+    assert_eq!(monomorphize("same(1)").unwrap_err().error, TypeError::Ambiguous(String::from("same")));
+    // interfaces cannot be specialized where they are implemented for, or packed as
+    let eqs = Type::Interface(vec![Signature { name: String::from("eq"), from: Type::Oneself, to: Func(Type::Oneself, Bool) }], None);
+    assert_eq!(context.monomorphize(&parse_file("impl interface[func twins(Self): bool] for interface[func eq(Self): Self -> bool]:\n  \
+        func twins(x) =\n    eq(x, x)\n").unwrap()).unwrap_err().error, TypeError::Polymorphic(eqs.clone()));
+    let packed = Program { functions: Vec::new(), instances: Vec::new(),
+        expressions: vec![parse_lambda("{*nat, 3} as interface[func eq(Self): Self -> bool]").unwrap()] };
+    assert_eq!(context.monomorphize(&packed).unwrap_err().error, TypeError::Polymorphic(eqs));
+}
@@ -1,6 +1,9 @@
 #![allow(non_upper_case_globals)]
 
+use std::collections::BTreeMap;
 use chrysanthemum::ast::*;
+use chrysanthemum::effects::*;
+use chrysanthemum::error::*;
 use chrysanthemum::parser::*;
 use chrysanthemum::util::*;
 
@@ -19,6 +22,8 @@ fn test_simple_phrases() {
 fn test_simple_annotations() {
     assert_eq!(parse_lambda("t: int").unwrap(), Ann(Var("t"), Int));
     assert_eq!(parse_lambda("12: nat").unwrap(), Ann(Const(Term::Natural(12)), Nat));
+    assert!(parse_lambda("t: 3").is_err());
+    // any other name is a type variable
     assert!(parse_lambda("t: fake").is_err());
 }
 
@@ -50,3 +55,366 @@ fn test_complex_annotations() {
     assert_eq!(parse_lambda("(lambda x. if x then 1: int else 0: int): (bool -> int)").unwrap(), Ann(Abs("x", Cond(Var("x"), Ann(Const(Term::Natural(1)), Int), Ann(Const(Term::Natural(0)), Int))), Func(Bool, Int)));
     assert_eq!(parse_lambda("(lambda x. if x then false else true): (bool -> bool)").unwrap(), Ann(Abs("x", Cond(Var("x"), Const(Term::Boolean(false)), Const(Term::Boolean(true)))), Func(Bool, Bool)));
 }
+
+fn tokens(input: &str) -> Vec<Token> {
+    lex(input).unwrap().into_iter().map(|(token, _)| token).collect()
+}
+
+#[test]
+fn test_lexing_files() {
+    assert!(lex(include_str!("src/fib.nim")).is_ok());
+    assert!(lex(include_str!("src/negate.nim")).is_ok());
+    assert_eq!(tokens(include_str!("src/negate.nim")), vec![
+        Token::Keyword("func".into()), Token::Identifier("negate".into()),
+        Token::Separator("(".into()), Token::Identifier("x".into()), Token::Separator(")".into()),
+        Token::Separator(":".into()), Token::Identifier("bool".into()), Token::Operator("->".into()),
+        Token::Identifier("bool".into()), Token::Operator("=".into()),
+        Token::ScopeBegin, Token::Keyword("if".into()), Token::Identifier("x".into()), Token::Separator(":".into()),
+        Token::ScopeBegin, Token::Value("0".into()), Token::ExprEnd, Token::ScopeEnd,
+        Token::Keyword("else".into()), Token::Separator(":".into()),
+        Token::ScopeBegin, Token::Value("1".into()), Token::ExprEnd, Token::ScopeEnd, Token::ScopeEnd
+    ]);
+}
+
+#[test]
+fn test_lexing_indentation() {
+    // dedenting across several levels closes every scope
+    assert_eq!(tokens("a:\n  b:\n    c\nd"), vec![
+        Token::Identifier("a".into()), Token::Separator(":".into()), Token::ScopeBegin,
+        Token::Identifier("b".into()), Token::Separator(":".into()), Token::ScopeBegin,
+        Token::Identifier("c".into()), Token::ExprEnd, Token::ScopeEnd, Token::ScopeEnd,
+        Token::Identifier("d".into()), Token::ExprEnd
+    ]);
+    // blank lines, comments, and newlines within brackets don't count
+    assert_eq!(tokens("f(a,\n      b)\n\n   # hi\ng # hi"), vec![
+        Token::Identifier("f".into()), Token::Separator("(".into()), Token::Identifier("a".into()),
+        Token::Separator(",".into()), Token::Identifier("b".into()), Token::Separator(")".into()),
+        Token::ExprEnd, Token::Identifier("g".into()), Token::ExprEnd
+    ]);
+    assert_eq!(lex("a:\n    b\n  c").unwrap_err().error, ParseError::InconsistentIndentation(2));
+    assert_eq!(lex("  a").unwrap_err().error, ParseError::UnexpectedIndentation);
+    assert_eq!(lex("a:\n\tb").unwrap_err().error, ParseError::Tab);
+    assert_eq!(lex("f(a))").unwrap_err().error, ParseError::UnbalancedBracket(')'));
+}
+
+#[test]
+fn test_parsing_files() {
+    assert_eq!(parse_file(include_str!("src/negate.nim")).unwrap(), Program {
+        functions: vec![(String::from("negate"), Ann(Abs("x",
+            Cond(Var("x"), Const(Term::Natural(0)), Const(Term::Natural(1)))), Func(Bool, Bool)))],
+        instances: vec![],
+        expressions: vec![]
+    });
+    let fib = parse_file(include_str!("src/fib.nim")).unwrap();
+    assert_eq!(fib.functions.len(), 1);
+    assert_eq!(fib.expressions, vec![
+        App(Var("negate"), App(Var("negate"), Const(Term::Natural(1)))),
+        App(Var("fib"), Const(Term::Natural(5)))
+    ]);
+    // multiple arguments are curried
+    assert_eq!(parse_file("f(a, -1)(b)").unwrap().expressions,
+        vec![App(App(App(Var("f"), Var("a")), Const(Term::Integer(-1))), Var("b"))]);
+    assert_eq!(parse_file("func k(x, y): int -> bool -> int = x").unwrap().functions,
+        vec![(String::from("k"), Ann(Abs("x", Abs("y", Var("x"))), Func(Int, Func(Bool, Int))))]);
+    // implementations take the types of their methods from the interface
+    let program = parse_file("impl interface[func double(Self): Self] for nat:\n  func double(x) =\n    add(x, x)\n\ndouble(2)\n").unwrap();
+    assert_eq!(program.instances, vec![Instance {
+        interface: Type::Interface(vec![Signature { name: String::from("double"), from: Type::Oneself, to: Type::Oneself }], None),
+        kind: Nat,
+        methods: vec![(String::from("double"), Abs("x", App(App(Var("add"), Var("x")), Var("x"))))],
+        span: Span::default()
+    }]);
+    assert_eq!(program.expressions, vec![App(Var("double"), Const(Term::Natural(2)))]);
+    assert!(parse_file("impl interface[func double(Self): Self] for nat:\n  double(2)\n").is_err());
+    assert!(parse_file("func f(x): int =\n  x\n  x").is_err());
+    assert!(parse_file("if x:\n  y").is_err());
+    // strings and floats, of which decimal points are part
+    assert_eq!(parse_file("print(\"hi\")\nadd(1.5, -2.5)\n").unwrap().expressions, vec![
+        App(Var("print"), Const(Str("hi"))),
+        App(App(Var("add"), Const(Term::Float(1.5))), Const(Term::Float(-2.5)))
+    ]);
+    assert_eq!(tokens("1.5 t.0.1"), vec![Token::Value("1.5".into()), Token::Identifier("t".into()),
+        Token::Separator(".".into()), Token::Value("0".into()), Token::Separator(".".into()), Token::Value("1".into()),
+        Token::ExprEnd]);
+}
+
+#[test]
+fn test_spans() {
+    // spans are in bytes: λ is two of them
+    let expr = parse_lambda("(λx. if x then y else z) 12").unwrap();
+    assert_eq!((expr.span().start, expr.span().end), (0, 28));
+    match expr {
+        Expression::Application { func, arg, .. } => {
+            assert_eq!((func.span().start, func.span().end), (1, 24));
+            assert_eq!((arg.span().start, arg.span().end), (26, 28));
+        },
+        _ => panic!("expected an application")
+    }
+    let spans: Vec<(usize, usize)> = lex("f(ab, \"c\")\n  'd'").unwrap().into_iter()
+        .map(|(_, span)| (span.start, span.end)).collect();
+    assert_eq!(spans, vec![(0, 1), (1, 2), (2, 4), (4, 5), (6, 9), (9, 10), (13, 13), (13, 16), (16, 16), (16, 16)]);
+    let program = "func f(x): int -> int =\n  g(x)\n";
+    let func = &parse_file(program).unwrap().functions[0].1;
+    assert_eq!((func.span().start, func.span().end), (0, 30));
+}
+
+#[test]
+fn test_bindings() {
+    assert_eq!(parse_lambda("let x = 1 in x").unwrap(), Let("x", None, Const(Term::Natural(1)), Var("x")));
+    assert_eq!(parse_lambda("let x: int = 1 in if y then x else -1").unwrap(),
+        Let("x", Some(Int), Const(Term::Natural(1)), Cond(Var("y"), Var("x"), Const(Term::Integer(-1)))));
+    assert_eq!(parse_lambda("let f: (int -> int) = λx. x in (f) 2").unwrap(),
+        Let("f", Some(Func(Int, Int)), Abs("x", Var("x")), App(Var("f"), Const(Term::Natural(2)))));
+    assert_eq!(parse_lambda("(let x = true in x): bool").unwrap(),
+        Ann(Let("x", None, Const(Term::Boolean(true)), Var("x")), Bool));
+}
+
+#[test]
+fn test_recursion() {
+    assert_eq!(parse_lambda("fix f").unwrap(), Fix(Var("f")));
+    assert_eq!(parse_lambda("fixed").unwrap(), Var("fixed"));
+    assert_eq!(parse_lambda("letrec f: (bool -> bool) = λx. (f) x in (f) true").unwrap(),
+        Let("f", Some(Func(Bool, Bool)),
+            Fix(Ann(Abs("f", Abs("x", App(Var("f"), Var("x")))), Func(Func(Bool, Bool), Func(Bool, Bool)))),
+            App(Var("f"), Const(Term::Boolean(true)))));
+}
+
+#[test]
+fn test_access() {
+    assert_eq!(parse_lambda("s.field").unwrap(), Access(Var("s"), "field"));
+    assert_eq!(parse_lambda("t.0.name").unwrap(), Access(Access(Var("t"), "0"), "name"));
+    assert_eq!(parse_lambda("a[1]").unwrap(), Index(Var("a"), Const(Term::Natural(1))));
+    assert_eq!(parse_lambda("(f) a[i].x").unwrap(), App(Var("f"), Access(Index(Var("a"), Var("i")), "x")));
+    assert_eq!(parse_lambda("((f) a).x").unwrap(), Access(App(Var("f"), Var("a")), "x"));
+    assert_eq!(parse_lambda("s.x: int").unwrap(), Ann(Access(Var("s"), "x"), Int));
+
+    let program = parse_file("f(s.x, t.0)[i]\n").unwrap();
+    assert_eq!(program.expressions, vec![
+        Index(App(App(Var("f"), Access(Var("s"), "x")), Access(Var("t"), "0")), Var("i"))]);
+    let expr = &parse_file("a.b[0]\n").unwrap().expressions[0];
+    assert_eq!((expr.span().start, expr.span().end), (0, 6));
+}
+
+#[test]
+fn test_literals() {
+    assert_eq!(parse_lambda("2.75").unwrap(), Const(Term::Float(2.75)));
+    assert_eq!(parse_lambda("-0.5").unwrap(), Const(Term::Float(-0.5)));
+    // numbers out of range are errors: the least integer is not
+    assert_eq!(parse_lambda("-9223372036854775808").unwrap(), Const(Term::Integer(isize::MIN)));
+    assert!(parse_lambda("-9223372036854775809").is_err());
+    assert!(parse_lambda("99999999999999999999").is_err());
+    assert_eq!(parse_file("-9223372036854775808\n").unwrap().expressions, vec![Const(Term::Integer(isize::MIN))]);
+    assert!(parse_file("-9223372036854775809\n").is_err());
+    assert_eq!(parse_lambda("\"hello\\tworld\\n\"").unwrap(), Const(Term::String(String::from("hello\tworld\n"))));
+    assert_eq!(parse_lambda("\"say \\\"hi\\\"\"").unwrap(), Const(Term::String(String::from("say \"hi\""))));
+    assert!(parse_lambda("\"\\q\"").is_err());
+    assert_eq!(parse_lambda("()").unwrap(), Const(Term::Unit()));
+    assert_eq!(parse_lambda("[1, 2]").unwrap(), Const(Term::List(vec![Term::Natural(1), Term::Natural(2)])));
+    assert_eq!(parse_lambda("[]").unwrap(), Const(Term::List(vec![])));
+    assert_eq!(parse_lambda("#[true,false]").unwrap(),
+        Const(Term::Array(vec![Term::Boolean(true), Term::Boolean(false)])));
+    assert_eq!(parse_lambda("{x = 1, y = true}").unwrap(), Const(Term::Struct(BTreeMap::from([
+        (String::from("x"), Term::Natural(1)), (String::from("y"), Term::Boolean(true))]))));
+    assert_eq!(parse_lambda("(1, name: 2)").unwrap(), Const(Term::Tuple(
+        vec![Term::Natural(1), Term::Natural(2)], vec![None, Some(String::from("name"))])));
+    assert_eq!(parse_lambda("(1,)").unwrap(), Const(Term::Tuple(vec![Term::Natural(1)], vec![None])));
+    assert_eq!(parse_lambda("(1)").unwrap(), Const(Term::Natural(1)));
+    assert_eq!(parse_lambda("[(1, 2), (3, 4)]").unwrap(), Const(Term::List(vec![
+        Term::Tuple(vec![Term::Natural(1), Term::Natural(2)], vec![None, None]),
+        Term::Tuple(vec![Term::Natural(3), Term::Natural(4)], vec![None, None])])));
+    // literals compose with everything else
+    assert_eq!(parse_lambda("{x = -1}.x").unwrap(), Access(Const(Term::Struct(BTreeMap::from([
+        (String::from("x"), Term::Integer(-1))]))), "x"));
+    assert_eq!(parse_lambda("(f) [1]").unwrap(), App(Var("f"), Const(Term::List(vec![Term::Natural(1)]))));
+    assert_eq!(parse_lambda("let s = \"str\" in s").unwrap(), Let("s", None, Const(Term::String(String::from("str"))), Var("s")));
+}
+
+fn option(kind: Type) -> Type {
+    Type::Variant(BTreeMap::from([(String::from("some"), kind), (String::from("none"), Unit)]))
+}
+
+#[test]
+fn test_types() {
+    let sig = |name: &str, from: Type, to: Type| Signature { name: String::from(name), from, to };
+    let kinds = vec![
+        Empty, Type::Error, Unit, Bool, Nat, Int, Type::Float, Type::String, Type::Oneself,
+        Type::List(Box::new(Int)),
+        Type::Array(Box::new(Type::List(Box::new(Bool))), 3),
+        Type::Slice(Box::new(Nat)),
+        Type::Union(vec![Int, Bool, Unit]),
+        Type::Struct(BTreeMap::from([(String::from("x"), Int), (String::from("y"), Func(Int, Bool))])),
+        Type::Tuple(vec![Nat, Bool], vec![None, Some(String::from("flag"))]),
+        Type::Tuple(vec![], vec![]),
+        Type::Interface(vec![sig("show", Type::Oneself, Type::String), sig("add", Int, Func(Int, Int))], None),
+        Type::Interface(vec![sig("eq", Type::Oneself, Bool)], Some(Box::new(Int))),
+        Type::Generic(None),
+        Type::Generic(Some(vec![Int, Type::Float])),
+        Func(Int, Func(Int, Int)),
+        Func(Func(Int, Int), Int),
+        Func(Type::List(Box::new(Func(Bool, Bool))), Type::Oneself),
+        Effectful(Type::String, Unit, &[Effect::Io]),
+        Effectful(Int, Effectful(Int, Int, &[Effect::Exn, Effect::Div]), &[]),
+        Func(Effectful(Unit, Int, &[Effect::State, Effect::Io]), Int),
+        option(Int),
+        Type::Variant(BTreeMap::new()),
+        Type::Variant(BTreeMap::from([(String::from("a"), option(Bool))])),
+        Func(option(Int), option(Func(Int, Int))),
+        Effectful(option(Int), Int, &[Effect::Exn]),
+    ];
+    // every printed type parses back to itself
+    for kind in kinds {
+        assert_eq!(parse_lambda(&format!("x: {}", kind)).unwrap(), Ann(Var("x"), kind.clone()), "{}", kind);
+        if kind != Empty && kind != Type::Error {
+            let program = parse_file(&format!("func f(x): {} =\n  x\n", kind)).unwrap();
+            assert_eq!(program.functions[0].1, Ann(Abs("x", Var("x")), kind.clone()), "{}", kind);
+        }
+    }
+
+    // the arrow is right-associative, with optional spaces and brackets
+    assert_eq!(parse_lambda("x: int -> bool -> unit").unwrap(), Ann(Var("x"), Func(Int, Func(Bool, Unit))));
+    assert_eq!(parse_lambda("x: (int -> bool) -> unit").unwrap(), Ann(Var("x"), Func(Func(Int, Bool), Unit)));
+    assert_eq!(parse_lambda("x: list[ int ]").unwrap(), Ann(Var("x"), Type::List(Box::new(Int))));
+    assert_eq!(Func(Func(Int, Bool), Unit).to_string(), "(int->bool)->unit");
+    assert!(parse_lambda("x: integer").is_err());
+    assert!(parse_lambda("x: array[int]").is_err());
+    assert!(parse_lambda("x: array[int, 99999999999999999999999]").is_err());
+}
+
+#[test]
+fn test_cases() {
+    let case = Case(Var("u"), vec![
+        (Member("x", Int), Var("x")),
+        (Member("b", Type::Union(vec![Bool, Unit])), Cond(Var("b"), Const(Term::Natural(1)), Const(Term::Natural(0))))
+    ]);
+    assert_eq!(parse_lambda("case u of x: int => x | b: union[bool, unit] => if b then 1 else 0").unwrap(), case);
+    assert_eq!(parse_lambda("(case u of x: int => x | b: union[bool, unit] => if b then 1 else 0): int").unwrap(),
+        Ann(case.clone(), Int));
+    assert_eq!(parse_lambda("λu. case u of x: int => x").unwrap(), Abs("u", Case(Var("u"), vec![(Member("x", Int), Var("x"))])));
+
+    let program = "case u:\n  of x: int:\n    x\n  of b: union[bool, unit]:\n    if b:\n      1\n    else:\n      0\n";
+    assert_eq!(parse_file(program).unwrap().expressions, vec![case]);
+    assert!(parse_file("case u:\n  x\n").is_err());
+}
+
+#[test]
+fn test_type_tests() {
+    let is = |expr: Expression, kind: Type| Expression::Is { expr: Box::new(expr), kind, span: Span::default() };
+    assert_eq!(parse_lambda("x is int").unwrap(), is(Var("x"), Int));
+    assert_eq!(parse_lambda("if x is union[int, bool] then x else 0").unwrap(),
+        Cond(is(Var("x"), Type::Union(vec![Int, Bool])), Var("x"), Const(Term::Natural(0))));
+    assert_eq!(parse_lambda("((f) x) is int -> int").unwrap(), is(App(Var("f"), Var("x")), Func(Int, Int)));
+    let program = "if x is int:\n  x\nelse:\n  0\n";
+    assert_eq!(parse_file(program).unwrap().expressions, vec![Cond(is(Var("x"), Int), Var("x"), Const(Term::Natural(0)))]);
+    assert_eq!(parse_file("f(x) is int\n").unwrap().expressions, vec![is(App(Var("f"), Var("x")), Int)]);
+    // displayed as parsed
+    assert_eq!(format!("{}", parse_lambda("x is int").unwrap()), "(x is int)");
+}
+
+#[test]
+fn test_compounds() {
+    let span = Span::default();
+    let list = |data: Vec<Expression>| Expression::List { data, span };
+    let one = Const(Term::Natural(1));
+    assert_eq!(parse_lambda("[x, 1]").unwrap(), list(vec![Var("x"), one.clone()]));
+    assert_eq!(parse_lambda("#[ x ]").unwrap(), Expression::Array { data: vec![Var("x")], span });
+    assert_eq!(parse_lambda("{a = x, b = 1}").unwrap(), Expression::Struct { data: BTreeMap::from([
+        (String::from("a"), Var("x")), (String::from("b"), one.clone())]), span });
+    assert_eq!(parse_lambda("(x, flag: (f) x)").unwrap(), Expression::Tuple {
+        data: vec![Var("x"), App(Var("f"), Var("x"))], fields: vec![None, Some(String::from("flag"))], span });
+    assert_eq!(parse_lambda("[[x], [1]]").unwrap(), list(vec![list(vec![Var("x")]), Const(Term::List(vec![Term::Natural(1)]))]));
+    assert_eq!(parse_lambda("[x][0]").unwrap(), Index(list(vec![Var("x")]), Const(Term::Natural(0))));
+    assert_eq!(parse_lambda("[x]: list[int]").unwrap(), Ann(list(vec![Var("x")]), Type::List(Box::new(Int))));
+    // literals are still constants
+    assert_eq!(parse_lambda("[1]").unwrap(), Const(Term::List(vec![Term::Natural(1)])));
+
+    let program = "@[x, 1]\n[x]\n(x, flag: f(x))\n{a = x}\n@[]\n";
+    assert_eq!(parse_file(program).unwrap().expressions, vec![
+        list(vec![Var("x"), one.clone()]),
+        Expression::Array { data: vec![Var("x")], span },
+        Expression::Tuple { data: vec![Var("x"), App(Var("f"), Var("x"))], fields: vec![None, Some(String::from("flag"))], span },
+        Expression::Struct { data: BTreeMap::from([(String::from("a"), Var("x"))]), span },
+        list(vec![])]);
+    // displayed as parsed
+    assert_eq!(format!("{}", parse_lambda("[x, (y, flag: {a = x})]").unwrap()), "[x, (y, flag: {a = x})]");
+    assert_eq!(format!("{}", parse_lambda("#[(x,)]").unwrap()), "#[(x,)]");
+}
+
+#[test]
+fn test_polymorphism() {
+    let var = |id: &str| Type::Variable(String::from(id));
+    let forall = |id: &str, kind: Type| Type::Forall(String::from(id), Box::new(Type::Empty), Box::new(kind));
+    let tabs = |param: &str, expr: Expression| Expression::TypeAbstraction { param: String::from(param), bound: Type::Empty, expr: Box::new(expr), span: Span::default() };
+    let tapp = |expr: Expression, kind: Type| Expression::TypeApplication { expr: Box::new(expr), kind, span: Span::default() };
+    let id = forall("a", Func(var("a"), var("a")));
+    assert_eq!(parse_lambda("x: forall a. a -> a").unwrap(), Ann(Var("x"), id.clone()));
+    assert_eq!(parse_lambda("x: (forall a. a) -> forall b. b").unwrap(), Ann(Var("x"), Func(forall("a", var("a")), forall("b", var("b")))));
+    assert_eq!(parse_lambda("Λa. λx. x").unwrap(), tabs("a", Abs("x", Var("x"))));
+    assert_eq!(parse_lambda("Lambda a. x").unwrap(), tabs("a", Var("x")));
+    assert_eq!(parse_lambda("id[int]").unwrap(), tapp(Var("id"), Int));
+    assert_eq!(parse_lambda("Λa. f[list[a]][int -> int]").unwrap(),
+        tabs("a", tapp(tapp(Var("f"), Type::List(Box::new(var("a")))), Func(Int, Int))));
+    assert_eq!(parse_lambda("(id[int]) 1").unwrap(), App(tapp(Var("id"), Int), Const(Term::Natural(1))));
+    // bare identifiers are indices
+    assert_eq!(parse_lambda("xs[i]").unwrap(), Index(Var("xs"), Var("i")));
+    assert_eq!(parse_lambda("Λa. f[(a)]").unwrap(), tabs("a", tapp(Var("f"), var("a"))));
+    assert_eq!(parse_lambda("f[(a)]").unwrap(), Index(Var("f"), Var("a")));
+    // type variables must be bound by an enclosing quantifier or type abstraction
+    assert!(parse_lambda("(λx. x): (integer -> integer)").is_err());
+    assert!(parse_lambda("x: (forall a. a) -> a").is_err());
+    assert!(parse_lambda("(Λa. λx. x): (a -> a)").is_err());
+    assert_eq!(parse_lambda("Λa. x: a").unwrap(), tabs("a", Ann(Var("x"), var("a"))));
+    assert!(parse_file("func id(x): a -> a =\n  x\n").is_err());
+    assert!(parse_file("func id[a](x): a -> a =\n  x is a\n").is_ok());
+
+    let program = "func id[a](x): a -> a =\n  x\n\nid[int](1)\nxs[i]\n";
+    let program = parse_file(program).unwrap();
+    assert_eq!(program.functions[0].1, Ann(tabs("a", Ann(Abs("x", Var("x")), Func(var("a"), var("a")))), id.clone()));
+    assert_eq!(program.expressions, vec![App(tapp(Var("id"), Int), Const(Term::Natural(1))), Index(Var("xs"), Var("i"))]);
+    assert!(parse_file("x: forall a. a -> a\n").is_err());
+    // displayed as parsed
+    assert_eq!(id.to_string(), "forall a. a->a");
+    assert_eq!(Func(id.clone(), Int).to_string(), "(forall a. a->a)->int");
+    assert_eq!(format!("{}", parse_lambda("id[int]").unwrap()), "id[int]");
+
+    // quantifiers may be bounded
+    let bounded = |id: &str, bound: Type, kind: Type| Type::Forall(String::from(id), Box::new(bound), Box::new(kind));
+    let shows = Type::Interface(vec![Signature { name: String::from("show"), from: Type::Oneself, to: Type::String }], None);
+    let show = bounded("a", shows.clone(), Func(var("a"), Type::String));
+    assert_eq!(parse_lambda("x: forall a <: interface[func show(Self): str]. a -> str").unwrap(), Ann(Var("x"), show.clone()));
+    assert_eq!(parse_lambda("Λa <: int. x").unwrap(), Expression::TypeAbstraction {
+        param: String::from("a"), bound: Int, expr: Box::new(Var("x")), span: Span::default() });
+    let program = parse_file("func show[a <: interface[func show(Self): str], b](x, y): a -> b -> str =\n  show(x)\n").unwrap();
+    assert!(matches!(&program.functions[0].1, Expression::Annotation { kind, .. }
+        if *kind == bounded("a", shows.clone(), forall("b", Func(var("a"), Func(var("b"), Type::String))))));
+    assert_eq!(show.to_string(), "forall a <: interface[func show(Self): str]. a->str");
+    assert_eq!(format!("{}", parse_lambda("Λa <: int. x").unwrap()), "(Λa <: int.x)");
+
+    // and packed and unpacked as existentials
+    assert_eq!(parse_lambda("{*int, x} as interface[func show(Self): str]").unwrap(), Expression::Pack {
+        witness: Int, expr: Box::new(Var("x")), kind: shows.clone(), span: Span::default() });
+    assert_eq!(parse_lambda("let {X, x} = p in x").unwrap(), Expression::Unpack {
+        param: String::from("X"), id: String::from("x"), expr: Box::new(Var("p")), body: Box::new(Var("x")), span: Span::default() });
+    assert_eq!(format!("{}", parse_lambda("{*int, x} as interface[func show(Self): str]").unwrap()),
+        "({*int, x} as interface[func show(Self): str])");
+    assert_eq!(format!("{}", parse_lambda("let {X, x} = p in (show) x").unwrap()), "(let {X, x} = p in (show x))");
+}
+
+#[test]
+fn test_variants() {
+    let inject = |label: &str, expr: Expression| Expression::Injection { label: String::from(label), expr: Box::new(expr), span: Span::default() };
+    assert_eq!(parse_lambda("<some = 3>").unwrap(), inject("some", Const(Term::Natural(3))));
+    assert_eq!(parse_lambda("< none = () >").unwrap(), inject("none", Const(Term::Unit())));
+    assert_eq!(parse_lambda("<some = <ok = x>>: <some: <ok: int>>").unwrap(), Ann(inject("some", inject("ok", Var("x"))),
+        Type::Variant(BTreeMap::from([(String::from("some"), Type::Variant(BTreeMap::from([(String::from("ok"), Int)])))]))));
+    let label = |label: &str, id: &str| Pattern::Label(String::from(label), String::from(id));
+    let case = Case(Var("o"), vec![(label("some", "x"), Var("x")), (label("none", "y"), Const(Term::Natural(0)))]);
+    assert_eq!(parse_lambda("case o of <some = x> => x | <none = y> => 0").unwrap(), case);
+    let program = "func get(o): <some: nat, none: unit> -> nat =\n  case o:\n    of <some = x>:\n      x\n    of <none = y>:\n      0\n\nget(<some = 3>)\n";
+    let program = parse_file(program).unwrap();
+    assert_eq!(program.functions[0].1, Ann(Abs("o", case), Func(Type::Variant(BTreeMap::from([
+        (String::from("some"), Nat), (String::from("none"), Unit)])), Nat)));
+    assert_eq!(program.expressions, vec![App(Var("get"), inject("some", Const(Term::Natural(3))))]);
+    // displayed as parsed
+    assert_eq!(format!("{}", parse_lambda("case o of <some = x> => <ok = x> | <none = y> => 0").unwrap()),
+        "(case o of <some = x> => <ok = x> | <none = y> => 'Natural(0))");
+}
@@ -0,0 +1,319 @@
+use std::collections::{BTreeMap, HashMap};
+use chrysanthemum::ast::*;
+use chrysanthemum::error::*;
+use chrysanthemum::parser::*;
+use chrysanthemum::util::*;
+
+fn var(id: &str) -> Type {
+    Type::Variable(String::from(id))
+}
+
+fn forall(id: &str, kind: Type) -> Type {
+    Type::Forall(String::from(id), Box::new(Type::Empty), Box::new(kind))
+}
+
+fn bounded(id: &str, bound: Type, kind: Type) -> Type {
+    Type::Forall(String::from(id), Box::new(bound), Box::new(kind))
+}
+
+const IDENTITY: &str = "(Λa. λx. x): (forall a. a -> a)";
+const COMPOSE: &str = "(Λa. Λb. Λc. λf. λg. λx. (f) (g) x): (forall a. forall b. forall c. (b -> c) -> (a -> b) -> a -> c)";
+const MAP: &str = "(Λa. Λb. λf. λxs. [(f) xs[0], (f) xs[1]]): (forall a. forall b. (a -> b) -> list[a] -[exn]-> list[b])";
+
+#[test]
+fn test_substitution() {
+    let with = |id: &str, kind: Type| HashMap::from([(String::from(id), kind)]);
+    let kind = forall("a", Func(var("a"), var("b")));
+    assert_eq!(kind.free(), vec![String::from("b")]);
+    // bound variables are not substituted for
+    assert_eq!(kind.substitute(&with("a", Int)), kind);
+    assert_eq!(kind.substitute(&with("b", Int)), forall("a", Func(var("a"), Int)));
+    // nor are free variables captured: the quantifier is renamed instead
+    assert_eq!(kind.substitute(&with("b", var("a"))), forall("a1", Func(var("a1"), var("a"))));
+    assert_eq!(kind.substitute(&with("b", Func(var("a"), var("a1")))),
+        forall("a2", Func(var("a2"), Func(var("a"), var("a1")))));
+
+    // quantified types are equal up to renaming
+    let context = Context::new();
+    assert!(context.subtype(&forall("a", Func(var("a"), var("a"))), &forall("b", Func(var("b"), var("b")))));
+    assert!(!context.subtype(&forall("a", Func(var("a"), var("a"))), &forall("b", Func(var("b"), Int))));
+    assert!(!context.subtype(&forall("a", Func(var("a"), var("b"))), &forall("b", Func(var("b"), var("b")))));
+}
+
+#[test]
+fn test_system_f() {
+    let context = Context::new();
+    let infer = |source: &str| context.infer(parse_lambda(source).unwrap());
+    assert_eq!(infer(IDENTITY).unwrap(), forall("a", Func(var("a"), var("a"))));
+    assert_eq!(infer(&format!("({})[int]", IDENTITY)).unwrap(), Func(Int, Int));
+    assert_eq!(infer(&format!("(({})[list[bool]]) [true]", IDENTITY)).unwrap(), Type::List(Box::new(Bool)));
+    // both occurrences of the variable are tied together: unlike generic[]
+    assert_eq!(infer(&format!("(({})[int]) true", IDENTITY)).unwrap_err().error,
+        TypeError::Mismatch { expected: Int, found: Bool });
+    assert_eq!(infer(COMPOSE).unwrap(), forall("a", forall("b", forall("c",
+        Func(Func(var("b"), var("c")), Func(Func(var("a"), var("b")), Func(var("a"), var("c"))))))));
+    assert_eq!(infer(&format!("({})[int][int][bool]", COMPOSE)).unwrap(),
+        Func(Func(Int, Bool), Func(Func(Int, Int), Func(Int, Bool))));
+    assert_eq!(infer(&format!("((({})[nat][bool]) (λn. ((eq) n) 0)) [1, 0]", MAP)).unwrap(),
+        Type::List(Box::new(Bool)));
+    // type applications substitute without capture
+    assert_eq!(infer("Λb. ((Λa. Λb. λx. x): (forall a. forall b. a -> a))[(b)]").unwrap(),
+        forall("b", forall("b1", Func(var("b"), var("b")))));
+
+    assert_eq!(infer("((λx. x): (int -> int))[int]").unwrap_err().error, TypeError::NotUniversal(Func(Int, Int)));
+    // and are checked for every type at once
+    assert_eq!(infer("(Λa. λx. 1): (forall a. a -> a)").unwrap_err().error,
+        TypeError::Mismatch { expected: var("a"), found: Nat });
+    assert_eq!(infer("(Λa. λx. x): (forall a. a -> int)").unwrap_err().error,
+        TypeError::Mismatch { expected: Int, found: var("a") });
+}
+
+#[test]
+fn test_higher_rank() {
+    let context = Context::new();
+    let infer = |source: &str| context.infer(parse_lambda(source).unwrap());
+    let pair = Type::Tuple(vec![Int, Bool], vec![None, None]);
+    // polymorphic functions are instantiated implicitly when applied
+    assert_eq!(infer(&format!("({}) 1", IDENTITY)).unwrap(), Nat);
+    assert_eq!(infer(&format!("(({}) (λx. x)) true", IDENTITY)).unwrap(), Bool);
+    assert_eq!(infer(&format!("((({})[int][int][bool]) (λn. ((lt) n) 0)) ({})", COMPOSE, IDENTITY)).unwrap(),
+        Func(Int, Bool));
+    assert_eq!(infer(&format!("(λx. ({}) x): (union[int, bool] -> union[int, bool])", IDENTITY)).unwrap(),
+        Func(Type::Union(vec![Int, Bool]), Type::Union(vec![Int, Bool])));
+    assert_eq!(infer(&format!("(({}) 1) true", IDENTITY)).unwrap_err().error,
+        TypeError::NotAFunction(Nat));
+    // what instantiation leaves unsolved is generalized, and nothing is left in the context between judgements
+    assert_eq!(infer(&format!("({}) (λx. x)", IDENTITY)).unwrap(), forall("a", Func(var("a"), var("a"))));
+    assert_eq!(infer(&format!("(({}) ({})) 1", IDENTITY, IDENTITY)).unwrap(), Nat);
+    assert_eq!(infer(&format!("((({})[int][int][nat]) (λb. 1)) ({})", COMPOSE, IDENTITY)).unwrap(), Func(Int, Nat));
+    assert!(context.get_ordered().borrow().is_empty());
+
+    // polymorphic arguments may be used at many types
+    let rank2 = "(λf. ((f) 1, (f) true)): ((forall a. a -> a) -> tuple[int, bool])";
+    assert_eq!(infer(rank2).unwrap(), Func(forall("a", Func(var("a"), var("a"))), pair.clone()));
+    assert_eq!(infer(&format!("({}) (λx. x)", rank2)).unwrap(), pair.clone());
+    assert_eq!(infer(&format!("({}) ({})", rank2, IDENTITY)).unwrap(), pair.clone());
+    // but only polymorphic functions may be passed for them
+    assert_eq!(infer(&format!("({}) ((λx. x): (int -> int))", rank2)).unwrap_err().error,
+        TypeError::Mismatch { expected: Func(var("a"), var("a")), found: Func(Int, Int) });
+    assert!(infer(&format!("({}) (λx. 1)", rank2)).is_err());
+    assert!(infer("(λf. ((f) 1, (f) true)): ((int -> int) -> tuple[int, bool])").is_err());
+}
+
+#[test]
+fn test_polymorphic_subtyping() {
+    let context = Context::new();
+    let identity = forall("a", Func(var("a"), var("a")));
+    // quantified types are subtypes of their instances
+    assert!(context.subtype(&identity, &Func(Int, Int)));
+    assert!(context.subtype(&identity, &forall("b", Func(Type::List(Box::new(var("b"))), Type::List(Box::new(var("b")))))));
+    assert!(!context.subtype(&Func(Int, Int), &identity));
+    assert!(!context.subtype(&identity, &Func(Int, Bool)));
+    // and contravariantly so in parameters
+    assert!(context.subtype(&Func(Func(Int, Int), Int), &Func(identity.clone(), Int)));
+    assert!(!context.subtype(&Func(identity.clone(), Int), &Func(Func(Int, Int), Int)));
+    // instances are related by the rest of the subtyping relation
+    let point = |x: Type| Type::Struct(std::collections::BTreeMap::from([(String::from("x"), x), (String::from("y"), Int)]));
+    let only = Type::Struct(std::collections::BTreeMap::from([(String::from("x"), Int)]));
+    assert!(context.subtype(&forall("a", Func(var("a"), point(var("a")))), &Func(Nat, only.clone())));
+    assert!(!context.subtype(&forall("a", Func(var("a"), point(var("a")))), &Func(Bool, only)));
+    assert!(context.subtype(&forall("a", Func(var("a"), var("a"))),
+        &Func(Type::Union(vec![Int, Bool]), Type::Union(vec![Int, Bool, Type::String]))));
+}
+
+#[test]
+fn test_bounded_quantification() {
+    let mut context = Context::new();
+    context.insert_func(Signature { name: String::from("show"), from: Int, to: Type::String },
+        Implementation::Defined(parse_lambda("(λx. \"int\"): (int -> str)").unwrap()));
+    let shows = Type::Interface(vec![Signature { name: String::from("show"), from: Type::Oneself, to: Type::String }], None);
+    let negates = Type::Interface(vec![Signature { name: String::from("neg"), from: Type::Oneself, to: Type::Oneself }], None);
+    let infer = |source: &str| context.infer(parse_lambda(source).unwrap());
+    let execute = |source: &str| {
+        let expr = parse_lambda(source).unwrap();
+        assert!(context.infer(expr.clone()).is_ok());
+        context.execute(expr).unwrap()
+    };
+
+    // the signatures of an interface bound may be called on the variable, and the caller's type is kept
+    let show = "(Λa <: interface[func show(Self): str]. λx. (x, (show) x)): (forall a <: interface[func show(Self): str]. a -> tuple[a, str])";
+    assert_eq!(infer(show).unwrap(), bounded("a", shows.clone(),
+        Func(var("a"), Type::Tuple(vec![var("a"), Type::String], vec![None, None]))));
+    assert_eq!(infer(&format!("({})[int]", show)).unwrap(), Func(Int, Type::Tuple(vec![Int, Type::String], vec![None, None])));
+    assert_eq!(infer(&format!("({}) -3", show)).unwrap(), Type::Tuple(vec![Int, Type::String], vec![None, None]));
+    assert_eq!(execute(&format!("({}) -3", show)), Term::Tuple(vec![Term::Integer(-3), Str("int")], vec![None, None]));
+    // types must be within the bound
+    assert_eq!(infer(&format!("({})[bool]", show)).unwrap_err().error, TypeError::Mismatch { expected: shows.clone(), found: Bool });
+    assert_eq!(infer(&format!("({}) 3", show)).unwrap_err().error, TypeError::Mismatch { expected: shows.clone(), found: Nat });
+
+    let negate = "(Λa <: interface[func neg(Self): Self]. λx. (neg) (neg) x): (forall a <: interface[func neg(Self): Self]. a -> a)";
+    assert_eq!(infer(&format!("({}) -3", negate)).unwrap(), Int);
+    assert_eq!(infer(&format!("({})[float]", negate)).unwrap(), Func(Type::Float, Type::Float));
+    assert_eq!(execute(&format!("({}) -3", negate)), Term::Integer(-3));
+    // and nothing more than the bound may be assumed
+    assert_eq!(infer("(Λa <: interface[func neg(Self): Self]. λx. (not) x): (forall a <: interface[func neg(Self): Self]. a -> bool)")
+        .unwrap_err().error, TypeError::NoOverload(String::from("not"), vec![var("a")]));
+    assert!(infer("(Λa. λx. (neg) x): (forall a. a -> a)").is_err());
+    assert_eq!(infer("(Λa <: int. λx. x): (forall a <: interface[func neg(Self): Self]. a -> a)").unwrap_err().error,
+        TypeError::Mismatch { expected: negates.clone(), found: Int });
+    // variables are subtypes of their bounds
+    assert_eq!(infer("(Λa <: nat. λx. ((add) x) 1): (forall a <: nat. a -> nat)").unwrap(), bounded("a", Nat, Func(var("a"), Nat)));
+    assert_eq!(infer("(Λa <: nat. λx. x): (forall a <: nat. a -> int)").unwrap(), bounded("a", Nat, Func(var("a"), Int)));
+    assert!(infer("(Λa <: int. λx. x): (forall a <: int. a -> nat)").is_err());
+
+    // kernel F<: subtyping: bounds agree and bodies are subtypes
+    let identity = |bound: Type| bounded("a", bound, Func(var("a"), var("a")));
+    assert!(context.subtype(&identity(Int), &bounded("b", Int, Func(var("b"), var("b")))));
+    assert!(context.subtype(&identity(Int), &bounded("b", Int, Func(var("b"), Int))));
+    assert!(!context.subtype(&identity(Int), &forall("b", Func(var("b"), var("b")))));
+    assert!(context.subtype(&identity(Int), &Func(Nat, Int)));
+    assert!(!context.subtype(&identity(Int), &Func(Bool, Bool)));
+    assert!(context.subtype(&identity(shows.clone()), &Func(Int, Int)));
+    assert!(!context.subtype(&identity(negates), &Func(Nat, Nat)));
+}
+
+#[test]
+fn test_existentials() {
+    let mut context = Context::new();
+    context.insert_func(Signature { name: String::from("show"), from: Nat, to: Type::String },
+        Implementation::Defined(parse_lambda("(λx. \"nat\"): (nat -> str)").unwrap()));
+    context.insert_func(Signature { name: String::from("show"), from: Int, to: Type::String },
+        Implementation::Defined(parse_lambda("(λx. \"int\"): (int -> str)").unwrap()));
+    context.insert_func(Signature { name: String::from("area"), from: Type::Struct(BTreeMap::from([(String::from("r"), Type::Float)])), to: Type::Float },
+        Implementation::Defined(parse_lambda("(λc. ((mul) 3.0) ((mul) c.r) c.r): (struct[r: float] -> float)").unwrap()));
+    context.insert_func(Signature { name: String::from("area"), from: Type::Struct(BTreeMap::from([(String::from("h"), Type::Float), (String::from("w"), Type::Float)])), to: Type::Float },
+        Implementation::Defined(parse_lambda("(λr. ((mul) r.w) r.h): (struct[h: float, w: float] -> float)").unwrap()));
+    let shows = Type::Interface(vec![Signature { name: String::from("show"), from: Type::Oneself, to: Type::String }], None);
+    let shapes = Type::Interface(vec![Signature { name: String::from("area"), from: Type::Oneself, to: Type::Float }], None);
+    let infer = |source: &str| context.infer(parse_lambda(source).unwrap());
+    let execute = |source: &str| {
+        let expr = parse_lambda(source).unwrap();
+        assert!(context.infer(expr.clone()).is_ok());
+        context.execute(expr).unwrap()
+    };
+
+    // packages hide their witness behind an interface it implements
+    const SHOWN: &str = "{*int, 3} as interface[func show(Self): str]";
+    assert_eq!(infer(SHOWN).unwrap(), shows.clone());
+    assert_eq!(execute(SHOWN), Term::Package(Int, Box::new(Term::Natural(3))));
+    assert_eq!(infer("{*bool, true} as interface[func show(Self): str]").unwrap_err().error,
+        TypeError::Mismatch { expected: shows.clone(), found: Bool });
+    assert!(infer("{*nat, -3} as interface[func show(Self): str]").is_err());
+
+    // and methods on them dispatch on the witness, not on the runtime type of the value
+    assert_eq!(infer(&format!("(show) {}", SHOWN)).unwrap(), Type::String);
+    assert_eq!(execute(&format!("(show) {}", SHOWN)), Str("int"));
+    assert_eq!(execute("(show) 3"), Str("nat"));
+
+    // unpacking opens a package for the extent of its body, which its type may not escape
+    assert_eq!(infer(&format!("let {{X, x}} = {} in (show) x", SHOWN)).unwrap(), Type::String);
+    assert_eq!(execute(&format!("let {{X, x}} = {} in (show) x", SHOWN)), Str("int"));
+    assert_eq!(infer(&format!("let {{X, x}} = {} in {{*X, x}} as interface[func show(Self): str]", SHOWN)).unwrap(), shows.clone());
+    assert_eq!(infer(&format!("let {{X, x}} = {} in x", SHOWN)).unwrap_err().error,
+        TypeError::Escape(String::from("X"), var("X")));
+    assert!(infer(&format!("let {{X, x}} = {} in ((add) x) 1", SHOWN)).is_err());
+
+    // heterogeneous collections of interface objects
+    const SHAPES: &str = "let shapes: list[interface[func area(Self): float]] = [{*struct[r: float], {r = 1.0}} as interface[func area(Self): float], {w = 2.0, h = 3.0}] in";
+    assert_eq!(infer(&format!("{} shapes", SHAPES)).unwrap(), Type::List(Box::new(shapes.clone())));
+    assert_eq!(infer(&format!("{} (area) shapes[1]", SHAPES)).unwrap(), Type::Float);
+    assert_eq!(execute(&format!("{} (area) shapes[0]", SHAPES)), Term::Float(3.0));
+    assert_eq!(execute(&format!("{} (area) shapes[1]", SHAPES)), Term::Float(6.0));
+    assert_eq!(infer(&format!("{} (show) shapes[0]", SHAPES)).unwrap_err().error,
+        TypeError::NoOverload(String::from("show"), vec![shapes]));
+}
+
+#[test]
+fn test_instances() {
+    let doubles = Type::Interface(vec![Signature { name: String::from("double"), from: Type::Oneself, to: Type::Oneself }], None);
+    let implement = |source: &str| -> Result<Context, TypeError> {
+        let mut context = Context::new();
+        for instance in parse_file(source).unwrap().instances() {
+            context.implement(instance)?;
+        }
+        Ok(context)
+    };
+    let run = |source: &str| {
+        let context = implement(source).unwrap();
+        parse_file(source).unwrap().lower().into_iter().map(|expr| {
+            assert!(context.infer(expr.clone()).is_ok());
+            context.execute(expr).unwrap()
+        }).collect::<Vec<Term>>()
+    };
+
+    // implementations make types satisfy interfaces, and may call each other and themselves
+    let program = "impl interface[func double(Self): Self] for nat:\n  func double(x) =\n    add(x, x)\n\n\
+        impl interface[func area(Self): nat, func scale(Self): nat -> Self] for struct[w: nat, h: nat]:\n  \
+        func area(r) =\n    mul(r.w, r.h)\n  func scale(r, n) =\n    {w = mul(r.w, n), h = r.h}\n\n\
+        impl interface[func fact(Self): Self] for nat:\n  func fact(n) =\n    if eq(n, 0):\n      1\n    else:\n      \
+        mul(n, fact(sub(n, 1)))\n\n\
+        func quad[a <: interface[func double(Self): Self]](x): a -> a =\n  double(double(x))\n\n\
+        quad(3)\narea(scale({w = 2, h = 3}, double(2)))\nfact(5)\n";
+    assert_eq!(run(program), vec![Term::Natural(12), Term::Natural(24), Term::Natural(120)]);
+    let context = implement(program).unwrap();
+    assert!(context.subtype(&Nat, &doubles));
+    assert!(!context.subtype(&Int, &doubles));
+    assert_eq!(context.infer(parse_file(&format!("{}quad(-3)\n", program)).unwrap().lower().remove(3)).unwrap_err().error,
+        TypeError::Mismatch { expected: doubles.clone(), found: Int });
+
+    // methods are checked against their signatures, with Self replaced by the implementing type
+    assert_eq!(implement("impl interface[func double(Self): Self] for nat:\n  func double(x) =\n    true\n").unwrap_err().error,
+        TypeError::Mismatch { expected: Nat, found: Bool });
+    // every signature must be implemented, exactly once, and nothing else may be
+    assert_eq!(implement("impl interface[func double(Self): Self, func half(Self): Self] for nat:\n  func double(x) =\n    x\n")
+        .unwrap_err().error, TypeError::Unimplemented(String::from("half"), Func(Nat, Nat)));
+    assert_eq!(implement("impl interface[func double(Self): Self] for nat:\n  func double(x) =\n    x\n  func double(y) =\n    y\n")
+        .unwrap_err().error, TypeError::Duplicate(String::from("double"), Func(Nat, Nat)));
+    assert_eq!(implement("impl interface[func neg(Self): Self] for int:\n  func neg(x) =\n    x\n").unwrap_err().error,
+        TypeError::Duplicate(String::from("neg"), Func(Int, Int)));
+    assert_eq!(implement("impl interface[func double(Self): Self] for nat:\n  func double(x) =\n    x\n\n\
+        impl interface[func double(Self): Self] for nat:\n  func double(x) =\n    x\n").unwrap_err().error,
+        TypeError::Duplicate(String::from("double"), Func(Nat, Nat)));
+    assert_eq!(implement("impl interface[func double(Self): Self] for nat:\n  func double(x) =\n    x\n  func triple(x) =\n    x\n")
+        .unwrap_err().error, TypeError::NoMethod(doubles, String::from("triple")));
+    assert_eq!(implement("impl nat for int:\n  func double(x) =\n    x\n").unwrap_err().error, TypeError::NotAnInterface(Nat));
+    // and nothing is registered if any method fails to check
+    let mut context = Context::new();
+    let instance = parse_file("impl interface[func double(Self): Self, func half(Self): Self] for nat:\n  \
+        func double(x) =\n    x\n  func half(x) =\n    true\n").unwrap().instances().remove(0);
+    assert!(context.implement(instance).is_err());
+    assert!(!context.contains_sig(&Signature { name: String::from("double"), from: Nat, to: Nat }));
+}
+
+#[test]
+fn test_polymorphic_programs() {
+    let context = Context::new();
+    let execute = |source: &str| {
+        let expr = parse_lambda(source).unwrap();
+        assert!(context.infer(expr.clone()).is_ok());
+        context.execute(expr).unwrap()
+    };
+    assert_eq!(execute(&format!("(({})[int]) -3", IDENTITY)), Term::Integer(-3));
+    assert_eq!(execute(&format!("(((({})[int][int][bool]) (λn. ((lt) n) 0)) (λn. (neg) n)) 3", COMPOSE)),
+        Term::Boolean(true));
+    assert_eq!(execute(&format!("let map: forall a. forall b. (a -> b) -> list[a] -[exn]-> list[b] = {} in ((map[nat][bool]) (λn. ((eq) n) 0)) [1, 0]", MAP)),
+        Term::List(vec![Term::Boolean(false), Term::Boolean(true)]));
+
+    let program = "func id[a](x): a -> a =\n  x\n\nfunc twice[a](f, x): (a -> a) -> a -> a =\n  f(f(x))\n\nid[int](-3)\ntwice[nat](id[nat], 2)\ntwice[list[bool]](id[list[bool]], @[true])\n";
+    let results: Vec<Term> = parse_file(program).unwrap().lower().into_iter().map(|expr| {
+        assert!(context.infer(expr.clone()).is_ok());
+        context.execute(expr).unwrap()
+    }).collect();
+    assert_eq!(results, vec![Term::Integer(-3), Term::Natural(2), Term::List(vec![Term::Boolean(true)])]);
+
+    // type arguments may be left implicit
+    let program = "func id[a](x): a -> a =\n  x\n\nfunc twice[a](f, x): (a -> a) -> a -> a =\n  f(f(x))\n\ntwice(id, 2)\ntwice(id, @[true])\n";
+    let results: Vec<Term> = parse_file(program).unwrap().lower().into_iter().map(|expr| {
+        assert!(context.infer(expr.clone()).is_ok());
+        context.execute(expr).unwrap()
+    }).collect();
+    assert_eq!(results, vec![Term::Natural(2), Term::List(vec![Term::Boolean(true)])]);
+    let program = "func twice[a <: interface[func neg(Self): Self]](x): a -> a =\n  neg(neg(x))\n\ntwice(-3)\ntwice[int](4)\n";
+    let results: Vec<Term> = parse_file(program).unwrap().lower().into_iter().map(|expr| {
+        assert!(context.infer(expr.clone()).is_ok());
+        context.execute(expr).unwrap()
+    }).collect();
+    assert_eq!(results, vec![Term::Integer(-3), Term::Integer(4)]);
+    assert_eq!(execute("((λf. ((f) 1, (f) true)): ((forall a. a -> a) -> tuple[int, bool])) (λx. x)"),
+        Term::Tuple(vec![Term::Natural(1), Term::Boolean(true)], vec![None, None]));
+}
@@ -0,0 +1,116 @@
+use chrysanthemum::ast::*;
+use chrysanthemum::effects::*;
+use chrysanthemum::error::*;
+use chrysanthemum::parser::*;
+use chrysanthemum::unification::*;
+use chrysanthemum::util::*;
+
+fn var(id: &str) -> Type {
+    Type::Variable(String::from(id))
+}
+
+#[test]
+fn test_principal_types() {
+    let context = Context::new();
+    let principal = |source: &str| context.principal(parse_lambda(source).unwrap());
+    assert_eq!(principal("413").unwrap(), Nat);
+    assert_eq!(principal("λx. x").unwrap(), Func(var("a"), var("a")));
+    assert_eq!(principal("λx. λy. x").unwrap(), Func(var("a"), Func(var("b"), var("a"))));
+    assert_eq!(principal("(λx. (λy. (λz. if x then y else z)))").unwrap(),
+        Func(Bool, Func(var("a"), Func(var("a"), var("a")))));
+    assert_eq!(principal("λf. λx. (f) (f) x").unwrap(),
+        Func(Func(var("a"), var("a")), Func(var("a"), var("a"))));
+    assert_eq!(principal("λf. λg. λx. (f) (g) x").unwrap(), Func(Func(var("a"), var("b")),
+        Func(Func(var("c"), var("a")), Func(var("c"), var("b")))));
+    // annotations and subtyping on ground types
+    assert_eq!(principal("(λx. x): (int -> int)").unwrap(), Func(Int, Int));
+    assert_eq!(principal("((λx. x): (int -> int)) 413").unwrap(), Int);
+    assert_eq!(principal("(λx. x) 413").unwrap(), Nat);
+    assert_eq!(principal("λx. ((add) x) 1").unwrap_err().error, TypeError::Ambiguous(String::from("add")));
+    assert_eq!(principal("λx. (not) x").unwrap(), Func(Bool, Bool));
+    // branches of differing ground types agree on the greater, in either order
+    assert_eq!(principal("λc. if c then 1 else -1").unwrap(), Func(Bool, Int));
+    assert_eq!(principal("λc. if c then -1 else 1").unwrap(), Func(Bool, Int));
+    // cases over unknown types are over the union of their patterns
+    assert_eq!(principal("λx. case x of i: int => 0 | b: bool => 1").unwrap(), Func(Type::Union(vec![Int, Bool]), Nat));
+    assert_eq!(principal("λo. case o of <some = x> => x | <none = y> => 0").unwrap(), Func(Type::Variant(
+        [(String::from("some"), Nat), (String::from("none"), var("a"))].into_iter().collect()), Nat));
+    assert_eq!(principal("<some = 3>").unwrap(), Type::Variant([(String::from("some"), Nat)].into_iter().collect()));
+    // elements must agree, and empty lists are of any element type
+    assert_eq!(principal("λx. [x, x]").unwrap(), Func(var("a"), Type::List(Box::new(var("a")))));
+    assert_eq!(principal("λx. (x, [])").unwrap(), Func(var("a"),
+        Type::Tuple(vec![var("a"), Type::List(Box::new(var("b")))], vec![None, None])));
+    assert_eq!(principal("λx. {a = (not) x}").unwrap(), Func(Bool,
+        Type::Struct([(String::from("a"), Bool)].into_iter().collect())));
+    // there are no row variables
+    assert!(matches!(principal("(λs. s.x) {x = 1}").unwrap_err().error, TypeError::NoField(Type::Variable(_), _)));
+}
+
+#[test]
+fn test_generalization() {
+    let context = Context::new();
+    let principal = |source: &str| context.principal(parse_lambda(source).unwrap());
+    // let-bound values are polymorphic
+    assert_eq!(principal("let id = λx. x in (id) true").unwrap(), Bool);
+    assert_eq!(principal("let id = λx. x in ((id) λy. y) (id) 1").unwrap(), Nat);
+    assert_eq!(principal("let k = λx. λy. x in (k) true").unwrap(), Func(var("a"), Bool));
+    // ...but abstraction-bound values are not
+    assert_eq!(principal("λf. if (f) true then (f) 1 else false").unwrap_err().error,
+        TypeError::Mismatch { expected: Bool, found: Nat });
+    // variables bound in the environment stay monomorphic
+    assert_eq!(principal("λx. let y = x in y").unwrap(), Func(var("a"), var("a")));
+    assert_eq!(principal("letrec f: (bool -[div]-> bool) = λx. if x then (f) false else true in f").unwrap(),
+        Effectful(Bool, Bool, &[Effect::Div]));
+    assert_eq!(principal("fix λf. λx. (f) x").unwrap(), Effectful(var("a"), var("b"), &[Effect::Div]));
+}
+
+#[test]
+fn test_unification_errors() {
+    let context = Context::new();
+    let principal = |source: &str| context.principal(parse_lambda(source).unwrap());
+    assert!(matches!(principal("λx. (x) x").unwrap_err().error, TypeError::Infinite(..)));
+    assert_eq!(principal("(1) 2").unwrap_err().error, TypeError::NotAFunction(Nat));
+    assert_eq!(principal("if 1 then 2 else 3").unwrap_err().error, TypeError::Mismatch { expected: Bool, found: Nat });
+    assert_eq!(principal("if true then true else 3").unwrap_err().error, TypeError::BranchMismatch(Bool, Nat));
+    assert_eq!(principal("if true then 3 else true").unwrap_err().error, TypeError::BranchMismatch(Nat, Bool));
+    assert_eq!(principal("x").unwrap_err().error, TypeError::Unbound(String::from("x")));
+}
+
+#[test]
+fn test_annotated_polymorphism() {
+    let context = Context::new();
+    let principal = |source: &str| context.principal(parse_lambda(source).unwrap());
+    let id = Type::Forall(String::from("a"), Box::new(Type::Empty), Box::new(Func(var("a"), var("a"))));
+    // variables written in annotations are rigid: they are not solved for
+    let constant = Ann(Abs("x", Const(Term::Natural(1))), Func(var("a"), var("a")));
+    assert_eq!(context.check_with(Engine::Unification, constant.clone(), &Func(var("a"), var("a"))).unwrap_err().error,
+        TypeError::Mismatch { expected: var("a"), found: Nat });
+    assert!(context.check_with(Engine::Unification, constant, &Func(Nat, Nat)).is_err());
+    // and quantified ones are checked for every type at once, and instantiated anew wherever used
+    for engine in [Engine::Bidirectional, Engine::Unification] {
+        assert!(context.check_with(engine, parse_lambda("(λx. x): (forall a. a -> a)").unwrap(), &id).is_ok());
+        assert!(context.check_with(engine, parse_lambda("(λx. 1): (forall a. a -> a)").unwrap(), &id).is_err());
+    }
+    assert_eq!(principal("(λx. x): (forall a. a -> a)").unwrap(), id);
+    assert_eq!(principal("(λx. 1): (forall a. a -> a)").unwrap_err().error,
+        TypeError::Mismatch { expected: var("a"), found: Nat });
+    assert_eq!(principal("((λx. x): (forall a. a -> a)) 1").unwrap(), Nat);
+    assert_eq!(principal("let id = (λx. x): (forall a. a -> a) in ((id) 1, (id) true)").unwrap(),
+        Type::Tuple(vec![Nat, Bool], vec![None, None]));
+    assert_eq!(principal("λy. (λx. y): (forall a. a -> a)").unwrap_err().error,
+        TypeError::Mismatch { expected: id, found: Func(var("a"), var("a")) });
+}
+
+#[test]
+fn test_engines() {
+    let context = Context::new();
+    let not_inferrable = parse_lambda("(λx. (λy. (λz. if x then y else z)))").unwrap();
+    assert!(context.infer_with(Engine::Bidirectional, not_inferrable.clone()).is_err());
+    assert!(context.infer_with(Engine::Unification, not_inferrable.clone()).is_ok());
+    for engine in [Engine::Bidirectional, Engine::Unification] {
+        assert!(context.check_with(engine, not_inferrable.clone(), &Func(Bool, Func(Int, Func(Int, Int)))).is_ok());
+        assert!(context.check_with(engine, not_inferrable.clone(), &Func(Int, Func(Int, Func(Int, Int)))).is_err());
+        assert_eq!(context.infer_with(engine, parse_lambda("((λx. x): (int -> int)) 413").unwrap()).unwrap(), Int);
+    }
+    assert_eq!(Engine::default(), Engine::Bidirectional);
+}